@@ -0,0 +1,267 @@
+//! A local OpenID Connect provider for trying single sign-on without a real one.
+//!
+//! ```bash
+//! cargo run --example mock_idp
+//! OIDC_ISSUER=http://localhost:9000 OIDC_CLIENT_ID=stock-analyzer cargo run
+//! ```
+//!
+//! The authorization endpoint signs in without asking. The user is taken from extra query
+//! parameters appended to the authorization URL: `sub`, `email`, `email_verified`, `username`
+//! and comma-separated `groups`. `MOCK_IDP_PORT` (default 9000) sets the port and
+//! `MOCK_IDP_CLIENT_SECRET` makes the token endpoint require a client secret.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Redirect, Response},
+    routing::{get, post},
+    Form, Router,
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use chrono::Utc;
+use jsonwebtoken::{
+    encode,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse,
+    },
+    Algorithm, EncodingKey, Header,
+};
+use reqwest::Url;
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+const KEY_ID: &str = "mock-idp";
+const ID_TOKEN_TTL_SECS: i64 = 300;
+
+struct MockIdp {
+    issuer: String,
+    client_secret: Option<String>,
+    signing_key: EncodingKey,
+    jwk: Jwk,
+    /// Authorization codes waiting to be redeemed.
+    codes: Mutex<HashMap<String, PendingCode>>,
+}
+
+struct PendingCode {
+    client_id: String,
+    redirect_uri: String,
+    code_challenge: String,
+    nonce: Option<String>,
+    claims: serde_json::Value,
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt().with_target(false).init();
+
+    let port: u16 = std::env::var("MOCK_IDP_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(9000);
+
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .expect("Failed to generate the signing key");
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).expect("Invalid signing key");
+    let jwk = Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(KeyAlgorithm::EdDSA),
+            key_id: Some(KEY_ID.to_string()),
+            ..Default::default()
+        },
+        algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: URL_SAFE_NO_PAD.encode(key_pair.public_key()),
+        }),
+    };
+
+    let idp = Arc::new(MockIdp {
+        issuer: format!("http://localhost:{}", port),
+        client_secret: std::env::var("MOCK_IDP_CLIENT_SECRET").ok(),
+        signing_key: EncodingKey::from_ed_der(pkcs8.as_ref()),
+        jwk,
+        codes: Mutex::new(HashMap::new()),
+    });
+
+    let app = Router::new()
+        .route("/.well-known/openid-configuration", get(discovery))
+        .route("/jwks", get(jwks))
+        .route("/authorize", get(authorize))
+        .route("/token", post(token))
+        .with_state(idp.clone());
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    tracing::info!("Mock OpenID Connect provider at {}", idp.issuer);
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .expect("Failed to bind to address");
+    axum::serve(listener, app).await.expect("Server failed");
+}
+
+async fn discovery(State(idp): State<Arc<MockIdp>>) -> Json<serde_json::Value> {
+    Json(json!({
+        "issuer": idp.issuer,
+        "authorization_endpoint": format!("{}/authorize", idp.issuer),
+        "token_endpoint": format!("{}/token", idp.issuer),
+        "jwks_uri": format!("{}/jwks", idp.issuer),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["EdDSA"],
+        "code_challenge_methods_supported": ["S256"],
+    }))
+}
+
+async fn jwks(State(idp): State<Arc<MockIdp>>) -> Json<JwkSet> {
+    Json(JwkSet {
+        keys: vec![idp.jwk.clone()],
+    })
+}
+
+/// Sign in as the user described by the query and send the browser back with a code.
+async fn authorize(
+    State(idp): State<Arc<MockIdp>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let param = |name: &str| query.get(name).cloned();
+    let (Some(client_id), Some(redirect_uri), Some(code_challenge)) = (
+        param("client_id"),
+        param("redirect_uri"),
+        param("code_challenge"),
+    ) else {
+        return error(StatusCode::BAD_REQUEST, "invalid_request");
+    };
+    if param("response_type").as_deref() != Some("code")
+        || param("code_challenge_method").as_deref() != Some("S256")
+    {
+        return error(StatusCode::BAD_REQUEST, "invalid_request");
+    }
+    let Ok(mut redirect) = Url::parse(&redirect_uri) else {
+        return error(StatusCode::BAD_REQUEST, "invalid_request");
+    };
+
+    let username = param("username").unwrap_or_else(|| "mock.user".to_string());
+    let groups: Vec<String> = param("groups")
+        .map(|groups| {
+            groups
+                .split(',')
+                .filter(|group| !group.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    let claims = json!({
+        "sub": param("sub").unwrap_or_else(|| format!("mock-{}", username)),
+        "email": param("email").unwrap_or_else(|| format!("{}@example.com", username)),
+        "email_verified": param("email_verified").as_deref() != Some("false"),
+        "preferred_username": username,
+        "groups": groups,
+    });
+
+    let code = Uuid::new_v4().simple().to_string();
+    idp.codes.lock().unwrap().insert(
+        code.clone(),
+        PendingCode {
+            client_id,
+            redirect_uri,
+            code_challenge,
+            nonce: param("nonce"),
+            claims,
+        },
+    );
+
+    redirect.query_pairs_mut().append_pair("code", &code);
+    if let Some(state) = param("state") {
+        redirect.query_pairs_mut().append_pair("state", &state);
+    }
+    Redirect::to(redirect.as_str()).into_response()
+}
+
+/// Redeem an authorization code for an ID token after checking the PKCE verifier.
+async fn token(
+    State(idp): State<Arc<MockIdp>>,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    let field = |name: &str| form.get(name).map(String::as_str);
+    if field("grant_type") != Some("authorization_code") {
+        return error(StatusCode::BAD_REQUEST, "unsupported_grant_type");
+    }
+    let Some(pending) = field("code").and_then(|code| idp.codes.lock().unwrap().remove(code))
+    else {
+        return error(StatusCode::BAD_REQUEST, "invalid_grant");
+    };
+
+    let (client_id, client_secret) = client_credentials(&headers, &form);
+    if client_id.as_deref() != Some(pending.client_id.as_str()) {
+        return error(StatusCode::UNAUTHORIZED, "invalid_client");
+    }
+    if idp.client_secret.is_some() && client_secret != idp.client_secret {
+        return error(StatusCode::UNAUTHORIZED, "invalid_client");
+    }
+    let challenge = field("code_verifier")
+        .map(|verifier| URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())));
+    if field("redirect_uri") != Some(pending.redirect_uri.as_str())
+        || challenge.as_deref() != Some(pending.code_challenge.as_str())
+    {
+        return error(StatusCode::BAD_REQUEST, "invalid_grant");
+    }
+
+    let now = Utc::now().timestamp();
+    let mut claims = pending.claims;
+    claims["iss"] = json!(idp.issuer);
+    claims["aud"] = json!(pending.client_id);
+    claims["iat"] = json!(now);
+    claims["exp"] = json!(now + ID_TOKEN_TTL_SECS);
+    if let Some(nonce) = pending.nonce {
+        claims["nonce"] = json!(nonce);
+    }
+    let mut header = Header::new(Algorithm::EdDSA);
+    header.kid = Some(KEY_ID.to_string());
+    let id_token = encode(&header, &claims, &idp.signing_key).expect("Failed to sign ID token");
+
+    Json(json!({
+        "access_token": Uuid::new_v4().simple().to_string(),
+        "token_type": "Bearer",
+        "expires_in": ID_TOKEN_TTL_SECS,
+        "id_token": id_token,
+    }))
+    .into_response()
+}
+
+/// Client ID and secret from HTTP Basic authentication or the form.
+fn client_credentials(
+    headers: &HeaderMap,
+    form: &HashMap<String, String>,
+) -> (Option<String>, Option<String>) {
+    let basic = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|value| STANDARD.decode(value).ok())
+        .and_then(|value| String::from_utf8(value).ok());
+    if let Some((id, secret)) = basic.as_deref().and_then(|value| value.split_once(':')) {
+        return (Some(id.to_string()), Some(secret.to_string()));
+    }
+    (
+        form.get("client_id").cloned(),
+        form.get("client_secret").cloned(),
+    )
+}
+
+fn error(status: StatusCode, code: &str) -> Response {
+    (status, Json(json!({ "error": code }))).into_response()
+}
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS analyses;
+DROP TABLE IF EXISTS fundamentals;
+DROP TABLE IF EXISTS price_bars;
+DROP TABLE IF EXISTS agents;
@@ -0,0 +1,88 @@
+-- Your SQL goes here
+CREATE TABLE agents (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    slug VARCHAR NOT NULL UNIQUE, -- 'buffett', 'schloss', etc.
+    name VARCHAR NOT NULL,
+    description TEXT,
+    system_prompt TEXT NOT NULL,
+    prompt_template TEXT NOT NULL, -- supports {{ticker}} and {{market_data}} placeholders
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE price_bars (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    ticker VARCHAR NOT NULL,
+    trade_date DATE NOT NULL,
+    open DOUBLE PRECISION NOT NULL,
+    high DOUBLE PRECISION NOT NULL,
+    low DOUBLE PRECISION NOT NULL,
+    close DOUBLE PRECISION NOT NULL,
+    volume BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (ticker, trade_date)
+);
+
+CREATE TABLE fundamentals (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    ticker VARCHAR NOT NULL,
+    period_end DATE NOT NULL,
+    period_type VARCHAR NOT NULL, -- 'annual' or 'quarterly'
+    revenue DOUBLE PRECISION,
+    net_income DOUBLE PRECISION,
+    eps DOUBLE PRECISION,
+    free_cash_flow DOUBLE PRECISION,
+    book_value_per_share DOUBLE PRECISION,
+    total_assets DOUBLE PRECISION,
+    total_liabilities DOUBLE PRECISION,
+    current_assets DOUBLE PRECISION,
+    total_debt DOUBLE PRECISION,
+    shares_outstanding DOUBLE PRECISION,
+    dividends_per_share DOUBLE PRECISION,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (ticker, period_end, period_type)
+);
+
+CREATE TABLE analyses (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+    provider_id UUID NOT NULL REFERENCES llm_providers(id) ON DELETE CASCADE,
+    llm_usage_id UUID REFERENCES llm_usage(id) ON DELETE SET NULL,
+    ticker VARCHAR NOT NULL,
+    input_data JSONB NOT NULL, -- prices, fundamentals and valuations sent to the model
+    prompt TEXT NOT NULL,
+    result_text TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_price_bars_ticker_date ON price_bars(ticker, trade_date DESC);
+CREATE INDEX idx_fundamentals_ticker_period ON fundamentals(ticker, period_end DESC);
+CREATE INDEX idx_analyses_user ON analyses(user_id);
+CREATE INDEX idx_analyses_ticker ON analyses(ticker);
+CREATE INDEX idx_analyses_created_at ON analyses(created_at);
+
+INSERT INTO agents (slug, name, description, system_prompt, prompt_template) VALUES
+(
+    'buffett',
+    'Agent Warren E. Buffett',
+    'Quality businesses with durable moats, bought at a sensible price and held for the long term.',
+    'You are an investment analyst who thinks like Warren E. Buffett. You favour simple, understandable businesses with durable competitive advantages, consistent high returns on equity, low debt, honest and capable management, and predictable owner earnings. You only recommend buying when the price offers a margin of safety against a conservative estimate of intrinsic value. Base every claim on the data provided and say clearly when data is missing.',
+    'Analyse {{ticker}} using the market data below.
+
+{{market_data}}
+
+Cover the business quality and moat, earnings consistency, balance sheet strength, an estimate of intrinsic value and the margin of safety at the current price. Finish with a clear verdict: buy, hold or sell.'
+),
+(
+    'schloss',
+    'Agent Walter Schloss',
+    'Statistically cheap stocks trading near or below book value, bought with little regard for the story.',
+    'You are an investment analyst who thinks like Walter Schloss. You look for statistically cheap companies trading near or below book value or net current asset value, with little or no debt, and you care more about the numbers than about the business narrative or management access. You diversify widely and demand a large discount to asset value. Base every claim on the data provided and say clearly when data is missing.',
+    'Analyse {{ticker}} using the market data below.
+
+{{market_data}}
+
+Cover price relative to book value and net current asset value, debt levels, the earnings and dividend record, and how far the stock sits from its multi-year lows. Finish with a clear verdict: buy, hold or sell.'
+);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS idx_analyses_verdict;
+ALTER TABLE analyses DROP COLUMN IF EXISTS verdict;
+ALTER TABLE analyses DROP COLUMN IF EXISTS structured_output;
+ALTER TABLE agents DROP COLUMN IF EXISTS output_schema;
@@ -0,0 +1,180 @@
+-- Your SQL goes here
+ALTER TABLE agents ADD COLUMN output_schema JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE agents ALTER COLUMN output_schema DROP DEFAULT;
+
+ALTER TABLE analyses ADD COLUMN structured_output JSONB;
+ALTER TABLE analyses ADD COLUMN verdict VARCHAR; -- 'buy', 'hold' or 'sell', copied from structured_output
+
+CREATE INDEX idx_analyses_verdict ON analyses(verdict);
+
+UPDATE agents SET output_schema = '{
+  "type": "object",
+  "properties": {
+    "verdict": {
+      "type": "string",
+      "enum": [
+        "buy",
+        "hold",
+        "sell"
+      ]
+    },
+    "conviction": {
+      "type": "integer",
+      "minimum": 1,
+      "maximum": 10,
+      "description": "How strongly the verdict is held, 1 (weak) to 10 (very strong)."
+    },
+    "intrinsic_value_low": {
+      "type": "number",
+      "description": "Conservative intrinsic value per share."
+    },
+    "intrinsic_value_high": {
+      "type": "number",
+      "description": "Optimistic intrinsic value per share."
+    },
+    "margin_of_safety": {
+      "type": "number",
+      "description": "(midpoint intrinsic value - price) / midpoint intrinsic value, as a fraction."
+    },
+    "summary": {
+      "type": "string",
+      "description": "Short explanation of the verdict."
+    },
+    "key_risks": {
+      "type": "array",
+      "items": {
+        "type": "string"
+      },
+      "minItems": 1
+    },
+    "moats": {
+      "type": "array",
+      "items": {
+        "type": "string"
+      }
+    },
+    "cited_data_points": {
+      "type": "array",
+      "minItems": 1,
+      "items": {
+        "type": "object",
+        "properties": {
+          "metric": {
+            "type": "string"
+          },
+          "value": {
+            "type": "number"
+          },
+          "period": {
+            "type": "string"
+          }
+        },
+        "required": [
+          "metric",
+          "value"
+        ]
+      }
+    },
+    "owner_earnings_per_share": {
+      "type": "number",
+      "description": "Estimated owner earnings per share used in the valuation."
+    }
+  },
+  "required": [
+    "verdict",
+    "conviction",
+    "intrinsic_value_low",
+    "intrinsic_value_high",
+    "margin_of_safety",
+    "summary",
+    "key_risks",
+    "moats",
+    "cited_data_points"
+  ]
+}' WHERE slug = 'buffett';
+
+UPDATE agents SET output_schema = '{
+  "type": "object",
+  "properties": {
+    "verdict": {
+      "type": "string",
+      "enum": [
+        "buy",
+        "hold",
+        "sell"
+      ]
+    },
+    "conviction": {
+      "type": "integer",
+      "minimum": 1,
+      "maximum": 10,
+      "description": "How strongly the verdict is held, 1 (weak) to 10 (very strong)."
+    },
+    "intrinsic_value_low": {
+      "type": "number",
+      "description": "Conservative intrinsic value per share."
+    },
+    "intrinsic_value_high": {
+      "type": "number",
+      "description": "Optimistic intrinsic value per share."
+    },
+    "margin_of_safety": {
+      "type": "number",
+      "description": "(midpoint intrinsic value - price) / midpoint intrinsic value, as a fraction."
+    },
+    "summary": {
+      "type": "string",
+      "description": "Short explanation of the verdict."
+    },
+    "key_risks": {
+      "type": "array",
+      "items": {
+        "type": "string"
+      },
+      "minItems": 1
+    },
+    "moats": {
+      "type": "array",
+      "items": {
+        "type": "string"
+      }
+    },
+    "cited_data_points": {
+      "type": "array",
+      "minItems": 1,
+      "items": {
+        "type": "object",
+        "properties": {
+          "metric": {
+            "type": "string"
+          },
+          "value": {
+            "type": "number"
+          },
+          "period": {
+            "type": "string"
+          }
+        },
+        "required": [
+          "metric",
+          "value"
+        ]
+      }
+    },
+    "discount_to_book": {
+      "type": "number",
+      "description": "(book value per share - price) / book value per share, as a fraction."
+    }
+  },
+  "required": [
+    "verdict",
+    "conviction",
+    "intrinsic_value_low",
+    "intrinsic_value_high",
+    "margin_of_safety",
+    "summary",
+    "key_risks",
+    "moats",
+    "cited_data_points"
+  ]
+}' WHERE slug = 'schloss';
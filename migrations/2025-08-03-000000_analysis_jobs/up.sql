@@ -0,0 +1,26 @@
+-- Your SQL goes here
+CREATE TABLE analysis_jobs (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(), -- also the id of the analysis it produces
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+    provider_id UUID NOT NULL REFERENCES llm_providers(id) ON DELETE CASCADE,
+    ticker VARCHAR NOT NULL,
+    status VARCHAR NOT NULL DEFAULT 'queued', -- 'queued', 'running', 'succeeded', 'failed', 'cancelled'
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 3,
+    run_after TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_error TEXT,
+    started_at TIMESTAMP,
+    finished_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_analysis_jobs_queue ON analysis_jobs(run_after) WHERE status = 'queued';
+CREATE INDEX idx_analysis_jobs_running ON analysis_jobs(started_at) WHERE status = 'running';
+CREATE INDEX idx_analysis_jobs_user ON analysis_jobs(user_id);
+
+-- Analyses created before the queue existed get a finished job so they can be polled too
+INSERT INTO analysis_jobs (id, user_id, agent_id, provider_id, ticker, status, attempts, started_at, finished_at, created_at, updated_at)
+SELECT id, user_id, agent_id, provider_id, ticker, 'succeeded', 1, created_at, created_at, created_at, created_at
+FROM analyses;
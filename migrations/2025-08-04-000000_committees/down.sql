@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS committee_members;
+DROP TABLE IF EXISTS committees;
+DELETE FROM agents WHERE kind = 'moderator';
+ALTER TABLE analysis_jobs DROP COLUMN IF EXISTS kind;
+ALTER TABLE agents DROP COLUMN IF EXISTS kind;
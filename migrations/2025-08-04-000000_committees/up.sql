@@ -0,0 +1,86 @@
+-- Your SQL goes here
+ALTER TABLE agents ADD COLUMN kind VARCHAR NOT NULL DEFAULT 'analyst'; -- 'analyst' or 'moderator'
+ALTER TABLE analysis_jobs ADD COLUMN kind VARCHAR NOT NULL DEFAULT 'analysis'; -- 'analysis' or 'committee'
+
+CREATE TABLE committees (
+    id UUID PRIMARY KEY REFERENCES analysis_jobs(id) ON DELETE CASCADE, -- shares its id with the job
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider_id UUID NOT NULL REFERENCES llm_providers(id) ON DELETE CASCADE,
+    ticker VARCHAR NOT NULL,
+    consensus_verdict VARCHAR, -- 'buy', 'hold' or 'sell', weighted from member verdicts
+    consensus_score DOUBLE PRECISION, -- -1 (unanimous sell) to 1 (unanimous buy)
+    moderator_output JSONB,
+    moderator_text TEXT,
+    llm_usage_id UUID REFERENCES llm_usage(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE committee_members (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    committee_id UUID NOT NULL REFERENCES committees(id) ON DELETE CASCADE,
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+    weight DOUBLE PRECISION NOT NULL DEFAULT 1,
+    analysis_id UUID REFERENCES analyses(id) ON DELETE SET NULL,
+    error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (committee_id, agent_id)
+);
+
+CREATE INDEX idx_committees_user ON committees(user_id);
+CREATE INDEX idx_committee_members_committee ON committee_members(committee_id);
+
+INSERT INTO agents (slug, name, description, system_prompt, prompt_template, kind, output_schema) VALUES
+(
+    'moderator',
+    'Investment Committee Moderator',
+    'Summarises a committee of agents and explains the weighted consensus.',
+    'You chair an investment committee. You receive the structured analyses of several analysts with different investment philosophies, together with a weighted consensus computed from their verdicts. Summarise where they agree and disagree, attribute each view to the analyst who holds it, and explain the consensus. Do not introduce new facts that none of the analysts cited.',
+    'The committee analysed {{ticker}}. Member analyses and the weighted consensus:
+
+{{committee}}
+
+Summarise the agreements and disagreements and give your recommended verdict.',
+    'moderator',
+    '{
+  "type": "object",
+  "properties": {
+    "summary": {
+      "type": "string",
+      "description": "Short overview of the committee discussion."
+    },
+    "agreements": {
+      "type": "array",
+      "items": {
+        "type": "string"
+      },
+      "description": "Points most members agree on."
+    },
+    "disagreements": {
+      "type": "array",
+      "items": {
+        "type": "string"
+      },
+      "description": "Points where members differ, naming who holds which view."
+    },
+    "recommended_verdict": {
+      "type": "string",
+      "enum": [
+        "buy",
+        "hold",
+        "sell"
+      ]
+    },
+    "rationale": {
+      "type": "string",
+      "description": "Why the recommended verdict follows from the members'' views and the weighted consensus."
+    }
+  },
+  "required": [
+    "summary",
+    "agreements",
+    "disagreements",
+    "recommended_verdict",
+    "rationale"
+  ]
+}'
+);
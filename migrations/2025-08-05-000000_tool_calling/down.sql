@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE analyses DROP COLUMN IF EXISTS tool_trace;
+ALTER TABLE analysis_jobs DROP COLUMN IF EXISTS use_tools;
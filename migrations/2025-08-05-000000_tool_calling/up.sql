@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE analysis_jobs ADD COLUMN use_tools BOOLEAN NOT NULL DEFAULT FALSE; -- let agents fetch data through MCP tools
+ALTER TABLE analyses ADD COLUMN tool_trace JSONB; -- every tool call the agent made, for auditing
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS mcp_usage;
+DROP TABLE IF EXISTS mcp_tool_access;
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+-- Roles allowed to call a tool over authenticated transports; tools without a row are open to every user
+CREATE TABLE mcp_tool_access (
+    tool_name VARCHAR(100) PRIMARY KEY,
+    allowed_roles TEXT[] NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- One row per tool call, resource read or prompt fetched over the HTTP transport
+CREATE TABLE mcp_usage (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    method VARCHAR(100) NOT NULL,
+    target VARCHAR(500), -- tool name, resource URI or prompt name
+    is_error BOOLEAN NOT NULL DEFAULT FALSE,
+    duration_ms INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_mcp_usage_user_id_created_at ON mcp_usage(user_id, created_at);
+
+-- Screening computes valuations for every stored ticker, so keep it to admins by default
+INSERT INTO mcp_tool_access (tool_name, allowed_roles) VALUES ('screen_stocks', ARRAY['admin']);
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+CREATE TABLE watchlists (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    description TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, name)
+);
+
+CREATE TABLE watchlist_items (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    watchlist_id UUID NOT NULL REFERENCES watchlists(id) ON DELETE CASCADE,
+    ticker VARCHAR(12) NOT NULL,
+    notes TEXT,
+    position INTEGER NOT NULL, -- display order within the watchlist, starting at 0
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (watchlist_id, ticker)
+);
+
+CREATE INDEX idx_watchlist_items_watchlist_id_position ON watchlist_items(watchlist_id, position);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS portfolio_transactions;
+DROP TABLE IF EXISTS portfolios;
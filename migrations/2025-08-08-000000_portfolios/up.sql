@@ -0,0 +1,27 @@
+-- Your SQL goes here
+CREATE TABLE portfolios (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    description TEXT,
+    cost_basis_method VARCHAR NOT NULL DEFAULT 'fifo', -- 'fifo' or 'average'
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, name)
+);
+
+CREATE TABLE portfolio_transactions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    portfolio_id UUID NOT NULL REFERENCES portfolios(id) ON DELETE CASCADE,
+    ticker VARCHAR(12) NOT NULL,
+    kind VARCHAR NOT NULL, -- 'buy', 'sell' or 'dividend'
+    trade_date DATE NOT NULL,
+    quantity DOUBLE PRECISION, -- shares traded; NULL for dividends
+    price DOUBLE PRECISION, -- price per share; NULL for dividends
+    amount DOUBLE PRECISION, -- cash received for dividends; NULL for trades
+    fees DOUBLE PRECISION NOT NULL DEFAULT 0,
+    notes TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_portfolio_transactions_portfolio_id_trade_date ON portfolio_transactions(portfolio_id, trade_date);
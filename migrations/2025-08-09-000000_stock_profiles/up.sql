@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE stock_profiles (
+    ticker VARCHAR(12) PRIMARY KEY,
+    name VARCHAR,
+    sector VARCHAR, -- used for portfolio sector concentration
+    industry VARCHAR,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS import_mappings;
+DROP INDEX IF EXISTS idx_portfolio_transactions_portfolio_id_external_id;
+ALTER TABLE portfolio_transactions DROP COLUMN IF EXISTS external_id;
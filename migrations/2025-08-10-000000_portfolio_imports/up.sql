@@ -0,0 +1,13 @@
+-- Your SQL goes here
+ALTER TABLE portfolio_transactions ADD COLUMN external_id VARCHAR; -- broker's transaction id, used to detect re-imports
+
+CREATE UNIQUE INDEX idx_portfolio_transactions_portfolio_id_external_id ON portfolio_transactions(portfolio_id, external_id);
+
+CREATE TABLE import_mappings (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    mapping JSONB NOT NULL, -- CSV columns, date format and action values of a broker export
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, name)
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS alert_triggers;
+DROP TABLE IF EXISTS alert_rules;
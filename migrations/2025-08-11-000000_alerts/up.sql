@@ -0,0 +1,32 @@
+-- Your SQL goes here
+CREATE TABLE alert_rules (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    ticker VARCHAR(12) NOT NULL,
+    condition VARCHAR NOT NULL, -- 'price_above', 'price_below', 'rsi_above', 'rsi_below' or 'below_intrinsic_value'
+    threshold DOUBLE PRECISION, -- price or RSI level; required margin of safety for 'below_intrinsic_value'
+    agent_id UUID REFERENCES agents(id) ON DELETE CASCADE, -- whose intrinsic value 'below_intrinsic_value' uses
+    cooldown_minutes INTEGER NOT NULL DEFAULT 1440, -- minimum time between two triggers of the rule
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    last_triggered_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE alert_triggers (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    rule_id UUID NOT NULL REFERENCES alert_rules(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    ticker VARCHAR(12) NOT NULL,
+    trade_date DATE NOT NULL, -- the price bar the rule was evaluated on
+    value DOUBLE PRECISION NOT NULL, -- observed close or RSI
+    threshold DOUBLE PRECISION NOT NULL, -- level it crossed, e.g. the intrinsic value less the margin
+    analysis_id UUID REFERENCES analyses(id) ON DELETE SET NULL,
+    message TEXT NOT NULL,
+    triggered_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (rule_id, trade_date)
+);
+
+CREATE INDEX idx_alert_rules_ticker ON alert_rules(ticker) WHERE is_active;
+CREATE INDEX idx_alert_rules_user_id ON alert_rules(user_id);
+CREATE INDEX idx_alert_triggers_user_id_triggered_at ON alert_triggers(user_id, triggered_at DESC);
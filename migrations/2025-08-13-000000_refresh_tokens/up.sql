@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE refresh_tokens (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    family_id UUID NOT NULL, -- shared by every token rotated from the same login
+    token_hash VARCHAR NOT NULL UNIQUE, -- hex SHA-256 of the opaque token; the token itself is never stored
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP, -- set when exchanged; presenting a used token again revokes its family
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_refresh_tokens_family_id ON refresh_tokens(family_id);
+CREATE INDEX idx_refresh_tokens_user_id ON refresh_tokens(user_id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE refresh_tokens DROP CONSTRAINT IF EXISTS refresh_tokens_family_id_fkey;
+DROP TABLE IF EXISTS sessions;
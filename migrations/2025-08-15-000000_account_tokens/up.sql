@@ -0,0 +1,18 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN email_verified_at TIMESTAMP;
+
+-- Accounts from before email verification keep working as verified
+UPDATE users SET email_verified_at = created_at;
+
+CREATE TABLE account_tokens (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    purpose VARCHAR NOT NULL, -- 'email_verification' or 'password_reset'
+    email VARCHAR NOT NULL, -- address the token was sent to
+    token_hash VARCHAR NOT NULL UNIQUE, -- hex SHA-256 of the token; the token itself is never stored
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP, -- tokens work once
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_account_tokens_user_id_purpose ON account_tokens(user_id, purpose);
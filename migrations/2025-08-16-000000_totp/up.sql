@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE totp_credentials (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    secret VARCHAR NOT NULL, -- base32 shared secret of the authenticator app
+    confirmed_at TIMESTAMP, -- NULL until the first code is verified; only confirmed credentials are asked for at login
+    last_used_step BIGINT, -- 30 second time step of the last accepted code, so codes cannot be replayed
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE mfa_recovery_codes (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash VARCHAR NOT NULL, -- hex SHA-256 of the code; the code itself is only shown once
+    used_at TIMESTAMP, -- codes work once
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_mfa_recovery_codes_user_id ON mfa_recovery_codes(user_id);
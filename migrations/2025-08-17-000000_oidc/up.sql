@@ -0,0 +1,24 @@
+-- Your SQL goes here
+CREATE TABLE user_identities (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    issuer VARCHAR NOT NULL, -- OpenID Connect issuer URL
+    subject VARCHAR NOT NULL, -- `sub` claim, stable per issuer
+    email VARCHAR, -- email claim at the last login, for display
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_login_at TIMESTAMP,
+    UNIQUE (issuer, subject)
+);
+
+CREATE INDEX idx_user_identities_user_id ON user_identities(user_id);
+
+-- Logins waiting for the identity provider to redirect back
+CREATE TABLE oidc_login_states (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    state_hash VARCHAR NOT NULL UNIQUE, -- hex SHA-256 of the `state` parameter
+    code_verifier VARCHAR NOT NULL, -- PKCE verifier sent with the authorization code
+    nonce VARCHAR NOT NULL, -- must come back in the ID token
+    link_user_id UUID REFERENCES users(id) ON DELETE CASCADE, -- set when a signed in user links their account
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+-- Personal access tokens for scripts and integrations, used in place of a login
+CREATE TABLE api_tokens (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    token_prefix VARCHAR NOT NULL, -- first characters of the token, to tell tokens apart
+    token_hash VARCHAR NOT NULL UNIQUE, -- hex SHA-256 of the token
+    scopes TEXT[] NOT NULL, -- read, write, admin
+    expires_at TIMESTAMP NOT NULL,
+    last_used_at TIMESTAMP,
+    last_used_ip VARCHAR(45),
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_api_tokens_user_id ON api_tokens(user_id);
@@ -0,0 +1,61 @@
+-- Your SQL goes here
+CREATE TABLE roles (
+    name VARCHAR(50) PRIMARY KEY,
+    description TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Checked by the API per route; new ones are added by migrations along with the code using them
+CREATE TABLE permissions (
+    name VARCHAR(50) PRIMARY KEY,
+    description TEXT NOT NULL
+);
+
+CREATE TABLE role_permissions (
+    role VARCHAR(50) NOT NULL REFERENCES roles(name) ON DELETE CASCADE,
+    permission VARCHAR(50) NOT NULL REFERENCES permissions(name) ON DELETE CASCADE,
+    PRIMARY KEY (role, permission)
+);
+
+INSERT INTO permissions (name, description) VALUES
+    ('analyses:run', 'Run analyses and committees'),
+    ('users:read', 'View users, their sessions and API tokens'),
+    ('users:manage', 'Sign users out, revoke their API tokens and reset their two-factor authentication'),
+    ('roles:read', 'View roles and permissions'),
+    ('roles:manage', 'Create, change and delete roles'),
+    ('providers:read', 'View LLM providers'),
+    ('providers:write', 'Create, change and delete LLM providers'),
+    ('usage:read', 'View LLM and MCP usage statistics'),
+    ('market_data:write', 'Ingest prices, fundamentals and stock profiles'),
+    ('mcp:read', 'View the MCP server status and tool access rules'),
+    ('mcp:manage', 'Change MCP tool access rules');
+
+INSERT INTO roles (name, description) VALUES
+    ('admin', 'Full access'),
+    ('user', 'Runs analyses and manages their own watchlists, portfolios and alerts'),
+    ('analyst', 'A user who also maintains market data'),
+    ('auditor', 'Read-only access to users, roles, providers, usage and the MCP server');
+
+INSERT INTO role_permissions (role, permission)
+SELECT 'admin', name FROM permissions;
+
+INSERT INTO role_permissions (role, permission) VALUES
+    ('user', 'analyses:run'),
+    ('analyst', 'analyses:run'),
+    ('analyst', 'market_data:write'),
+    ('analyst', 'providers:read'),
+    ('analyst', 'usage:read'),
+    ('auditor', 'users:read'),
+    ('auditor', 'roles:read'),
+    ('auditor', 'providers:read'),
+    ('auditor', 'usage:read'),
+    ('auditor', 'mcp:read');
+
+-- Roles set by hand before this table existed keep their users, without permissions
+INSERT INTO roles (name)
+SELECT DISTINCT role FROM users
+ON CONFLICT (name) DO NOTHING;
+
+ALTER TABLE users
+    ADD CONSTRAINT users_role_fkey
+    FOREIGN KEY (role) REFERENCES roles(name);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+UPDATE permissions
+SET description = 'Sign users out, revoke their API tokens and reset their two-factor authentication'
+WHERE name = 'users:manage';
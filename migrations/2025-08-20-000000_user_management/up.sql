@@ -0,0 +1,4 @@
+-- Your SQL goes here
+UPDATE permissions
+SET description = 'Change roles of users, activate, deactivate and delete them, force password resets, sign them out, revoke their API tokens and reset their two-factor authentication'
+WHERE name = 'users:manage';
@@ -0,0 +1,112 @@
+use chrono::{Duration, Utc};
+use diesel::dsl::now;
+use diesel::prelude::*;
+
+use crate::{
+    auth::{generate_opaque_token, hash_token},
+    environments,
+    mailer::Email,
+    models::{AccountToken, NewAccountToken, User},
+    schema::account_tokens,
+};
+
+pub const PURPOSE_EMAIL_VERIFICATION: &str = "email_verification";
+pub const PURPOSE_PASSWORD_RESET: &str = "password_reset";
+/// Proves the password was right while the login waits for a second factor.
+pub const PURPOSE_MFA_CHALLENGE: &str = "mfa_challenge";
+
+const EMAIL_VERIFICATION_TTL: Duration = Duration::hours(48);
+const PASSWORD_RESET_TTL: Duration = Duration::hours(1);
+pub const MFA_CHALLENGE_TTL: Duration = Duration::minutes(5);
+
+/// Issue a single-use token for `purpose` tied to the user's current email address. Unused
+/// tokens issued earlier for the same purpose stop working. Only the token's hash is stored.
+pub fn issue(conn: &mut PgConnection, user: &User, purpose: &str) -> QueryResult<String> {
+    let ttl = match purpose {
+        PURPOSE_PASSWORD_RESET => PASSWORD_RESET_TTL,
+        PURPOSE_MFA_CHALLENGE => MFA_CHALLENGE_TTL,
+        _ => EMAIL_VERIFICATION_TTL,
+    };
+    let token = generate_opaque_token();
+
+    conn.transaction(|conn| {
+        diesel::delete(
+            account_tokens::table
+                .filter(account_tokens::user_id.eq(user.id))
+                .filter(account_tokens::purpose.eq(purpose))
+                .filter(account_tokens::used_at.is_null()),
+        )
+        .execute(conn)?;
+        diesel::insert_into(account_tokens::table)
+            .values(&NewAccountToken {
+                user_id: user.id,
+                purpose: purpose.to_string(),
+                email: user.email.clone(),
+                token_hash: hash_token(&token),
+                expires_at: Utc::now().naive_utc() + ttl,
+            })
+            .execute(conn)
+    })?;
+
+    Ok(token)
+}
+
+/// Use up a token issued for `purpose`. `None` if it is unknown, already used or expired.
+pub fn consume(
+    conn: &mut PgConnection,
+    purpose: &str,
+    token: &str,
+) -> QueryResult<Option<AccountToken>> {
+    diesel::update(
+        account_tokens::table
+            .filter(account_tokens::token_hash.eq(hash_token(token)))
+            .filter(account_tokens::purpose.eq(purpose))
+            .filter(account_tokens::used_at.is_null())
+            .filter(account_tokens::expires_at.gt(now)),
+    )
+    .set(account_tokens::used_at.eq(now))
+    .returning(AccountToken::as_select())
+    .get_result(conn)
+    .optional()
+}
+
+pub fn verification_email(user: &User, token: &str) -> Email {
+    Email {
+        to: user.email.clone(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Hi {},\n\n\
+             Confirm this email address for your Stock Analyzer account by opening\n\n\
+             {}/verify-email?token={}\n\n\
+             or by sending this token to /api/auth/verify-email:\n\n\
+             {}\n\n\
+             The link expires in {} hours. If you did not create an account, ignore this email.\n",
+            user.username,
+            environments::get_app_base_url(),
+            token,
+            token,
+            EMAIL_VERIFICATION_TTL.num_hours()
+        ),
+    }
+}
+
+pub fn password_reset_email(user: &User, token: &str) -> Email {
+    Email {
+        to: user.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\n\
+             Choose a new password for your Stock Analyzer account by opening\n\n\
+             {}/reset-password?token={}\n\n\
+             or by sending this token with the new password to /api/auth/reset-password:\n\n\
+             {}\n\n\
+             The link expires in {} minutes and works once. Resetting the password signs out \
+             every session. If you did not ask for a reset, ignore this email.\n",
+            user.username,
+            environments::get_app_base_url(),
+            token,
+            token,
+            PASSWORD_RESET_TTL.num_minutes()
+        ),
+    }
+}
@@ -0,0 +1,263 @@
+use chrono::{Duration, NaiveDateTime};
+use diesel::{dsl::now, prelude::*};
+use uuid::Uuid;
+
+use crate::{
+    comparison,
+    models::{AlertRule, AlertTrigger, Analysis, NewAlertTrigger, PriceBar},
+    notifications::{self, KIND_ALERT_TRIGGERED},
+    schema::{alert_rules, alert_triggers, analyses},
+    stocks,
+};
+
+pub const CONDITION_PRICE_ABOVE: &str = "price_above";
+pub const CONDITION_PRICE_BELOW: &str = "price_below";
+pub const CONDITION_RSI_ABOVE: &str = "rsi_above";
+pub const CONDITION_RSI_BELOW: &str = "rsi_below";
+pub const CONDITION_BELOW_INTRINSIC_VALUE: &str = "below_intrinsic_value";
+pub const CONDITIONS: [&str; 5] = [
+    CONDITION_PRICE_ABOVE,
+    CONDITION_PRICE_BELOW,
+    CONDITION_RSI_ABOVE,
+    CONDITION_RSI_BELOW,
+    CONDITION_BELOW_INTRINSIC_VALUE,
+];
+
+pub const DEFAULT_COOLDOWN_MINUTES: i32 = 24 * 60;
+pub const MAX_COOLDOWN_MINUTES: i32 = 365 * 24 * 60;
+/// Agent whose intrinsic value `below_intrinsic_value` rules use unless another is named.
+pub const DEFAULT_INTRINSIC_VALUE_AGENT: &str = "buffett";
+pub const RSI_PERIOD: usize = 14;
+/// Closes loaded for the RSI; Wilder smoothing needs a long run-in before it settles.
+const RSI_HISTORY_BARS: i64 = 250;
+
+/// Whether `threshold` suits `condition`: a positive price, an RSI level between 0 and 100,
+/// or an optional margin of safety below the intrinsic value as a fraction in `[0, 1)`.
+pub fn valid_threshold(condition: &str, threshold: Option<f64>) -> bool {
+    match (condition, threshold) {
+        (CONDITION_PRICE_ABOVE | CONDITION_PRICE_BELOW, Some(price)) => {
+            price.is_finite() && price > 0.0
+        }
+        (CONDITION_RSI_ABOVE | CONDITION_RSI_BELOW, Some(level)) => (0.0..=100.0).contains(&level),
+        (CONDITION_BELOW_INTRINSIC_VALUE, None) => true,
+        (CONDITION_BELOW_INTRINSIC_VALUE, Some(margin)) => (0.0..1.0).contains(&margin),
+        _ => false,
+    }
+}
+
+/// A rule whose condition holds, before it is recorded.
+struct Firing {
+    value: f64,
+    threshold: f64,
+    analysis_id: Option<Uuid>,
+    message: String,
+}
+
+/// Check every active rule on `ticker` against its latest stored close and record a trigger
+/// for each one whose condition holds.
+///
+/// Conditions are levels, not crossings: a rule keeps firing while its condition holds, but
+/// at most once per price bar and never again before its cooldown has passed.
+pub fn evaluate_ticker(conn: &mut PgConnection, ticker: &str) -> QueryResult<Vec<AlertTrigger>> {
+    let rules = alert_rules::table
+        .filter(alert_rules::ticker.eq(ticker))
+        .filter(alert_rules::is_active.eq(true))
+        .select(AlertRule::as_select())
+        .load(conn)?;
+    if rules.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let bars = stocks::load_price_history(conn, ticker, RSI_HISTORY_BARS)?;
+    let Some(latest) = bars.first() else {
+        return Ok(Vec::new());
+    };
+    let closes: Vec<f64> = bars.iter().rev().map(|bar| bar.close).collect();
+    let rsi = relative_strength_index(&closes, RSI_PERIOD);
+    let current_time: NaiveDateTime = diesel::select(now).get_result(conn)?;
+
+    let mut triggered = Vec::new();
+    for rule in rules {
+        let cooling_down = rule.last_triggered_at.is_some_and(|last| {
+            current_time < last + Duration::minutes(rule.cooldown_minutes.into())
+        });
+        if cooling_down {
+            continue;
+        }
+        let Some(firing) = check(conn, &rule, latest, rsi)? else {
+            continue;
+        };
+
+        let recorded = conn.transaction(|conn| record_trigger(conn, &rule, latest, firing))?;
+        triggered.extend(recorded);
+    }
+
+    Ok(triggered)
+}
+
+/// Store the trigger, start the rule's cooldown and notify its owner; `None` if the rule
+/// already fired on this bar, e.g. when the same prices are ingested twice.
+fn record_trigger(
+    conn: &mut PgConnection,
+    rule: &AlertRule,
+    bar: &PriceBar,
+    firing: Firing,
+) -> QueryResult<Option<AlertTrigger>> {
+    let trigger = diesel::insert_into(alert_triggers::table)
+        .values(&NewAlertTrigger {
+            rule_id: rule.id,
+            user_id: rule.user_id,
+            ticker: rule.ticker.clone(),
+            trade_date: bar.trade_date,
+            value: firing.value,
+            threshold: firing.threshold,
+            analysis_id: firing.analysis_id,
+            message: firing.message,
+        })
+        .on_conflict((alert_triggers::rule_id, alert_triggers::trade_date))
+        .do_nothing()
+        .returning(AlertTrigger::as_select())
+        .get_result(conn)
+        .optional()?;
+    let Some(trigger) = trigger else {
+        return Ok(None);
+    };
+
+    diesel::update(alert_rules::table.find(rule.id))
+        .set(alert_rules::last_triggered_at.eq(trigger.triggered_at))
+        .execute(conn)?;
+    notifications::notify(
+        conn,
+        rule.user_id,
+        KIND_ALERT_TRIGGERED,
+        format!("Alert: {}", trigger.ticker),
+        trigger.message.clone(),
+        serde_json::json!({
+            "rule_id": rule.id,
+            "trigger_id": trigger.id,
+            "ticker": trigger.ticker,
+            "condition": rule.condition,
+            "trade_date": trigger.trade_date,
+            "value": trigger.value,
+            "threshold": trigger.threshold,
+        }),
+    )?;
+
+    Ok(Some(trigger))
+}
+
+fn check(
+    conn: &mut PgConnection,
+    rule: &AlertRule,
+    bar: &PriceBar,
+    rsi: Option<f64>,
+) -> QueryResult<Option<Firing>> {
+    let threshold = rule.threshold.unwrap_or(0.0);
+    let ticker = &rule.ticker;
+    let close = bar.close;
+
+    let firing = match rule.condition.as_str() {
+        CONDITION_PRICE_ABOVE => (close > threshold).then(|| Firing {
+            value: close,
+            threshold,
+            analysis_id: None,
+            message: format!("{} closed at {:.2}, above {:.2}", ticker, close, threshold),
+        }),
+        CONDITION_PRICE_BELOW => (close < threshold).then(|| Firing {
+            value: close,
+            threshold,
+            analysis_id: None,
+            message: format!("{} closed at {:.2}, below {:.2}", ticker, close, threshold),
+        }),
+        CONDITION_RSI_ABOVE => rsi.filter(|rsi| *rsi > threshold).map(|rsi| Firing {
+            value: rsi,
+            threshold,
+            analysis_id: None,
+            message: format!(
+                "{} RSI({}) is {:.1}, above {:.1}",
+                ticker, RSI_PERIOD, rsi, threshold
+            ),
+        }),
+        CONDITION_RSI_BELOW => rsi.filter(|rsi| *rsi < threshold).map(|rsi| Firing {
+            value: rsi,
+            threshold,
+            analysis_id: None,
+            message: format!(
+                "{} RSI({}) is {:.1}, below {:.1}",
+                ticker, RSI_PERIOD, rsi, threshold
+            ),
+        }),
+        CONDITION_BELOW_INTRINSIC_VALUE => {
+            let Some(analysis) = latest_valuation(conn, rule)? else {
+                return Ok(None);
+            };
+            let Some(output) = comparison::structured(&analysis) else {
+                return Ok(None);
+            };
+            let intrinsic_value = (output.intrinsic_value_low + output.intrinsic_value_high) / 2.0;
+            let buy_below = intrinsic_value * (1.0 - threshold);
+            (close < buy_below).then(|| Firing {
+                value: close,
+                threshold: buy_below,
+                analysis_id: Some(analysis.id),
+                message: format!(
+                    "{} closed at {:.2}, {:.0}% below the intrinsic value of {:.2} from the analysis of {}",
+                    ticker,
+                    close,
+                    (1.0 - close / intrinsic_value) * 100.0,
+                    intrinsic_value,
+                    analysis.created_at.date()
+                ),
+            })
+        }
+        _ => None,
+    };
+
+    Ok(firing)
+}
+
+/// The rule owner's newest structured analysis of the ticker by the rule's agent.
+fn latest_valuation(conn: &mut PgConnection, rule: &AlertRule) -> QueryResult<Option<Analysis>> {
+    let Some(agent_id) = rule.agent_id else {
+        return Ok(None);
+    };
+    analyses::table
+        .filter(analyses::user_id.eq(rule.user_id))
+        .filter(analyses::ticker.eq(&rule.ticker))
+        .filter(analyses::agent_id.eq(agent_id))
+        .filter(analyses::structured_output.is_not_null())
+        .order(analyses::created_at.desc())
+        .select(Analysis::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Wilder's relative strength index of closes ordered oldest first; `None` until there are
+/// `period` price changes.
+pub fn relative_strength_index(closes: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || closes.len() <= period {
+        return None;
+    }
+    let changes: Vec<f64> = closes.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let (seed, rest) = changes.split_at(period);
+    let n = period as f64;
+
+    let mut gain = seed
+        .iter()
+        .fold(0.0, |total, change| total + change.max(0.0))
+        / n;
+    let mut loss = seed
+        .iter()
+        .fold(0.0, |total, change| total + (-change).max(0.0))
+        / n;
+    for change in rest {
+        gain = (gain * (n - 1.0) + change.max(0.0)) / n;
+        loss = (loss * (n - 1.0) + (-change).max(0.0)) / n;
+    }
+
+    if loss == 0.0 {
+        // A flat series has no direction; one without losses is maximally overbought
+        return Some(if gain == 0.0 { 50.0 } else { 100.0 });
+    }
+    Some(100.0 - 100.0 / (1.0 + gain / loss))
+}
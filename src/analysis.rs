@@ -0,0 +1,404 @@
+use chrono::{Datelike, NaiveDate};
+use diesel::prelude::*;
+use serde::Serialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+    database::DbPool,
+    llm::{ChatMessage, ChatRequest, LlmClient, LlmError, ResponseSchema, ToolDefinition},
+    mcp::client::{McpClient, McpError},
+    models::{
+        Agent, Analysis, Fundamental, LlmProvider, LlmUsage, NewAnalysis, NewLlmUsage, PriceBar,
+    },
+    schema::{analyses, llm_usage},
+    stocks,
+    structured_output::{parse_analysis, StructuredAnalysis, ANALYSIS_SCHEMA_NAME},
+    tool_calling::{research, tool_definitions},
+    valuation::{self, Valuation},
+};
+
+/// `llm_usage.request_type` recorded for single-agent analyses.
+pub const REQUEST_TYPE_STOCK_ANALYSIS: &str = "stock_analysis";
+/// `llm_usage.request_type` recorded for every call made by a committee, moderator included.
+pub const REQUEST_TYPE_COMMITTEE: &str = "committee";
+
+/// Roughly five years of daily bars, enough for multi-year highs and lows.
+const PRICE_HISTORY_LIMIT: i64 = 5 * 252;
+const ANNUAL_PERIODS: i64 = 5;
+const QUARTERLY_PERIODS: i64 = 4;
+/// Calls made before giving up on a model that keeps returning malformed output.
+const MAX_OUTPUT_ATTEMPTS: usize = 3;
+/// Stands in for the market data block in prompts of agents that fetch their own data.
+const TOOL_MARKET_DATA: &str = "Market data is not included here. Use the available tools to \
+look up the quote, price history, financials and valuation you need, then give your analysis.";
+/// Sent when research ends without a usable answer, so the agent answers from what it gathered.
+const FINAL_ANSWER_PROMPT: &str = "Submit your final analysis now as a JSON object matching \
+the required schema, using only the data gathered so far.";
+
+#[derive(Debug, Error)]
+pub enum AnalysisError {
+    #[error("no market data stored for {0}")]
+    NoMarketData(String),
+    #[error("model returned invalid output after {MAX_OUTPUT_ATTEMPTS} attempts: {0}")]
+    InvalidOutput(String),
+    #[error("LLM call failed: {0}")]
+    Llm(#[from] LlmError),
+    #[error("MCP tools unavailable: {0}")]
+    Mcp(#[from] McpError),
+    #[error("database error: {0}")]
+    Database(#[from] diesel::result::Error),
+    #[error("connection pool error: {0}")]
+    Pool(#[from] diesel::r2d2::PoolError),
+}
+
+/// Everything the agent gets to see about a stock, also stored with the analysis.
+#[derive(Debug, Serialize)]
+pub struct MarketData {
+    pub ticker: String,
+    pub as_of: Option<NaiveDate>,
+    pub monthly_closes: Vec<MonthlyClose>,
+    pub five_year_high: Option<f64>,
+    pub five_year_low: Option<f64>,
+    pub annual: Vec<ReportedPeriod>,
+    pub quarterly: Vec<ReportedPeriod>,
+    pub valuation: Valuation,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MonthlyClose {
+    pub month: String,
+    pub close: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportedPeriod {
+    pub period_end: NaiveDate,
+    pub revenue: Option<f64>,
+    pub net_income: Option<f64>,
+    pub eps: Option<f64>,
+    pub free_cash_flow: Option<f64>,
+    pub book_value_per_share: Option<f64>,
+    pub total_assets: Option<f64>,
+    pub total_liabilities: Option<f64>,
+    pub current_assets: Option<f64>,
+    pub total_debt: Option<f64>,
+    pub shares_outstanding: Option<f64>,
+    pub dividends_per_share: Option<f64>,
+}
+
+impl From<Fundamental> for ReportedPeriod {
+    fn from(fundamental: Fundamental) -> Self {
+        Self {
+            period_end: fundamental.period_end,
+            revenue: fundamental.revenue,
+            net_income: fundamental.net_income,
+            eps: fundamental.eps,
+            free_cash_flow: fundamental.free_cash_flow,
+            book_value_per_share: fundamental.book_value_per_share,
+            total_assets: fundamental.total_assets,
+            total_liabilities: fundamental.total_liabilities,
+            current_assets: fundamental.current_assets,
+            total_debt: fundamental.total_debt,
+            shares_outstanding: fundamental.shares_outstanding,
+            dividends_per_share: fundamental.dividends_per_share,
+        }
+    }
+}
+
+/// Collect prices, fundamentals and computed valuations for `ticker`.
+pub fn gather_market_data(
+    conn: &mut PgConnection,
+    ticker: &str,
+) -> Result<MarketData, AnalysisError> {
+    let prices = stocks::load_price_history(conn, ticker, PRICE_HISTORY_LIMIT)?;
+    let annual = stocks::load_fundamentals(conn, ticker, "annual", ANNUAL_PERIODS)?;
+    let quarterly = stocks::load_fundamentals(conn, ticker, "quarterly", QUARTERLY_PERIODS)?;
+
+    if prices.is_empty() && annual.is_empty() && quarterly.is_empty() {
+        return Err(AnalysisError::NoMarketData(ticker.to_string()));
+    }
+
+    let valuation = valuation::compute(&prices, &annual);
+
+    Ok(MarketData {
+        ticker: ticker.to_string(),
+        as_of: prices.first().map(|bar| bar.trade_date),
+        monthly_closes: monthly_closes(&prices),
+        five_year_high: prices.iter().map(|bar| bar.high).reduce(f64::max),
+        five_year_low: prices.iter().map(|bar| bar.low).reduce(f64::min),
+        annual: annual.into_iter().map(ReportedPeriod::from).collect(),
+        quarterly: quarterly.into_iter().map(ReportedPeriod::from).collect(),
+        valuation,
+    })
+}
+
+/// Fill the agent's prompt template with the ticker and pretty-printed market data.
+pub fn render_prompt(agent: &Agent, ticker: &str, market_data: &serde_json::Value) -> String {
+    let market_data =
+        serde_json::to_string_pretty(market_data).unwrap_or_else(|_| market_data.to_string());
+
+    agent
+        .prompt_template
+        .replace("{{ticker}}", ticker)
+        .replace("{{market_data}}", &market_data)
+}
+
+/// Fill the agent's prompt template for a run where the agent looks the data up itself.
+pub fn render_tool_prompt(agent: &Agent, ticker: &str) -> String {
+    agent
+        .prompt_template
+        .replace("{{ticker}}", ticker)
+        .replace("{{market_data}}", TOOL_MARKET_DATA)
+}
+
+/// A validated model answer along with every token spent getting it.
+pub struct StructuredReply<T> {
+    pub raw_text: String,
+    pub parsed: T,
+    pub tokens_used: i32,
+}
+
+/// Structured analysis from one agent: the full validated document and its typed fields.
+pub type AgentOutput = StructuredReply<(serde_json::Value, StructuredAnalysis)>;
+
+/// Ask the agent for output matching its `output_schema`, feeding validation errors from
+/// `parse` back to the model until it produces an acceptable document.
+///
+/// `messages` is the conversation so far, ending with the request for the answer. `tools`
+/// are offered to the model for context only; it is not allowed to call them here.
+///
+/// On failure the tokens already spent are returned alongside the error so they can still
+/// be recorded as usage.
+pub async fn request_structured<T>(
+    client: &LlmClient,
+    agent: &Agent,
+    mut messages: Vec<ChatMessage>,
+    tools: &[ToolDefinition],
+    parse: fn(&str, &serde_json::Value) -> Result<T, String>,
+) -> Result<StructuredReply<T>, (AnalysisError, i32)> {
+    let schema = ResponseSchema {
+        name: ANALYSIS_SCHEMA_NAME,
+        schema: &agent.output_schema,
+    };
+    let mut tokens_used = 0;
+    let mut last_error = String::new();
+
+    for attempt in 1..=MAX_OUTPUT_ATTEMPTS {
+        let completion = client
+            .chat(&ChatRequest {
+                system: &agent.system_prompt,
+                messages: &messages,
+                tools,
+                response_schema: Some(schema),
+            })
+            .await
+            .map_err(|e| (e.into(), tokens_used))?;
+        tokens_used += completion.tokens_used;
+
+        match parse(&completion.text, &agent.output_schema) {
+            Ok(parsed) => {
+                return Ok(StructuredReply {
+                    raw_text: completion.text,
+                    parsed,
+                    tokens_used,
+                })
+            }
+            Err(error) => {
+                tracing::warn!(
+                    "Agent {} returned invalid output (attempt {}): {}",
+                    agent.slug,
+                    attempt,
+                    error
+                );
+                messages.push(ChatMessage::assistant(completion.text));
+                messages.push(ChatMessage::user(format!(
+                    "That response was rejected: {}. Reply again with only a JSON object that matches the required schema.",
+                    error
+                )));
+                last_error = error;
+            }
+        }
+    }
+
+    Err((AnalysisError::InvalidOutput(last_error), tokens_used))
+}
+
+/// Record tokens spent on a provider call.
+pub fn record_usage(
+    conn: &mut PgConnection,
+    provider_id: Uuid,
+    user_id: Uuid,
+    tokens_used: i32,
+    request_type: &str,
+) -> QueryResult<LlmUsage> {
+    diesel::insert_into(llm_usage::table)
+        .values(&NewLlmUsage {
+            provider_id,
+            user_id,
+            tokens_used,
+            cost: None,
+            request_type: request_type.to_string(),
+        })
+        .returning(LlmUsage::as_select())
+        .get_result(conn)
+}
+
+/// A finished agent run that has not been stored yet.
+pub struct CompletedAnalysis {
+    pub ticker: String,
+    pub input_data: serde_json::Value,
+    pub prompt: String,
+    pub output: AgentOutput,
+    /// Tool calls made by an agent that fetched its own data.
+    pub tool_trace: Option<serde_json::Value>,
+}
+
+/// Run one agent against `ticker`.
+///
+/// With `mcp` the agent researches the stock through MCP tools before answering instead of
+/// getting the market data in its prompt. The data is still gathered and stored as input.
+///
+/// Tokens spent on a run that ends in an error are recorded as usage here; a successful
+/// run is recorded by `save_analysis` together with the analysis it produced.
+pub async fn perform_analysis(
+    pool: &DbPool,
+    user_id: Uuid,
+    agent: &Agent,
+    provider: &LlmProvider,
+    ticker: &str,
+    request_type: &str,
+    mcp: Option<&McpClient>,
+) -> Result<CompletedAnalysis, AnalysisError> {
+    let market_data = {
+        let mut conn = pool.get()?;
+        gather_market_data(&mut conn, ticker)?
+    };
+    let input_data = serde_json::to_value(&market_data).unwrap_or_default();
+
+    let client = LlmClient::from_provider(provider)?;
+    let (prompt, result) = match mcp {
+        Some(mcp) => {
+            let prompt = render_tool_prompt(agent, ticker);
+            let tools = tool_definitions(&mcp.tools().await?);
+            let result = research_and_answer(&client, mcp, agent, &tools, &prompt).await;
+            (prompt, result)
+        }
+        None => {
+            let prompt = render_prompt(agent, ticker, &input_data);
+            let messages = vec![ChatMessage::user(&prompt)];
+            let result = request_structured(&client, agent, messages, &[], parse_analysis)
+                .await
+                .map(|output| (output, None));
+            (prompt, result)
+        }
+    };
+
+    match result {
+        Ok((output, tool_trace)) => Ok(CompletedAnalysis {
+            ticker: ticker.to_string(),
+            input_data,
+            prompt,
+            output,
+            tool_trace,
+        }),
+        Err((error, tokens_used)) => {
+            if tokens_used > 0 {
+                let mut conn = pool.get()?;
+                record_usage(&mut conn, provider.id, user_id, tokens_used, request_type)?;
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Let the agent call tools, then take its answer, asking for one explicitly when it stopped
+/// at a limit or answered with something that does not validate.
+async fn research_and_answer(
+    client: &LlmClient,
+    mcp: &McpClient,
+    agent: &Agent,
+    tools: &[ToolDefinition],
+    prompt: &str,
+) -> Result<(AgentOutput, Option<serde_json::Value>), (AnalysisError, i32)> {
+    let research = research(client, mcp, agent, tools, prompt).await?;
+    let research_tokens = research.trace.tokens_used;
+    let tool_trace = serde_json::to_value(&research.trace).ok();
+
+    if let Some(text) = research.final_text {
+        if let Ok(parsed) = parse_analysis(&text, &agent.output_schema) {
+            let output = StructuredReply {
+                raw_text: text,
+                parsed,
+                tokens_used: research_tokens,
+            };
+            return Ok((output, tool_trace));
+        }
+    }
+
+    let mut messages = research.messages;
+    messages.push(ChatMessage::user(FINAL_ANSWER_PROMPT));
+    match request_structured(client, agent, messages, tools, parse_analysis).await {
+        Ok(mut output) => {
+            output.tokens_used += research_tokens;
+            Ok((output, tool_trace))
+        }
+        Err((error, tokens_used)) => Err((error, tokens_used + research_tokens)),
+    }
+}
+
+/// Persist a completed run and its usage record. `id` lets the caller choose the analysis id.
+pub fn save_analysis(
+    conn: &mut PgConnection,
+    id: Option<Uuid>,
+    user_id: Uuid,
+    agent: &Agent,
+    provider: &LlmProvider,
+    completed: CompletedAnalysis,
+    request_type: &str,
+) -> QueryResult<(Analysis, LlmUsage)> {
+    let usage = record_usage(
+        conn,
+        provider.id,
+        user_id,
+        completed.output.tokens_used,
+        request_type,
+    )?;
+
+    let analysis: Analysis = diesel::insert_into(analyses::table)
+        .values(&NewAnalysis {
+            id,
+            user_id,
+            agent_id: agent.id,
+            provider_id: provider.id,
+            llm_usage_id: Some(usage.id),
+            ticker: completed.ticker,
+            input_data: completed.input_data,
+            prompt: completed.prompt,
+            result_text: completed.output.raw_text,
+            structured_output: Some(completed.output.parsed.0),
+            verdict: Some(completed.output.parsed.1.verdict.as_str().to_string()),
+            tool_trace: completed.tool_trace,
+        })
+        .returning(Analysis::as_select())
+        .get_result(conn)?;
+
+    Ok((analysis, usage))
+}
+
+/// Last close of each calendar month, newest first.
+fn monthly_closes(prices: &[PriceBar]) -> Vec<MonthlyClose> {
+    let mut closes: Vec<MonthlyClose> = Vec::new();
+
+    for bar in prices {
+        let month = format!("{}-{:02}", bar.trade_date.year(), bar.trade_date.month());
+        if closes.last().is_none_or(|last| last.month != month) {
+            closes.push(MonthlyClose {
+                month,
+                close: bar.close,
+            });
+        }
+    }
+
+    closes
+}
@@ -0,0 +1,135 @@
+use axum::http::Method;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::dsl::now;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    auth::{generate_opaque_token, hash_token},
+    models::{ApiToken, NewApiToken, User},
+    schema::{api_tokens, users},
+};
+
+/// Every API token starts with this, which is how they are told apart from access tokens.
+pub const TOKEN_PREFIX: &str = "sat_";
+/// Characters of the token kept in the clear so users can recognize it.
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+/// Read-only requests to user endpoints.
+pub const SCOPE_READ: &str = "read";
+/// Any request to user endpoints; implies `read`.
+pub const SCOPE_WRITE: &str = "write";
+/// Admin endpoints, for tokens of admins.
+pub const SCOPE_ADMIN: &str = "admin";
+pub const SCOPES: [&str; 3] = [SCOPE_READ, SCOPE_WRITE, SCOPE_ADMIN];
+
+pub const DEFAULT_TTL_DAYS: i64 = 90;
+pub const MAX_TTL_DAYS: i64 = 365;
+
+/// How stale `last_used_at` may get before a request updates it, so busy tokens do not cost a
+/// write per request.
+const LAST_USED_RESOLUTION: Duration = Duration::minutes(1);
+
+/// Create a token for the user. The token itself is returned once and only its hash is stored.
+pub fn create(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    name: String,
+    scopes: Vec<String>,
+    expires_at: NaiveDateTime,
+) -> QueryResult<(ApiToken, String)> {
+    let token = format!("{}{}", TOKEN_PREFIX, generate_opaque_token());
+    let api_token = diesel::insert_into(api_tokens::table)
+        .values(&NewApiToken {
+            user_id,
+            name,
+            token_prefix: token[..DISPLAY_PREFIX_LEN].to_string(),
+            token_hash: hash_token(&token),
+            scopes,
+            expires_at,
+        })
+        .returning(ApiToken::as_returning())
+        .get_result(conn)?;
+
+    Ok((api_token, token))
+}
+
+/// The user and token for a bearer API token that is not revoked or expired and whose user is
+/// active. Records when and from where the token was used.
+pub fn authenticate(
+    conn: &mut PgConnection,
+    token: &str,
+    ip_address: Option<String>,
+) -> QueryResult<Option<(User, ApiToken)>> {
+    let found = api_tokens::table
+        .inner_join(users::table)
+        .filter(api_tokens::token_hash.eq(hash_token(token)))
+        .filter(api_tokens::revoked_at.is_null())
+        .filter(api_tokens::expires_at.gt(now))
+        .filter(users::is_active.eq(true))
+        .select((User::as_select(), ApiToken::as_select()))
+        .first::<(User, ApiToken)>(conn)
+        .optional()?;
+    let Some((user, api_token)) = found else {
+        return Ok(None);
+    };
+
+    let stale_before = Utc::now().naive_utc() - LAST_USED_RESOLUTION;
+    let is_stale = api_token
+        .last_used_at
+        .is_none_or(|last_used_at| last_used_at < stale_before);
+    if is_stale || api_token.last_used_ip != ip_address {
+        diesel::update(api_tokens::table.find(api_token.id))
+            .set((
+                api_tokens::last_used_at.eq(now),
+                api_tokens::last_used_ip.eq(&ip_address),
+            ))
+            .execute(conn)?;
+    }
+
+    Ok(Some((user, api_token)))
+}
+
+/// Whether a token with these scopes may make a request with `method` to user endpoints.
+pub fn permits(scopes: &[String], method: &Method) -> bool {
+    let has = |scope: &str| scopes.iter().any(|s| s == scope);
+    if has(SCOPE_WRITE) {
+        return true;
+    }
+    has(SCOPE_READ) && matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// The user's tokens that are not revoked, newest first. Expired tokens are included until
+/// they are revoked.
+pub fn list(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<Vec<ApiToken>> {
+    api_tokens::table
+        .filter(api_tokens::user_id.eq(user_id))
+        .filter(api_tokens::revoked_at.is_null())
+        .order(api_tokens::created_at.desc())
+        .select(ApiToken::as_select())
+        .load(conn)
+}
+
+/// Revoke one of the user's tokens. Returns whether it existed and was not already revoked.
+pub fn revoke(conn: &mut PgConnection, user_id: Uuid, token_id: Uuid) -> QueryResult<bool> {
+    let revoked = diesel::update(
+        api_tokens::table
+            .filter(api_tokens::id.eq(token_id))
+            .filter(api_tokens::user_id.eq(user_id))
+            .filter(api_tokens::revoked_at.is_null()),
+    )
+    .set(api_tokens::revoked_at.eq(now))
+    .execute(conn)?;
+    Ok(revoked > 0)
+}
+
+/// Revoke every token of the user, returning how many were revoked.
+pub fn revoke_all(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<usize> {
+    diesel::update(
+        api_tokens::table
+            .filter(api_tokens::user_id.eq(user_id))
+            .filter(api_tokens::revoked_at.is_null()),
+    )
+    .set(api_tokens::revoked_at.eq(now))
+    .execute(conn)
+}
@@ -1,27 +1,29 @@
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
-use crate::environments;
+use crate::{environments, jwt_keys};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: String, // subject (user id)
     pub username: String,
     pub role: String,
-    pub exp: usize, // expiration time
-    pub iat: usize, // issued at
+    pub exp: usize,  // expiration time
+    pub iat: usize,  // issued at
+    pub jti: String, // unique token id
+    pub sid: String, // session the token was issued for
 }
 
 pub fn create_jwt(
     user_id: Uuid,
     username: &str,
     role: &str,
+    session_id: Uuid,
 ) -> Result<String, jsonwebtoken::errors::Error> {
-    let secret = environments::get_jwt_secret();
     let now = Utc::now();
-    let expires_at = now + Duration::days(environments::get_jwt_expiration_days());
+    let expires_at = now + Duration::minutes(environments::get_jwt_expiration_minutes());
 
     let claims = Claims {
         sub: user_id.to_string(),
@@ -29,25 +31,15 @@ pub fn create_jwt(
         role: role.to_string(),
         exp: expires_at.timestamp() as usize,
         iat: now.timestamp() as usize,
+        jti: Uuid::new_v4().to_string(),
+        sid: session_id.to_string(),
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
+    jwt_keys::keys().sign(&claims)
 }
 
 pub fn verify_jwt(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let secret = environments::get_jwt_secret();
-    let validation = Validation::new(Algorithm::HS256);
-
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &validation,
-    )
-    .map(|data| data.claims)
+    jwt_keys::keys().verify(token)
 }
 
 pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
@@ -57,3 +49,13 @@ pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
 pub fn verify_password(password: &str, hash: &str) -> Result<bool, bcrypt::BcryptError> {
     bcrypt::verify(password, hash)
 }
+
+/// A random opaque token for refresh and similar single-purpose credentials.
+pub fn generate_opaque_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Hex SHA-256 of an opaque token, which is what gets stored instead of the token.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,28 @@
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{self, fmt, EnvFilter};
+
+use r_stock_analyzer::{
+    database, environments,
+    mcp::server::{self, McpServer},
+};
+
+#[tokio::main]
+async fn main() {
+    // Load environment variables
+    environments::init_env();
+
+    // stdout carries the protocol, so logs go to stderr
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(fmt::layer().with_target(false).with_writer(std::io::stderr))
+        .init();
+
+    // Migrations are run by the API server; this process only reads
+    let pool = database::create_connection_pool();
+
+    tracing::info!("MCP server listening on stdio");
+    if let Err(e) = server::serve_stdio(McpServer::new(pool)).await {
+        tracing::error!("MCP server stopped: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,259 @@
+use diesel::dsl::now;
+use diesel::prelude::*;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    analysis::{
+        perform_analysis, record_usage, request_structured, save_analysis, AnalysisError,
+        CompletedAnalysis, REQUEST_TYPE_COMMITTEE,
+    },
+    database::DbPool,
+    jobs::{notify_finished, STATUS_RUNNING, STATUS_SUCCEEDED},
+    llm::{ChatMessage, LlmClient},
+    mcp::client::McpClient,
+    models::{Agent, AnalysisJob, CommitteeMember, LlmProvider},
+    schema::{agents, analysis_jobs, committee_members, committees, llm_providers},
+    structured_output::{parse_moderation, StructuredAnalysis, Verdict},
+};
+
+pub const AGENT_KIND_ANALYST: &str = "analyst";
+pub const AGENT_KIND_MODERATOR: &str = "moderator";
+pub const MIN_MEMBERS: usize = 2;
+pub const MAX_MEMBERS: usize = 6;
+
+/// Consensus scores above this are a buy, below its negation a sell, anything between a hold.
+const CONSENSUS_THRESHOLD: f64 = 1.0 / 3.0;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Consensus {
+    pub verdict: Verdict,
+    /// -1 when every vote is a high-conviction sell, 1 when every vote is a high-conviction buy.
+    pub score: f64,
+}
+
+/// Combine member verdicts, weighting each vote by the member's weight and its conviction.
+pub fn weighted_consensus(votes: &[(f64, &StructuredAnalysis)]) -> Consensus {
+    let (weighted, total) =
+        votes
+            .iter()
+            .fold((0.0, 0.0), |(weighted, total), (weight, analysis)| {
+                let strength = weight * f64::from(analysis.conviction);
+                let direction = match analysis.verdict {
+                    Verdict::Buy => 1.0,
+                    Verdict::Hold => 0.0,
+                    Verdict::Sell => -1.0,
+                };
+                (weighted + strength * direction, total + strength)
+            });
+
+    let score = if total > 0.0 { weighted / total } else { 0.0 };
+    let verdict = if score > CONSENSUS_THRESHOLD {
+        Verdict::Buy
+    } else if score < -CONSENSUS_THRESHOLD {
+        Verdict::Sell
+    } else {
+        Verdict::Hold
+    };
+
+    Consensus { verdict, score }
+}
+
+/// Run every member concurrently, then the moderator, and store the committee result.
+///
+/// A committee succeeds as long as one member produced an analysis. A failed moderator call
+/// leaves the weighted consensus in place without a moderator summary.
+pub async fn execute_committee_job(
+    pool: &DbPool,
+    mcp: Option<&McpClient>,
+    job: &AnalysisJob,
+) -> Result<(), AnalysisError> {
+    let (moderator, provider, members) = {
+        let mut conn = pool.get()?;
+        let moderator = agents::table
+            .find(job.agent_id)
+            .select(Agent::as_select())
+            .first(&mut conn)?;
+        let provider = llm_providers::table
+            .find(job.provider_id)
+            .select(LlmProvider::as_select())
+            .first(&mut conn)?;
+        let members = committee_members::table
+            .inner_join(agents::table)
+            .filter(committee_members::committee_id.eq(job.id))
+            .order(committee_members::created_at.asc())
+            .select((CommitteeMember::as_select(), Agent::as_select()))
+            .load::<(CommitteeMember, Agent)>(&mut conn)?;
+        (moderator, provider, members)
+    };
+
+    let runs = futures::future::join_all(members.iter().map(|(_, agent)| {
+        perform_analysis(
+            pool,
+            job.user_id,
+            agent,
+            &provider,
+            &job.ticker,
+            REQUEST_TYPE_COMMITTEE,
+            mcp,
+        )
+    }))
+    .await;
+
+    let mut outcomes: Vec<(
+        CommitteeMember,
+        Agent,
+        Result<CompletedAnalysis, AnalysisError>,
+    )> = members
+        .into_iter()
+        .zip(runs)
+        .map(|((member, agent), run)| (member, agent, run))
+        .collect();
+
+    if outcomes.iter().all(|(_, _, run)| run.is_err()) {
+        let (_, _, run) = outcomes.remove(0);
+        return Err(run.err().expect("every member run failed"));
+    }
+
+    let votes: Vec<(f64, &StructuredAnalysis)> = outcomes
+        .iter()
+        .filter_map(|(member, _, run)| {
+            run.as_ref()
+                .ok()
+                .map(|completed| (member.weight, &completed.output.parsed.1))
+        })
+        .collect();
+    let consensus = weighted_consensus(&votes);
+
+    let briefing = json!({
+        "consensus": consensus,
+        "members": outcomes.iter().map(|(member, agent, run)| match run {
+            Ok(completed) => json!({
+                "agent": agent.name,
+                "weight": member.weight,
+                "analysis": completed.output.parsed.0,
+            }),
+            Err(error) => json!({
+                "agent": agent.name,
+                "weight": member.weight,
+                "error": error.to_string(),
+            }),
+        }).collect::<Vec<_>>(),
+    });
+    let prompt = moderator
+        .prompt_template
+        .replace("{{ticker}}", &job.ticker)
+        .replace(
+            "{{committee}}",
+            &serde_json::to_string_pretty(&briefing).unwrap_or_default(),
+        );
+
+    let client = LlmClient::from_provider(&provider)?;
+    let moderation = request_structured(
+        &client,
+        &moderator,
+        vec![ChatMessage::user(&prompt)],
+        &[],
+        parse_moderation,
+    )
+    .await;
+    let moderator_tokens = match &moderation {
+        Ok(reply) => reply.tokens_used,
+        Err((error, tokens_used)) => {
+            tracing::warn!("Moderator for committee {} failed: {}", job.id, error);
+            *tokens_used
+        }
+    };
+
+    let mut conn = pool.get()?;
+    conn.transaction(|conn| {
+        let status: String = analysis_jobs::table
+            .find(job.id)
+            .select(analysis_jobs::status)
+            .for_update()
+            .first(conn)?;
+        let is_cancelled = status != STATUS_RUNNING;
+
+        for (member, agent, run) in outcomes {
+            match run {
+                // Cancelled while the members were working: keep the usage, drop the results
+                Ok(completed) if is_cancelled => {
+                    record_usage(
+                        conn,
+                        provider.id,
+                        job.user_id,
+                        completed.output.tokens_used,
+                        REQUEST_TYPE_COMMITTEE,
+                    )?;
+                }
+                Ok(completed) => {
+                    let (analysis, _) = save_analysis(
+                        conn,
+                        None,
+                        job.user_id,
+                        &agent,
+                        &provider,
+                        completed,
+                        REQUEST_TYPE_COMMITTEE,
+                    )?;
+                    diesel::update(committee_members::table.find(member.id))
+                        .set((
+                            committee_members::analysis_id.eq(analysis.id),
+                            committee_members::error.eq(None::<String>),
+                        ))
+                        .execute(conn)?;
+                }
+                Err(error) => {
+                    diesel::update(committee_members::table.find(member.id))
+                        .set(committee_members::error.eq(error.to_string()))
+                        .execute(conn)?;
+                }
+            }
+        }
+
+        let usage = if moderator_tokens > 0 {
+            Some(record_usage(
+                conn,
+                provider.id,
+                job.user_id,
+                moderator_tokens,
+                REQUEST_TYPE_COMMITTEE,
+            )?)
+        } else {
+            None
+        };
+
+        if is_cancelled {
+            return Ok(());
+        }
+
+        let (moderator_output, moderator_text) = match moderation {
+            Ok(reply) => (Some(reply.parsed.0), Some(reply.raw_text)),
+            Err(_) => (None, None),
+        };
+
+        diesel::update(committees::table.find(job.id))
+            .set((
+                committees::consensus_verdict.eq(consensus.verdict.as_str()),
+                committees::consensus_score.eq(consensus.score),
+                committees::moderator_output.eq(moderator_output),
+                committees::moderator_text.eq(moderator_text),
+                committees::llm_usage_id.eq(usage.map(|usage| usage.id)),
+            ))
+            .execute(conn)?;
+
+        diesel::update(analysis_jobs::table.find(job.id))
+            .set((
+                analysis_jobs::status.eq(STATUS_SUCCEEDED),
+                analysis_jobs::last_error.eq(None::<String>),
+                analysis_jobs::finished_at.eq(now),
+                analysis_jobs::updated_at.eq(now),
+            ))
+            .execute(conn)?;
+        notify_finished(conn, job, None)?;
+
+        diesel::QueryResult::Ok(())
+    })?;
+
+    Ok(())
+}
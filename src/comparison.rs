@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{models::Analysis, structured_output::StructuredAnalysis};
+
+/// Market figures taken from each analysis' stored input and compared side by side.
+const MARKET_FIGURES: [&str; 8] = [
+    "price",
+    "pe_ratio",
+    "pb_ratio",
+    "earnings_yield",
+    "graham_number",
+    "ncav_per_share",
+    "dcf_value_per_share",
+    "margin_of_safety_dcf",
+];
+
+#[derive(Debug, Serialize)]
+pub struct VerdictChange {
+    pub left: Option<String>,
+    pub right: Option<String>,
+    pub changed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NumberChange {
+    pub left: Option<f64>,
+    pub right: Option<f64>,
+    pub change: Option<f64>,
+    pub percent_change: Option<f64>,
+}
+
+impl NumberChange {
+    fn new(left: Option<f64>, right: Option<f64>) -> Self {
+        let change = left.zip(right).map(|(left, right)| right - left);
+        let percent_change = left
+            .zip(change)
+            .filter(|(left, _)| *left != 0.0)
+            .map(|(left, change)| change / left.abs());
+
+        Self {
+            left,
+            right,
+            change,
+            percent_change,
+        }
+    }
+}
+
+/// Items only in the right analysis are `added`, items only in the left are `removed`.
+#[derive(Debug, Serialize)]
+pub struct ListChange {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+impl ListChange {
+    fn new(left: &[String], right: &[String]) -> Self {
+        let contains = |items: &[String], item: &str| {
+            let item = normalize(item);
+            items.iter().any(|other| normalize(other) == item)
+        };
+
+        Self {
+            added: right
+                .iter()
+                .filter(|item| !contains(left, item))
+                .cloned()
+                .collect(),
+            removed: left
+                .iter()
+                .filter(|item| !contains(right, item))
+                .cloned()
+                .collect(),
+            unchanged: left
+                .iter()
+                .filter(|item| contains(right, item))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Structured difference between two analyses, reading from `left` to `right`.
+#[derive(Debug, Serialize)]
+pub struct AnalysisDiff {
+    pub same_ticker: bool,
+    pub same_agent: bool,
+    pub days_apart: i64,
+    pub verdict: VerdictChange,
+    pub conviction: NumberChange,
+    pub intrinsic_value_low: NumberChange,
+    pub intrinsic_value_high: NumberChange,
+    pub margin_of_safety: NumberChange,
+    pub key_risks: ListChange,
+    pub moats: ListChange,
+    pub market: BTreeMap<&'static str, NumberChange>,
+}
+
+pub fn diff(left: &Analysis, right: &Analysis) -> AnalysisDiff {
+    let left_output = structured(left);
+    let right_output = structured(right);
+    let field = |output: &Option<StructuredAnalysis>, f: fn(&StructuredAnalysis) -> f64| {
+        output.as_ref().map(f)
+    };
+    let list = |output: &Option<StructuredAnalysis>, f: fn(&StructuredAnalysis) -> &Vec<String>| {
+        output.as_ref().map(f).cloned().unwrap_or_default()
+    };
+
+    let market = MARKET_FIGURES
+        .into_iter()
+        .map(|name| {
+            let figure = |analysis: &Analysis| analysis.input_data["valuation"][name].as_f64();
+            (name, NumberChange::new(figure(left), figure(right)))
+        })
+        .collect();
+
+    AnalysisDiff {
+        same_ticker: left.ticker == right.ticker,
+        same_agent: left.agent_id == right.agent_id,
+        days_apart: (right.created_at - left.created_at).num_days(),
+        verdict: VerdictChange {
+            left: left.verdict.clone(),
+            right: right.verdict.clone(),
+            changed: left.verdict != right.verdict,
+        },
+        conviction: NumberChange::new(
+            field(&left_output, |o| o.conviction as f64),
+            field(&right_output, |o| o.conviction as f64),
+        ),
+        intrinsic_value_low: NumberChange::new(
+            field(&left_output, |o| o.intrinsic_value_low),
+            field(&right_output, |o| o.intrinsic_value_low),
+        ),
+        intrinsic_value_high: NumberChange::new(
+            field(&left_output, |o| o.intrinsic_value_high),
+            field(&right_output, |o| o.intrinsic_value_high),
+        ),
+        margin_of_safety: NumberChange::new(
+            field(&left_output, |o| o.margin_of_safety),
+            field(&right_output, |o| o.margin_of_safety),
+        ),
+        key_risks: ListChange::new(
+            &list(&left_output, |o| &o.key_risks),
+            &list(&right_output, |o| &o.key_risks),
+        ),
+        moats: ListChange::new(
+            &list(&left_output, |o| &o.moats),
+            &list(&right_output, |o| &o.moats),
+        ),
+        market,
+    }
+}
+
+/// The typed structured output of an analysis, if it has a valid one.
+pub fn structured(analysis: &Analysis) -> Option<StructuredAnalysis> {
+    analysis
+        .structured_output
+        .clone()
+        .and_then(|output: Value| serde_json::from_value(output).ok())
+}
+
+fn normalize(item: &str) -> String {
+    item.trim().trim_end_matches('.').to_lowercase()
+}
@@ -0,0 +1,394 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    models::CreatePortfolioTransactionRequest,
+    portfolio::{KIND_BUY, KIND_DIVIDEND, KIND_SELL, TRANSACTION_KINDS},
+};
+
+/// Rows accepted in one upload.
+pub const MAX_ROWS: usize = 10_000;
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("invalid mapping: {0}")]
+    InvalidMapping(String),
+    #[error("no header row with a {0:?} column")]
+    MissingHeader(String),
+    #[error("missing columns: {0}")]
+    MissingColumns(String),
+    #[error("too many rows: at most {MAX_ROWS} can be imported at once")]
+    TooManyRows,
+    #[error("unreadable CSV: {0}")]
+    Csv(#[from] csv::Error),
+}
+
+/// Where a broker's CSV export keeps each field of a transaction. Column names are matched
+/// against the header row case-insensitively; optional columns absent from a file are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnMapping {
+    pub date: String,
+    pub ticker: String,
+    /// Column holding the action, e.g. "Buy" or "YOU BOUGHT APPLE INC (AAPL)".
+    pub action: String,
+    pub quantity: Option<String>,
+    pub price: Option<String>,
+    /// Cash amount, read for dividends.
+    pub amount: Option<String>,
+    /// Columns summed into the fees, e.g. commission and regulatory fees.
+    #[serde(default)]
+    pub fees: Vec<String>,
+    pub external_id: Option<String>,
+    pub notes: Option<String>,
+    /// chrono format of the date column.
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    /// Action prefixes mapped to buy, sell or dividend, matched case-insensitively with the
+    /// longest prefix winning. Rows with other actions (deposits, interest, ...) are skipped.
+    pub actions: BTreeMap<String, String>,
+}
+
+fn default_date_format() -> String {
+    DEFAULT_DATE_FORMAT.to_string()
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+impl ColumnMapping {
+    pub fn validate(&self) -> Result<(), ImportError> {
+        let invalid = |message: &str| Err(ImportError::InvalidMapping(message.to_string()));
+        if [&self.date, &self.ticker, &self.action]
+            .iter()
+            .any(|column| column.trim().is_empty())
+        {
+            return invalid("date, ticker and action columns are required");
+        }
+        if self.actions.is_empty() {
+            return invalid("actions must map at least one action");
+        }
+        if self
+            .actions
+            .values()
+            .any(|kind| !TRANSACTION_KINDS.contains(&kind.as_str()))
+        {
+            return invalid("actions must map to buy, sell or dividend");
+        }
+        if !self.delimiter.is_ascii() || self.delimiter == '"' {
+            return invalid("delimiter must be a single ASCII character");
+        }
+        if self.date_format.trim().is_empty() {
+            return invalid("date_format must not be empty");
+        }
+        Ok(())
+    }
+
+    fn kind_of(&self, action: &str) -> Option<&str> {
+        let action = action.to_lowercase();
+        self.actions
+            .iter()
+            .filter(|(prefix, _)| action.starts_with(&prefix.trim().to_lowercase()))
+            .max_by_key(|(prefix, _)| prefix.trim().len())
+            .map(|(_, kind)| kind.as_str())
+    }
+}
+
+/// Built-in mappings for the default CSV exports of common brokers, by name.
+pub fn presets() -> BTreeMap<String, ColumnMapping> {
+    let columns = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+    let actions = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(action, kind)| (action.to_string(), kind.to_string()))
+            .collect()
+    };
+    let column = |name: &str| Some(name.to_string());
+
+    BTreeMap::from([
+        (
+            "generic".to_string(),
+            ColumnMapping {
+                date: "date".to_string(),
+                ticker: "ticker".to_string(),
+                action: "kind".to_string(),
+                quantity: column("quantity"),
+                price: column("price"),
+                amount: column("amount"),
+                fees: columns(&["fees"]),
+                external_id: column("external_id"),
+                notes: column("notes"),
+                date_format: DEFAULT_DATE_FORMAT.to_string(),
+                delimiter: ',',
+                actions: actions(&[
+                    ("buy", KIND_BUY),
+                    ("sell", KIND_SELL),
+                    ("dividend", KIND_DIVIDEND),
+                ]),
+            },
+        ),
+        (
+            "schwab".to_string(),
+            ColumnMapping {
+                date: "Date".to_string(),
+                ticker: "Symbol".to_string(),
+                action: "Action".to_string(),
+                quantity: column("Quantity"),
+                price: column("Price"),
+                amount: column("Amount"),
+                fees: columns(&["Fees & Comm"]),
+                external_id: None,
+                notes: column("Description"),
+                date_format: "%m/%d/%Y".to_string(),
+                delimiter: ',',
+                actions: actions(&[
+                    ("Buy", KIND_BUY),
+                    ("Reinvest Shares", KIND_BUY),
+                    ("Sell", KIND_SELL),
+                    ("Cash Dividend", KIND_DIVIDEND),
+                    ("Qualified Dividend", KIND_DIVIDEND),
+                    ("Non-Qualified Div", KIND_DIVIDEND),
+                    ("Reinvest Dividend", KIND_DIVIDEND),
+                    ("Special Dividend", KIND_DIVIDEND),
+                ]),
+            },
+        ),
+        (
+            "fidelity".to_string(),
+            ColumnMapping {
+                date: "Run Date".to_string(),
+                ticker: "Symbol".to_string(),
+                action: "Action".to_string(),
+                quantity: column("Quantity"),
+                price: column("Price ($)"),
+                amount: column("Amount ($)"),
+                fees: columns(&["Commission ($)", "Fees ($)"]),
+                external_id: None,
+                notes: column("Description"),
+                date_format: "%m/%d/%Y".to_string(),
+                delimiter: ',',
+                actions: actions(&[
+                    ("YOU BOUGHT", KIND_BUY),
+                    ("REINVESTMENT", KIND_BUY),
+                    ("YOU SOLD", KIND_SELL),
+                    ("DIVIDEND RECEIVED", KIND_DIVIDEND),
+                ]),
+            },
+        ),
+        (
+            "interactive_brokers".to_string(),
+            ColumnMapping {
+                date: "TradeDate".to_string(),
+                ticker: "Symbol".to_string(),
+                action: "Buy/Sell".to_string(),
+                quantity: column("Quantity"),
+                price: column("TradePrice"),
+                amount: None,
+                fees: columns(&["IBCommission"]),
+                external_id: column("TradeID"),
+                notes: None,
+                date_format: "%Y%m%d".to_string(),
+                delimiter: ',',
+                actions: actions(&[("BUY", KIND_BUY), ("SELL", KIND_SELL)]),
+            },
+        ),
+    ])
+}
+
+/// What a CSV row turned into.
+pub enum RowOutcome {
+    Transaction(CreatePortfolioTransactionRequest),
+    /// Not a transaction we import, with the reason.
+    Skipped(String),
+    Invalid(Vec<String>),
+}
+
+pub struct ParsedRow {
+    /// Line of the row in the file, starting at 1.
+    pub line: u64,
+    pub outcome: RowOutcome,
+}
+
+/// Column positions in the header row.
+struct Columns {
+    date: usize,
+    ticker: usize,
+    action: usize,
+    quantity: Option<usize>,
+    price: Option<usize>,
+    amount: Option<usize>,
+    fees: Vec<usize>,
+    external_id: Option<usize>,
+    notes: Option<usize>,
+}
+
+/// Read the rows after the header. Lines before the header row, which broker exports use for
+/// account details, are ignored.
+pub fn parse(text: &str, mapping: &ColumnMapping) -> Result<Vec<ParsedRow>, ImportError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(mapping.delimiter as u8)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.trim_start_matches('\u{feff}').as_bytes());
+    let mut records = reader.records();
+
+    let header = loop {
+        let Some(record) = records.next() else {
+            return Err(ImportError::MissingHeader(mapping.date.clone()));
+        };
+        let record = record?;
+        if record
+            .iter()
+            .any(|field| field.trim().eq_ignore_ascii_case(mapping.date.trim()))
+        {
+            break record;
+        }
+    };
+    let find = |name: &str| {
+        header
+            .iter()
+            .position(|field| field.trim().eq_ignore_ascii_case(name.trim()))
+    };
+    let required: Vec<(&String, Option<usize>)> = [&mapping.date, &mapping.ticker, &mapping.action]
+        .into_iter()
+        .map(|name| (name, find(name)))
+        .collect();
+    let missing: Vec<&str> = required
+        .iter()
+        .filter(|(_, index)| index.is_none())
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let [Some(date), Some(ticker), Some(action)] = [required[0].1, required[1].1, required[2].1]
+    else {
+        return Err(ImportError::MissingColumns(missing.join(", ")));
+    };
+    let columns = Columns {
+        date,
+        ticker,
+        action,
+        quantity: mapping.quantity.as_deref().and_then(find),
+        price: mapping.price.as_deref().and_then(find),
+        amount: mapping.amount.as_deref().and_then(find),
+        fees: mapping.fees.iter().filter_map(|name| find(name)).collect(),
+        external_id: mapping.external_id.as_deref().and_then(find),
+        notes: mapping.notes.as_deref().and_then(find),
+    };
+
+    let mut rows = Vec::new();
+    for record in records {
+        let record = record?;
+        if rows.len() == MAX_ROWS {
+            return Err(ImportError::TooManyRows);
+        }
+        let line = record.position().map_or(0, |position| position.line());
+        rows.push(ParsedRow {
+            line,
+            outcome: parse_row(&record, &columns, mapping),
+        });
+    }
+    Ok(rows)
+}
+
+fn parse_row(record: &csv::StringRecord, columns: &Columns, mapping: &ColumnMapping) -> RowOutcome {
+    let cell = |index: usize| record.get(index).map(str::trim).unwrap_or_default();
+    let optional_cell = |index: Option<usize>| {
+        index
+            .map(cell)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+
+    let action = cell(columns.action);
+    if action.is_empty() {
+        return RowOutcome::Skipped("no action".to_string());
+    }
+    let Some(kind) = mapping.kind_of(action) else {
+        return RowOutcome::Skipped(format!("action {:?} is not imported", action));
+    };
+
+    let mut errors = Vec::new();
+    let mut number = |index: Option<usize>, field: &str| {
+        parse_number(index.map(cell).unwrap_or_default())
+            .map_err(|e| errors.push(format!("{}: {}", field, e)))
+            .ok()
+            .flatten()
+            .map(f64::abs)
+    };
+    let quantity = number(columns.quantity, "quantity");
+    let price = number(columns.price, "price");
+    let amount = number(columns.amount, "amount");
+    let fees = columns
+        .fees
+        .iter()
+        .filter_map(|index| number(Some(*index), "fees"))
+        .fold(0.0, |total, fee| total + fee);
+
+    let trade_date = parse_date(cell(columns.date), &mapping.date_format);
+    if trade_date.is_none() {
+        errors.push(format!(
+            "date: {:?} does not match {}",
+            cell(columns.date),
+            mapping.date_format
+        ));
+    }
+    let ticker = cell(columns.ticker);
+    if ticker.is_empty() {
+        errors.push("ticker is empty".to_string());
+    }
+
+    match trade_date {
+        Some(trade_date) if errors.is_empty() => {
+            RowOutcome::Transaction(CreatePortfolioTransactionRequest {
+                ticker: ticker.to_string(),
+                kind: kind.to_string(),
+                trade_date,
+                quantity,
+                price,
+                amount,
+                fees: Some(fees),
+                notes: optional_cell(columns.notes),
+                external_id: optional_cell(columns.external_id),
+            })
+        }
+        _ => RowOutcome::Invalid(errors),
+    }
+}
+
+/// Dates like "08/01/2025 as of 07/31/2025" are read by their first word.
+fn parse_date(value: &str, format: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value, format).ok().or_else(|| {
+        value
+            .split_whitespace()
+            .next()
+            .and_then(|first| NaiveDate::parse_from_str(first, format).ok())
+    })
+}
+
+/// Numbers with currency symbols, thousands separators or accounting parentheses.
+/// Blank cells and "--" are `None`.
+fn parse_number(value: &str) -> Result<Option<f64>, String> {
+    let cleaned: String = value
+        .chars()
+        .filter(|c| !matches!(c, '$' | ',' | ' '))
+        .collect();
+    if cleaned.is_empty() || cleaned == "--" {
+        return Ok(None);
+    }
+    let (negative, digits) = match cleaned.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+        Some(digits) => (true, digits),
+        None => (false, cleaned.as_str()),
+    };
+    let number: f64 = digits
+        .parse()
+        .map_err(|_| format!("{:?} is not a number", value))?;
+    if !number.is_finite() {
+        return Err(format!("{:?} is not a number", value));
+    }
+    Ok(Some(if negative { -number } else { number }))
+}
@@ -1,4 +1,7 @@
 use std::env;
+use std::path::PathBuf;
+
+use crate::permissions::DEFAULT_ROLE;
 
 /// Initialize environment variables from a `.env` file if it exists.
 pub fn init_env() {
@@ -9,17 +12,196 @@ pub fn init_env() {
 
 /// Get the development environment flag. True if the application is running in development mode.
 pub fn is_development() -> bool {
-    env::var("RUST_LOG").map_or(false, |log| log.contains("debug"))
+    env::var("RUST_LOG").is_ok_and(|log| log.contains("debug"))
+}
+
+/// Get the JWT secret from environment variables. Only used when no JWT private key is set.
+pub fn get_jwt_secret() -> Option<String> {
+    env::var("JWT_SECRET").ok().filter(|secret| !secret.is_empty())
 }
 
-/// Get the JWT secret from environment variables.
-pub fn get_jwt_secret() -> String {
-    env::var("JWT_SECRET").expect("JWT_SECRET must be set")
+/// Get the path of the PEM private key that signs JWTs from environment variables.
+pub fn get_jwt_private_key_path() -> Option<PathBuf> {
+    env::var("JWT_PRIVATE_KEY_PATH")
+        .ok()
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
 }
 
-/// Get the JWT expiration time in days from environment variables.
-pub fn get_jwt_expiration_days() -> i64 {
-    env::var("JWT_EXPIRATION_DAYS")
+/// Get the comma-separated paths of additional PEM public keys that verify JWTs from environment variables.
+pub fn get_jwt_public_key_paths() -> Vec<PathBuf> {
+    env::var("JWT_PUBLIC_KEY_PATHS")
+        .map(|val| {
+            val.split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Get the access token (JWT) expiration time in minutes from environment variables.
+pub fn get_jwt_expiration_minutes() -> i64 {
+    env::var("JWT_EXPIRATION_MINUTES")
+        .map(|val| val.parse().unwrap_or(15)) // Default to 15 minutes if not set
+        .unwrap_or(15)
+}
+
+/// Get the refresh token expiration time in days from environment variables.
+pub fn get_refresh_token_expiration_days() -> i64 {
+    env::var("REFRESH_TOKEN_EXPIRATION_DAYS")
         .map(|val| val.parse().unwrap_or(15)) // Default to 15 days if not set
         .unwrap_or(15)
 }
+
+/// Get whether users with admin access must enable two-factor authentication before using admin
+/// endpoints.
+pub fn is_mfa_required_for_admins() -> bool {
+    env::var("MFA_REQUIRED_FOR_ADMINS").is_ok_and(|val| val == "true" || val == "1")
+}
+
+/// Get whether the server runs behind a proxy whose `X-Forwarded-For` header gives the client
+/// address. Off unless set, since clients could otherwise forge the address they are recorded with.
+pub fn is_behind_trusted_proxy() -> bool {
+    env::var("TRUST_PROXY_HEADERS").is_ok_and(|val| val == "true" || val == "1")
+}
+
+/// Get the OpenID Connect issuer URL from environment variables. Single sign-on is disabled when unset.
+pub fn get_oidc_issuer() -> Option<String> {
+    env::var("OIDC_ISSUER")
+        .ok()
+        .filter(|issuer| !issuer.is_empty())
+        .map(|issuer| issuer.trim_end_matches('/').to_string())
+}
+
+/// Get the client ID registered with the OpenID Connect provider from environment variables.
+pub fn get_oidc_client_id() -> Option<String> {
+    env::var("OIDC_CLIENT_ID").ok().filter(|id| !id.is_empty())
+}
+
+/// Get the OpenID Connect client secret from environment variables. Public clients rely on PKCE alone.
+pub fn get_oidc_client_secret() -> Option<String> {
+    env::var("OIDC_CLIENT_SECRET").ok().filter(|secret| !secret.is_empty())
+}
+
+/// Get the URL the identity provider redirects back to from environment variables.
+pub fn get_oidc_redirect_uri() -> String {
+    env::var("OIDC_REDIRECT_URI")
+        .unwrap_or_else(|_| format!("{}/api/auth/oidc/callback", get_app_base_url()))
+}
+
+/// Get the space-separated scopes requested from the identity provider from environment variables.
+pub fn get_oidc_scopes() -> String {
+    env::var("OIDC_SCOPES").unwrap_or_else(|_| "openid email profile".to_string())
+}
+
+/// Get the ID token claim that lists the user's groups from environment variables.
+pub fn get_oidc_groups_claim() -> String {
+    env::var("OIDC_GROUPS_CLAIM").unwrap_or_else(|_| "groups".to_string())
+}
+
+/// Get the `group=role` pairs that map identity provider groups to roles from environment
+/// variables, comma-separated and in priority order.
+pub fn get_oidc_role_mapping() -> Vec<(String, String)> {
+    env::var("OIDC_ROLE_MAPPING")
+        .map(|mapping| {
+            mapping
+                .split(',')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(group, role)| (group.trim().to_string(), role.trim().to_string()))
+                .filter(|(group, role)| !group.is_empty() && !role.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Get the role of single sign-on users in none of the mapped groups from environment variables.
+/// Set it empty to refuse them.
+pub fn get_oidc_default_role() -> Option<String> {
+    match env::var("OIDC_DEFAULT_ROLE") {
+        Ok(role) if role.is_empty() => None,
+        Ok(role) => Some(role),
+        Err(_) => Some(DEFAULT_ROLE.to_string()), // Default to the user role if not set
+    }
+}
+
+/// Get the number of background analysis workers from environment variables.
+pub fn get_analysis_worker_count() -> usize {
+    env::var("ANALYSIS_WORKERS")
+        .map(|val| val.parse().unwrap_or(2)) // Default to 2 workers if not set
+        .unwrap_or(2)
+}
+
+/// Get the MCP server executable from environment variables.
+/// Defaults to the `mcp-server` binary next to the running executable.
+pub fn get_mcp_server_command() -> PathBuf {
+    env::var("MCP_SERVER_COMMAND")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(|dir| dir.join("mcp-server")))
+                .unwrap_or_else(|| PathBuf::from("mcp-server"))
+        })
+}
+
+/// Get the timeout for a single MCP request in seconds from environment variables.
+pub fn get_mcp_call_timeout_secs() -> u64 {
+    env::var("MCP_CALL_TIMEOUT_SECS")
+        .map(|val| val.parse().unwrap_or(30)) // Default to 30 seconds if not set
+        .unwrap_or(30)
+}
+
+/// Get how email is sent from environment variables: `smtp`, `file`, `log` or `disabled`.
+/// Defaults to `smtp` when an SMTP host is set; email is disabled otherwise.
+pub fn get_mail_transport() -> Option<String> {
+    env::var("MAIL_TRANSPORT")
+        .ok()
+        .filter(|transport| !transport.is_empty())
+        .or_else(|| get_smtp_host().map(|_| "smtp".to_string()))
+}
+
+/// Get the directory the `file` mail transport writes emails to from environment variables.
+pub fn get_mail_file_dir() -> PathBuf {
+    env::var("MAIL_FILE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("mail")) // Default to ./mail if not set
+}
+
+/// Get the public base URL of the application, used for links in emails, from environment variables.
+pub fn get_app_base_url() -> String {
+    env::var("APP_BASE_URL")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| "http://localhost:3000".to_string())
+}
+
+/// Get the SMTP server host from environment variables.
+pub fn get_smtp_host() -> Option<String> {
+    env::var("SMTP_HOST").ok().filter(|host| !host.is_empty())
+}
+
+/// Get the SMTP server port from environment variables. Defaults to the port of the security mode.
+pub fn get_smtp_port() -> Option<u16> {
+    env::var("SMTP_PORT").ok().and_then(|val| val.parse().ok())
+}
+
+/// Get the SMTP connection security from environment variables: `starttls`, `tls` or `none`.
+pub fn get_smtp_security() -> String {
+    env::var("SMTP_SECURITY").unwrap_or_else(|_| "starttls".to_string())
+}
+
+/// Get the SMTP username from environment variables.
+pub fn get_smtp_username() -> Option<String> {
+    env::var("SMTP_USERNAME").ok()
+}
+
+/// Get the SMTP password from environment variables.
+pub fn get_smtp_password() -> Option<String> {
+    env::var("SMTP_PASSWORD").ok()
+}
+
+/// Get the sender address of all emails from environment variables.
+pub fn get_smtp_from() -> String {
+    env::var("SMTP_FROM").unwrap_or_else(|_| "Stock Analyzer <noreply@localhost>".to_string())
+}
@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Extension, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    account_tokens::{self, PURPOSE_EMAIL_VERIFICATION, PURPOSE_PASSWORD_RESET},
+    api_tokens,
+    auth::{hash_password, Claims},
+    database::DbPool,
+    mailer::{self, Mailer},
+    models::{ForgotPasswordRequest, ResetPasswordRequest, User, UserResponse, VerifyEmailRequest},
+    schema::users,
+    sessions,
+};
+
+/// Confirm the email address a verification token was sent to. Fails with 400 if the token is
+/// invalid or the user changed their email address since.
+pub async fn verify_email(
+    State(pool): State<DbPool>,
+    Json(request): Json<VerifyEmailRequest>,
+) -> Result<Json<UserResponse>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = conn
+        .transaction(|conn| {
+            let Some(token) =
+                account_tokens::consume(conn, PURPOSE_EMAIL_VERIFICATION, &request.token)?
+            else {
+                return Ok(None);
+            };
+
+            let user: User = users::table
+                .find(token.user_id)
+                .select(User::as_select())
+                .first(conn)?;
+            if user.email != token.email {
+                return Ok(None);
+            }
+            if user.email_verified_at.is_some() {
+                return Ok(Some(user));
+            }
+
+            diesel::update(users::table.find(user.id))
+                .set(users::email_verified_at.eq(Utc::now().naive_utc()))
+                .returning(User::as_select())
+                .get_result(conn)
+                .map(Some)
+        })
+        .map_err(|_: diesel::result::Error| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    Ok(Json(user.into()))
+}
+
+/// Send the caller a new verification email. Fails with 409 if their address is verified.
+pub async fn resend_verification_email(
+    Extension(claims): Extension<Claims>,
+    Extension(mailer): Extension<Arc<dyn Mailer>>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user: User = users::table
+        .find(user_id)
+        .select(User::as_select())
+        .first(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if user.email_verified_at.is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    send_verification_email(&mut conn, mailer, &user)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Email a password reset link to the active account with this address. Always answers 202 so
+/// the response does not reveal which addresses have accounts.
+pub async fn forgot_password(
+    Extension(mailer): Extension<Arc<dyn Mailer>>,
+    State(pool): State<DbPool>,
+    Json(request): Json<ForgotPasswordRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = users::table
+        .filter(users::email.eq(request.email.trim()))
+        .filter(users::is_active.eq(true))
+        .select(User::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(user) = user {
+        let token = account_tokens::issue(&mut conn, &user, PURPOSE_PASSWORD_RESET)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        mailer::send_in_background(mailer, account_tokens::password_reset_email(&user, &token));
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Set a new password with a reset token and sign the user out everywhere, revoking their API
+/// tokens too. Fails with 400 if the token is invalid or the password is empty.
+pub async fn reset_password(
+    State(pool): State<DbPool>,
+    Json(request): Json<ResetPasswordRequest>,
+) -> Result<StatusCode, StatusCode> {
+    if request.new_password.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let password_hash =
+        hash_password(&request.new_password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let revoked = conn
+        .transaction(|conn| {
+            let Some(token) =
+                account_tokens::consume(conn, PURPOSE_PASSWORD_RESET, &request.token)?
+            else {
+                return Ok(None);
+            };
+
+            let user: User = users::table
+                .find(token.user_id)
+                .select(User::as_select())
+                .first(conn)?;
+            if !user.is_active {
+                return Ok(None);
+            }
+
+            let now = Utc::now().naive_utc();
+            diesel::update(users::table.find(user.id))
+                .set((
+                    users::password_hash.eq(&password_hash),
+                    users::updated_at.eq(now),
+                ))
+                .execute(conn)?;
+            // Following the emailed link proves the address belongs to the user.
+            if user.email_verified_at.is_none() && user.email == token.email {
+                diesel::update(users::table.find(user.id))
+                    .set(users::email_verified_at.eq(now))
+                    .execute(conn)?;
+            }
+
+            let revoked = sessions::revoke_all(conn, user.id, None)?;
+            api_tokens::revoke_all(conn, user.id)?;
+            Ok(Some(revoked))
+        })
+        .map_err(|_: diesel::result::Error| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    sessions::forget(&revoked);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Issue a verification token for the user's current address and email it in the background.
+pub fn send_verification_email(
+    conn: &mut PgConnection,
+    mailer: Arc<dyn Mailer>,
+    user: &User,
+) -> QueryResult<()> {
+    let token = account_tokens::issue(conn, user, PURPOSE_EMAIL_VERIFICATION)?;
+    mailer::send_in_background(mailer, account_tokens::verification_email(user, &token));
+    Ok(())
+}
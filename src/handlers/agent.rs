@@ -0,0 +1,26 @@
+use axum::{extract::State, http::StatusCode, response::Json};
+use diesel::prelude::*;
+
+use crate::{
+    committee::AGENT_KIND_ANALYST,
+    database::DbPool,
+    models::{Agent, AgentResponse},
+    schema::agents,
+};
+
+pub async fn list_agents(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<AgentResponse>>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let agents_list = agents::table
+        .filter(agents::is_active.eq(true))
+        .filter(agents::kind.eq(AGENT_KIND_ANALYST))
+        .order(agents::name.asc())
+        .select(Agent::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let response: Vec<AgentResponse> = agents_list.into_iter().map(|a| a.into()).collect();
+    Ok(Json(response))
+}
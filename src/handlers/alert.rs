@@ -0,0 +1,214 @@
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use diesel::dsl::now;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    alerts::{
+        valid_threshold, CONDITIONS, CONDITION_BELOW_INTRINSIC_VALUE, DEFAULT_COOLDOWN_MINUTES,
+        DEFAULT_INTRINSIC_VALUE_AGENT, MAX_COOLDOWN_MINUTES,
+    },
+    auth::Claims,
+    database::DbPool,
+    models::{
+        AlertRule, AlertTrigger, AlertTriggerQuery, CreateAlertRuleRequest, NewAlertRule,
+        UpdateAlertRuleRequest,
+    },
+    schema::{agents, alert_rules, alert_triggers},
+    stocks::normalize_ticker,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+pub async fn list_alert_rules(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<AlertRule>>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let rules = alert_rules::table
+        .filter(alert_rules::user_id.eq(user_id))
+        .order(alert_rules::created_at.asc())
+        .select(AlertRule::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(rules))
+}
+
+/// Rules are checked whenever prices for their ticker are ingested.
+pub async fn create_alert_rule(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Json(request): Json<CreateAlertRuleRequest>,
+) -> Result<(StatusCode, Json<AlertRule>), StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let ticker = normalize_ticker(&request.ticker).ok_or(StatusCode::BAD_REQUEST)?;
+    if !CONDITIONS.contains(&request.condition.as_str())
+        || !valid_threshold(&request.condition, request.threshold)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let cooldown_minutes = validate_cooldown(request.cooldown_minutes)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let agent_id = if request.condition == CONDITION_BELOW_INTRINSIC_VALUE {
+        let slug = request
+            .agent_slug
+            .as_deref()
+            .unwrap_or(DEFAULT_INTRINSIC_VALUE_AGENT);
+        let agent_id: Uuid = agents::table
+            .filter(agents::slug.eq(slug))
+            .filter(agents::is_active.eq(true))
+            .select(agents::id)
+            .first(&mut conn)
+            .optional()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        Some(agent_id)
+    } else {
+        None
+    };
+
+    let rule = diesel::insert_into(alert_rules::table)
+        .values(&NewAlertRule {
+            user_id,
+            ticker,
+            condition: request.condition,
+            threshold: request.threshold,
+            agent_id,
+            cooldown_minutes,
+        })
+        .returning(AlertRule::as_select())
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+pub async fn get_alert_rule(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(rule_id): Path<Uuid>,
+) -> Result<Json<AlertRule>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(find_rule(&mut conn, user_id, rule_id)?))
+}
+
+/// Change a rule's threshold or cooldown, or pause and resume it.
+pub async fn update_alert_rule(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(rule_id): Path<Uuid>,
+    Json(request): Json<UpdateAlertRuleRequest>,
+) -> Result<Json<AlertRule>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if request.cooldown_minutes.is_some() {
+        validate_cooldown(request.cooldown_minutes)?;
+    }
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let rule = find_rule(&mut conn, user_id, rule_id)?;
+    if request.threshold.is_some() && !valid_threshold(&rule.condition, request.threshold) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let rule = diesel::update(alert_rules::table.find(rule.id))
+        .set((&request, alert_rules::updated_at.eq(now)))
+        .returning(AlertRule::as_select())
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(rule))
+}
+
+pub async fn delete_alert_rule(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(rule_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let deleted_count = diesel::delete(
+        alert_rules::table
+            .filter(alert_rules::id.eq(rule_id))
+            .filter(alert_rules::user_id.eq(user_id)),
+    )
+    .execute(&mut conn)
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted_count == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Trigger history, newest first, optionally for one rule or ticker.
+pub async fn list_alert_triggers(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Query(query): Query<AlertTriggerQuery>,
+) -> Result<Json<Vec<AlertTrigger>>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut statement = alert_triggers::table
+        .filter(alert_triggers::user_id.eq(user_id))
+        .into_boxed();
+
+    if let Some(rule_id) = query.rule_id {
+        statement = statement.filter(alert_triggers::rule_id.eq(rule_id));
+    }
+    if let Some(ticker) = &query.ticker {
+        let ticker = normalize_ticker(ticker).ok_or(StatusCode::BAD_REQUEST)?;
+        statement = statement.filter(alert_triggers::ticker.eq(ticker));
+    }
+
+    let triggers = statement
+        .order(alert_triggers::triggered_at.desc())
+        .limit(
+            query
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        )
+        .offset(query.offset.unwrap_or(0).max(0))
+        .select(AlertTrigger::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(triggers))
+}
+
+fn find_rule(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    rule_id: Uuid,
+) -> Result<AlertRule, StatusCode> {
+    alert_rules::table
+        .filter(alert_rules::id.eq(rule_id))
+        .filter(alert_rules::user_id.eq(user_id))
+        .select(AlertRule::as_select())
+        .first(conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+fn validate_cooldown(cooldown_minutes: Option<i32>) -> Result<i32, StatusCode> {
+    let cooldown_minutes = cooldown_minutes.unwrap_or(DEFAULT_COOLDOWN_MINUTES);
+    if !(0..=MAX_COOLDOWN_MINUTES).contains(&cooldown_minutes) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(cooldown_minutes)
+}
@@ -0,0 +1,280 @@
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::NaiveTime;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    auth::Claims,
+    committee::AGENT_KIND_ANALYST,
+    comparison,
+    database::DbPool,
+    jobs::{KIND_ANALYSIS, STATUS_CANCELLED, STATUS_QUEUED, STATUS_RUNNING},
+    models::{
+        Agent, Analysis, AnalysisComparisonResponse, AnalysisJob, AnalysisJobResponse,
+        AnalysisListQuery, AnalysisResponse, AnalysisSummary, CompareAnalysesQuery,
+        CreateAnalysisRequest, LlmProvider, NewAnalysisJob,
+    },
+    schema::{agents, analyses, analysis_jobs, llm_providers, llm_usage},
+    stocks::normalize_ticker,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+pub async fn create_analysis(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Json(request): Json<CreateAnalysisRequest>,
+) -> Result<(StatusCode, Json<AnalysisJobResponse>), StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let ticker = normalize_ticker(&request.ticker).ok_or(StatusCode::BAD_REQUEST)?;
+
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let agent = find_active_agent(&mut conn, &request.agent_slug)?;
+    let provider = find_active_provider(&mut conn, request.provider_id)?;
+
+    let new_job = NewAnalysisJob {
+        user_id,
+        agent_id: agent.id,
+        provider_id: provider.id,
+        ticker,
+        kind: KIND_ANALYSIS.to_string(),
+        use_tools: request.use_tools.unwrap_or(false),
+    };
+
+    let job: AnalysisJob = diesel::insert_into(analysis_jobs::table)
+        .values(&new_job)
+        .returning(AnalysisJob::as_select())
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(AnalysisJobResponse::new(job, None)),
+    ))
+}
+
+pub async fn get_analysis(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(analysis_id): Path<Uuid>,
+) -> Result<Json<AnalysisJobResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let job = analysis_jobs::table
+        .filter(analysis_jobs::id.eq(analysis_id))
+        .filter(analysis_jobs::user_id.eq(user_id))
+        .filter(analysis_jobs::kind.eq(KIND_ANALYSIS))
+        .select(AnalysisJob::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Analyses share their id with the job that produced them
+    let analysis = analyses::table
+        .find(job.id)
+        .left_join(llm_usage::table)
+        .select((Analysis::as_select(), llm_usage::tokens_used.nullable()))
+        .first::<(Analysis, Option<i32>)>(&mut conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(|(analysis, tokens_used)| AnalysisResponse::new(analysis, tokens_used));
+
+    Ok(Json(AnalysisJobResponse::new(job, analysis)))
+}
+
+pub async fn cancel_analysis(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(analysis_id): Path<Uuid>,
+) -> Result<Json<AnalysisJobResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let job = cancel_job(&mut conn, user_id, analysis_id, KIND_ANALYSIS)?;
+    Ok(Json(AnalysisJobResponse::new(job, None)))
+}
+
+/// Cancel one of the user's queued or running jobs of the given kind.
+///
+/// Returns `CONFLICT` when the job already finished and `NOT_FOUND` when it does not exist.
+pub(crate) fn cancel_job(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    job_id: Uuid,
+    kind: &str,
+) -> Result<AnalysisJob, StatusCode> {
+    let own_job = analysis_jobs::table
+        .filter(analysis_jobs::id.eq(job_id))
+        .filter(analysis_jobs::user_id.eq(user_id))
+        .filter(analysis_jobs::kind.eq(kind));
+
+    let cancelled = diesel::update(
+        own_job.filter(analysis_jobs::status.eq_any([STATUS_QUEUED, STATUS_RUNNING])),
+    )
+    .set((
+        analysis_jobs::status.eq(STATUS_CANCELLED),
+        analysis_jobs::finished_at.eq(diesel::dsl::now),
+        analysis_jobs::updated_at.eq(diesel::dsl::now),
+    ))
+    .returning(AnalysisJob::as_select())
+    .get_result(conn)
+    .optional()
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match cancelled {
+        Some(job) => Ok(job),
+        None => {
+            // Distinguish a finished job from one that does not exist
+            let exists: i64 = own_job
+                .count()
+                .get_result(conn)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            Err(if exists > 0 {
+                StatusCode::CONFLICT
+            } else {
+                StatusCode::NOT_FOUND
+            })
+        }
+    }
+}
+
+pub async fn list_analyses(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Query(query): Query<AnalysisListQuery>,
+) -> Result<Json<Vec<AnalysisSummary>>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut statement = analyses::table
+        .inner_join(agents::table)
+        .filter(analyses::user_id.eq(user_id))
+        .into_boxed();
+
+    if let Some(ticker) = &query.ticker {
+        let ticker = normalize_ticker(ticker).ok_or(StatusCode::BAD_REQUEST)?;
+        statement = statement.filter(analyses::ticker.eq(ticker));
+    }
+    if let Some(agent_slug) = &query.agent {
+        statement = statement.filter(agents::slug.eq(agent_slug));
+    }
+    if let Some(from) = query.from {
+        statement = statement.filter(analyses::created_at.ge(from.and_time(NaiveTime::MIN)));
+    }
+    if let Some(to) = query.to {
+        let end = to.succ_opt().ok_or(StatusCode::BAD_REQUEST)?;
+        statement = statement.filter(analyses::created_at.lt(end.and_time(NaiveTime::MIN)));
+    }
+
+    let rows = statement
+        .order(analyses::created_at.desc())
+        .limit(
+            query
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        )
+        .offset(query.offset.unwrap_or(0).max(0))
+        .select((Analysis::as_select(), Agent::as_select()))
+        .load::<(Analysis, Agent)>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let response: Vec<AnalysisSummary> = rows
+        .iter()
+        .map(|(analysis, agent)| summarize(analysis, agent))
+        .collect();
+    Ok(Json(response))
+}
+
+pub async fn compare_analyses(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Query(query): Query<CompareAnalysesQuery>,
+) -> Result<Json<AnalysisComparisonResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut load = |analysis_id: Uuid| {
+        analyses::table
+            .inner_join(agents::table)
+            .filter(analyses::id.eq(analysis_id))
+            .filter(analyses::user_id.eq(user_id))
+            .select((Analysis::as_select(), Agent::as_select()))
+            .first::<(Analysis, Agent)>(&mut conn)
+            .optional()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::NOT_FOUND)
+    };
+
+    let (left, left_agent) = load(query.left)?;
+    let (right, right_agent) = load(query.right)?;
+
+    Ok(Json(AnalysisComparisonResponse {
+        diff: comparison::diff(&left, &right),
+        left: summarize(&left, &left_agent),
+        right: summarize(&right, &right_agent),
+    }))
+}
+
+fn summarize(analysis: &Analysis, agent: &Agent) -> AnalysisSummary {
+    let output = comparison::structured(analysis);
+
+    AnalysisSummary {
+        id: analysis.id,
+        ticker: analysis.ticker.clone(),
+        agent_slug: agent.slug.clone(),
+        agent_name: agent.name.clone(),
+        provider_id: analysis.provider_id,
+        verdict: analysis.verdict.clone(),
+        conviction: output.as_ref().map(|o| o.conviction),
+        intrinsic_value_low: output.as_ref().map(|o| o.intrinsic_value_low),
+        intrinsic_value_high: output.as_ref().map(|o| o.intrinsic_value_high),
+        margin_of_safety: output.as_ref().map(|o| o.margin_of_safety),
+        created_at: analysis.created_at,
+    }
+}
+
+pub(crate) fn find_active_agent(conn: &mut PgConnection, slug: &str) -> Result<Agent, StatusCode> {
+    agents::table
+        .filter(agents::slug.eq(slug))
+        .filter(agents::is_active.eq(true))
+        .filter(agents::kind.eq(AGENT_KIND_ANALYST))
+        .select(Agent::as_select())
+        .first(conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Use the requested provider, or the oldest active one when none is given.
+pub(crate) fn find_active_provider(
+    conn: &mut PgConnection,
+    provider_id: Option<Uuid>,
+) -> Result<LlmProvider, StatusCode> {
+    let query = llm_providers::table
+        .filter(llm_providers::is_active.eq(true))
+        .select(LlmProvider::as_select());
+
+    match provider_id {
+        Some(provider_id) => query
+            .filter(llm_providers::id.eq(provider_id))
+            .first(conn)
+            .optional()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::NOT_FOUND),
+        None => query
+            .order(llm_providers::created_at.asc())
+            .first(conn)
+            .optional()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::SERVICE_UNAVAILABLE),
+    }
+}
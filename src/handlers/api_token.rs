@@ -0,0 +1,129 @@
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use crate::{
+    api_tokens::{self, DEFAULT_TTL_DAYS, MAX_TTL_DAYS, SCOPES, SCOPE_ADMIN},
+    auth::Claims,
+    database::DbPool,
+    handlers::{
+        session::{ensure_can_manage, ensure_user_exists, find_user},
+        validate_name,
+    },
+    models::{ApiTokenResponse, CreateApiTokenRequest, CreatedApiTokenResponse},
+    permissions,
+};
+
+pub async fn list_api_tokens(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<ApiTokenResponse>>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let tokens =
+        api_tokens::list(&mut conn, user_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(
+        tokens.into_iter().map(ApiTokenResponse::from).collect(),
+    ))
+}
+
+/// Create an API token. The token is only ever returned by this request. The `admin` scope is
+/// only granted to roles with admin access.
+pub async fn create_api_token(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Json(request): Json<CreateApiTokenRequest>,
+) -> Result<(StatusCode, Json<CreatedApiTokenResponse>), StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let name = validate_name(&request.name)?;
+    let mut scopes = Vec::new();
+    for scope in request.scopes {
+        if !SCOPES.contains(&scope.as_str()) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    if scopes.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let days = request.expires_in_days.unwrap_or(DEFAULT_TTL_DAYS);
+    if !(1..=MAX_TTL_DAYS).contains(&days) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let expires_at = (Utc::now() + Duration::days(days)).naive_utc();
+
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if scopes.iter().any(|scope| scope == SCOPE_ADMIN) {
+        let granted = permissions::load(&mut conn, &claims.role)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !permissions::has_admin_access(&granted) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+    let (api_token, token) = api_tokens::create(&mut conn, user_id, name, scopes, expires_at)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedApiTokenResponse {
+            token,
+            api_token: api_token.into(),
+        }),
+    ))
+}
+
+pub async fn revoke_api_token(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(token_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let revoked = api_tokens::revoke(&mut conn, user_id, token_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !revoked {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_user_api_tokens(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Vec<ApiTokenResponse>>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    ensure_user_exists(&mut conn, user_id)?;
+    let tokens =
+        api_tokens::list(&mut conn, user_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(
+        tokens.into_iter().map(ApiTokenResponse::from).collect(),
+    ))
+}
+
+pub async fn revoke_user_api_token(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path((user_id, token_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = find_user(&mut conn, user_id)?;
+    ensure_can_manage(&mut conn, &claims, &user.role)?;
+
+    let revoked = api_tokens::revoke(&mut conn, user_id, token_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !revoked {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
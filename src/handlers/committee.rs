@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    auth::Claims,
+    committee::{AGENT_KIND_MODERATOR, MAX_MEMBERS, MIN_MEMBERS},
+    database::DbPool,
+    handlers::analysis::{cancel_job, find_active_agent, find_active_provider},
+    jobs::KIND_COMMITTEE,
+    models::{
+        Agent, AnalysisJob, Committee, CommitteeMember, CommitteeMemberResponse, CommitteeResponse,
+        CreateCommitteeRequest, NewAnalysisJob, NewCommittee, NewCommitteeMember,
+    },
+    schema::{agents, analyses, analysis_jobs, committee_members, committees},
+    stocks::normalize_ticker,
+};
+
+pub async fn create_committee(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Json(request): Json<CreateCommitteeRequest>,
+) -> Result<(StatusCode, Json<CommitteeResponse>), StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let ticker = normalize_ticker(&request.ticker).ok_or(StatusCode::BAD_REQUEST)?;
+
+    if !(MIN_MEMBERS..=MAX_MEMBERS).contains(&request.members.len()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let distinct: HashSet<&str> = request
+        .members
+        .iter()
+        .map(|member| member.agent_slug.as_str())
+        .collect();
+    if distinct.len() != request.members.len() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut members = Vec::with_capacity(request.members.len());
+    for member in &request.members {
+        let weight = member.weight.unwrap_or(1.0);
+        if !weight.is_finite() || weight <= 0.0 {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        members.push((find_active_agent(&mut conn, &member.agent_slug)?, weight));
+    }
+
+    let provider = find_active_provider(&mut conn, request.provider_id)?;
+    let moderator = agents::table
+        .filter(agents::kind.eq(AGENT_KIND_MODERATOR))
+        .filter(agents::is_active.eq(true))
+        .order(agents::created_at.asc())
+        .select(Agent::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let (job, committee) = conn
+        .transaction(|conn| {
+            // The job runs the moderator; members are listed on the committee
+            let job: AnalysisJob = diesel::insert_into(analysis_jobs::table)
+                .values(&NewAnalysisJob {
+                    user_id,
+                    agent_id: moderator.id,
+                    provider_id: provider.id,
+                    ticker: ticker.clone(),
+                    kind: KIND_COMMITTEE.to_string(),
+                    use_tools: request.use_tools.unwrap_or(false),
+                })
+                .returning(AnalysisJob::as_select())
+                .get_result(conn)?;
+
+            let committee: Committee = diesel::insert_into(committees::table)
+                .values(&NewCommittee {
+                    id: job.id,
+                    user_id,
+                    provider_id: provider.id,
+                    ticker: ticker.clone(),
+                })
+                .returning(Committee::as_select())
+                .get_result(conn)?;
+
+            let new_members: Vec<NewCommitteeMember> = members
+                .iter()
+                .map(|(agent, weight)| NewCommitteeMember {
+                    committee_id: committee.id,
+                    agent_id: agent.id,
+                    weight: *weight,
+                })
+                .collect();
+            diesel::insert_into(committee_members::table)
+                .values(&new_members)
+                .execute(conn)?;
+
+            diesel::QueryResult::Ok((job, committee))
+        })
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let members = members
+        .into_iter()
+        .map(|(agent, weight)| CommitteeMemberResponse {
+            agent_slug: agent.slug,
+            agent_name: agent.name,
+            weight,
+            analysis_id: None,
+            verdict: None,
+            error: None,
+        })
+        .collect();
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(CommitteeResponse::new(job, committee, members)),
+    ))
+}
+
+pub async fn get_committee(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(committee_id): Path<Uuid>,
+) -> Result<Json<CommitteeResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (committee, job) = committees::table
+        .inner_join(analysis_jobs::table)
+        .filter(committees::id.eq(committee_id))
+        .filter(committees::user_id.eq(user_id))
+        .select((Committee::as_select(), AnalysisJob::as_select()))
+        .first::<(Committee, AnalysisJob)>(&mut conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let members = load_members(&mut conn, committee.id)?;
+    Ok(Json(CommitteeResponse::new(job, committee, members)))
+}
+
+pub async fn cancel_committee(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(committee_id): Path<Uuid>,
+) -> Result<Json<CommitteeResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let job = cancel_job(&mut conn, user_id, committee_id, KIND_COMMITTEE)?;
+    let committee = committees::table
+        .find(job.id)
+        .select(Committee::as_select())
+        .first(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let members = load_members(&mut conn, committee.id)?;
+    Ok(Json(CommitteeResponse::new(job, committee, members)))
+}
+
+fn load_members(
+    conn: &mut PgConnection,
+    committee_id: Uuid,
+) -> Result<Vec<CommitteeMemberResponse>, StatusCode> {
+    let rows = committee_members::table
+        .inner_join(agents::table)
+        .left_join(analyses::table)
+        .filter(committee_members::committee_id.eq(committee_id))
+        .order(committee_members::created_at.asc())
+        .select((
+            CommitteeMember::as_select(),
+            Agent::as_select(),
+            analyses::verdict.nullable(),
+        ))
+        .load::<(CommitteeMember, Agent, Option<String>)>(conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(member, agent, verdict)| CommitteeMemberResponse {
+            agent_slug: agent.slug,
+            agent_name: agent.name,
+            weight: member.weight,
+            analysis_id: member.analysis_id,
+            verdict,
+            error: member.error,
+        })
+        .collect())
+}
@@ -0,0 +1,11 @@
+use axum::{http::header, response::IntoResponse, Json};
+
+use crate::jwt_keys;
+
+/// Public keys that verify access tokens, for services that check tokens themselves.
+pub async fn get_jwks() -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(jwt_keys::keys().jwks().clone()),
+    )
+}
@@ -1,5 +1,5 @@
 use axum::{
-    extract::{State, Path, Extension},
+    extract::{State, Path},
     http::StatusCode,
     response::Json,
 };
@@ -12,7 +12,6 @@ use crate::{
         LlmProvider, NewLlmProvider, CreateLlmProviderRequest, 
         UpdateLlmProviderRequest, LlmProviderResponse
     },
-    auth::Claims,
     schema::{llm_providers, llm_usage},
 };
 
@@ -23,16 +22,19 @@ fn encrypt_api_key(api_key: &str) -> String {
     base64::engine::general_purpose::STANDARD.encode(api_key)
 }
 
+// Reverses `encrypt_api_key` so the key can be sent to the provider
+pub fn decrypt_api_key(api_key_encrypted: &str) -> Option<String> {
+    use base64::Engine;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(api_key_encrypted)
+        .ok()?;
+    String::from_utf8(bytes).ok()
+}
+
 pub async fn create_llm_provider(
-    Extension(claims): Extension<Claims>,
     State(pool): State<DbPool>,
     Json(request): Json<CreateLlmProviderRequest>,
 ) -> Result<Json<LlmProviderResponse>, StatusCode> {
-    // Only admin can create LLM providers
-    if claims.role != "admin" {
-        return Err(StatusCode::FORBIDDEN);
-    }
-
     let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let encrypted_api_key = encrypt_api_key(&request.api_key);
@@ -55,14 +57,8 @@ pub async fn create_llm_provider(
 }
 
 pub async fn list_llm_providers(
-    Extension(claims): Extension<Claims>,
     State(pool): State<DbPool>,
 ) -> Result<Json<Vec<LlmProviderResponse>>, StatusCode> {
-    // Only admin can list LLM providers
-    if claims.role != "admin" {
-        return Err(StatusCode::FORBIDDEN);
-    }
-
     let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let providers = llm_providers::table
@@ -75,15 +71,9 @@ pub async fn list_llm_providers(
 }
 
 pub async fn get_llm_provider(
-    Extension(claims): Extension<Claims>,
     State(pool): State<DbPool>,
     Path(provider_id): Path<Uuid>,
 ) -> Result<Json<LlmProviderResponse>, StatusCode> {
-    // Only admin can get LLM provider details
-    if claims.role != "admin" {
-        return Err(StatusCode::FORBIDDEN);
-    }
-
     let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let provider = llm_providers::table
@@ -98,16 +88,10 @@ pub async fn get_llm_provider(
 }
 
 pub async fn update_llm_provider(
-    Extension(claims): Extension<Claims>,
     State(pool): State<DbPool>,
     Path(provider_id): Path<Uuid>,
     Json(request): Json<UpdateLlmProviderRequest>,
 ) -> Result<Json<LlmProviderResponse>, StatusCode> {
-    // Only admin can update LLM providers
-    if claims.role != "admin" {
-        return Err(StatusCode::FORBIDDEN);
-    }
-
     let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // Simple update approach - update each field separately if provided
@@ -164,15 +148,9 @@ pub async fn update_llm_provider(
 }
 
 pub async fn delete_llm_provider(
-    Extension(claims): Extension<Claims>,
     State(pool): State<DbPool>,
     Path(provider_id): Path<Uuid>,
 ) -> Result<StatusCode, StatusCode> {
-    // Only admin can delete LLM providers
-    if claims.role != "admin" {
-        return Err(StatusCode::FORBIDDEN);
-    }
-
     let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let deleted_count = diesel::delete(llm_providers::table.find(provider_id))
@@ -187,14 +165,8 @@ pub async fn delete_llm_provider(
 }
 
 pub async fn get_llm_usage_stats(
-    Extension(claims): Extension<Claims>,
     State(pool): State<DbPool>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    // Only admin can view usage stats
-    if claims.role != "admin" {
-        return Err(StatusCode::FORBIDDEN);
-    }
-
     let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // Simple query to get basic usage stats
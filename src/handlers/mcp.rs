@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::time::Instant;
+
+use axum::{
+    extract::{Extension, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+};
+use diesel::dsl::{count_star, now};
+use diesel::prelude::*;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    auth::Claims,
+    database::DbPool,
+    mcp::{
+        client::{McpClient, McpStatus},
+        protocol::{self, RpcError, INTERNAL_ERROR},
+        server::{parse_request, McpServer},
+        tools, SUPPORTED_PROTOCOL_VERSIONS,
+    },
+    models::{
+        McpToolAccess, McpUsageStat, NewMcpToolAccess, NewMcpUsage, UpdateMcpToolAccessRequest,
+    },
+    schema::{mcp_tool_access, mcp_usage, roles, users},
+};
+
+/// Sent by clients on every request after initialization.
+const PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+
+pub async fn get_mcp_status(Extension(mcp): Extension<McpClient>) -> Json<McpStatus> {
+    Json(mcp.status())
+}
+
+/// Streamable HTTP transport: one JSON-RPC message per POST, answered with JSON, or with an
+/// SSE stream for tool calls when the client accepts one so slow tools keep the connection
+/// alive. The transport is stateless; the caller is identified by its JWT on every request.
+pub async fn handle_mcp_request(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(version) = headers.get(PROTOCOL_VERSION_HEADER) {
+        let supported = version
+            .to_str()
+            .is_ok_and(|version| SUPPORTED_PROTOCOL_VERSIONS.contains(&version));
+        if !supported {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let request = match parse_request(&body) {
+        Ok(request) => request,
+        Err(response) => return Ok((StatusCode::BAD_REQUEST, Json(*response)).into_response()),
+    };
+    // Notifications need no answer
+    if request.id.is_none() {
+        return Ok(StatusCode::ACCEPTED.into_response());
+    }
+
+    let accepts_stream = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"));
+    let stream = accepts_stream && request.method == "tools/call";
+    let response = run_request(pool, user_id, claims.role, request);
+
+    if stream {
+        let events = futures::stream::once(async move {
+            let response = response.await;
+            Ok::<_, Infallible>(
+                Event::default()
+                    .event("message")
+                    .json_data(&response)
+                    .unwrap_or_default(),
+            )
+        });
+        Ok(Sse::new(events)
+            .keep_alive(KeepAlive::default())
+            .into_response())
+    } else {
+        Ok(Json(response.await).into_response())
+    }
+}
+
+/// Handle a request on the blocking pool and record what the user used.
+async fn run_request(
+    pool: DbPool,
+    user_id: Uuid,
+    role: String,
+    request: protocol::Request,
+) -> protocol::Response {
+    let id = request.id.clone().unwrap_or(Value::Null);
+    let method = request.method.clone();
+    let target = match method.as_str() {
+        "tools/call" | "prompts/get" => request.params.as_ref().map(|params| &params["name"]),
+        "resources/read" => request.params.as_ref().map(|params| &params["uri"]),
+        _ => None,
+    }
+    .and_then(Value::as_str)
+    .map(str::to_string);
+    let metered = matches!(
+        method.as_str(),
+        "tools/call" | "prompts/get" | "resources/read"
+    );
+
+    let handled = tokio::task::spawn_blocking(move || {
+        let started = Instant::now();
+        let response = McpServer::new(pool.clone()).handle(request, Some(&role))?;
+
+        if metered {
+            let is_error = response.error.is_some()
+                || response
+                    .result
+                    .as_ref()
+                    .is_some_and(|result| result["isError"] == true);
+            let usage = NewMcpUsage {
+                user_id,
+                method,
+                target,
+                is_error,
+                duration_ms: started.elapsed().as_millis().try_into().unwrap_or(i32::MAX),
+            };
+            let recorded = pool.get().map_err(|e| e.to_string()).and_then(|mut conn| {
+                diesel::insert_into(mcp_usage::table)
+                    .values(&usage)
+                    .execute(&mut conn)
+                    .map_err(|e| e.to_string())
+            });
+            if let Err(e) = recorded {
+                tracing::error!("Failed to record MCP usage: {}", e);
+            }
+        }
+
+        Some(response)
+    })
+    .await;
+
+    match handled {
+        Ok(Some(response)) => response,
+        _ => protocol::Response::failure(id, RpcError::new(INTERNAL_ERROR, "request failed")),
+    }
+}
+
+pub async fn list_tool_access(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<McpToolAccess>>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let rules = mcp_tool_access::table
+        .order(mcp_tool_access::tool_name.asc())
+        .select(McpToolAccess::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(rules))
+}
+
+/// Restrict a tool to the given roles on authenticated transports.
+pub async fn update_tool_access(
+    State(pool): State<DbPool>,
+    Path(tool_name): Path<String>,
+    Json(request): Json<UpdateMcpToolAccessRequest>,
+) -> Result<Json<McpToolAccess>, StatusCode> {
+    if !tools::list().iter().any(|tool| tool.name == tool_name) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let mut allowed_roles = request.allowed_roles;
+    allowed_roles.sort();
+    allowed_roles.dedup();
+    if allowed_roles.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let known_roles: i64 = roles::table
+        .filter(roles::name.eq_any(&allowed_roles))
+        .count()
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if known_roles != allowed_roles.len() as i64 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let rule = NewMcpToolAccess {
+        tool_name,
+        allowed_roles,
+    };
+    let rule = diesel::insert_into(mcp_tool_access::table)
+        .values(&rule)
+        .on_conflict(mcp_tool_access::tool_name)
+        .do_update()
+        .set((&rule, mcp_tool_access::updated_at.eq(now)))
+        .returning(McpToolAccess::as_select())
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(rule))
+}
+
+/// Open a tool to every role again.
+pub async fn delete_tool_access(
+    State(pool): State<DbPool>,
+    Path(tool_name): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let deleted_count = diesel::delete(mcp_tool_access::table.find(tool_name))
+        .execute(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted_count == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Calls over the HTTP transport per user, method and tool, resource or prompt.
+pub async fn get_mcp_usage_stats(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<McpUsageStat>>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let grouped =
+        mcp_usage::table.group_by((mcp_usage::user_id, mcp_usage::method, mcp_usage::target));
+    let calls: Vec<(Uuid, String, Option<String>, i64)> = grouped
+        .select((
+            mcp_usage::user_id,
+            mcp_usage::method,
+            mcp_usage::target,
+            count_star(),
+        ))
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let errors: HashMap<(Uuid, String, Option<String>), i64> = grouped
+        .filter(mcp_usage::is_error.eq(true))
+        .select((
+            mcp_usage::user_id,
+            mcp_usage::method,
+            mcp_usage::target,
+            count_star(),
+        ))
+        .load::<(Uuid, String, Option<String>, i64)>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(|(user_id, method, target, errors)| ((user_id, method, target), errors))
+        .collect();
+
+    let user_ids: Vec<Uuid> = calls.iter().map(|(user_id, ..)| *user_id).collect();
+    let usernames: HashMap<Uuid, String> = users::table
+        .filter(users::id.eq_any(&user_ids))
+        .select((users::id, users::username))
+        .load::<(Uuid, String)>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .collect();
+
+    let mut stats: Vec<McpUsageStat> = calls
+        .into_iter()
+        .map(|(user_id, method, target, calls)| {
+            let errors = errors
+                .get(&(user_id, method.clone(), target.clone()))
+                .copied()
+                .unwrap_or(0);
+            McpUsageStat {
+                user_id,
+                username: usernames.get(&user_id).cloned().unwrap_or_default(),
+                method,
+                target,
+                calls,
+                errors,
+            }
+        })
+        .collect();
+    stats.sort_by(|a, b| {
+        (&a.username, &a.method, &a.target).cmp(&(&b.username, &b.method, &b.target))
+    });
+
+    Ok(Json(stats))
+}
@@ -0,0 +1,148 @@
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::Claims,
+    database::DbPool,
+    environments::is_mfa_required_for_admins,
+    handlers::session::{ensure_can_manage, find_user},
+    mfa,
+    models::{MfaCodeRequest, MfaStatusResponse, RecoveryCodesResponse, TotpEnrollmentResponse},
+    permissions,
+};
+
+pub async fn get_mfa_status(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MfaStatusResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let totp_enabled =
+        mfa::is_enabled(&mut conn, user_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let recovery_codes_remaining = mfa::recovery_codes_remaining(&mut conn, user_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let required = if is_mfa_required_for_admins() {
+        let granted = permissions::load(&mut conn, &claims.role)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        permissions::has_admin_access(&granted)
+    } else {
+        false
+    };
+
+    Ok(Json(MfaStatusResponse {
+        totp_enabled,
+        recovery_codes_remaining,
+        required,
+    }))
+}
+
+/// Start TOTP enrollment. TOTP is enabled once a code is confirmed; until then enrolling again
+/// replaces the secret. Fails with 409 if TOTP is already enabled.
+pub async fn enroll_totp(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+) -> Result<(StatusCode, Json<TotpEnrollmentResponse>), StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let secret = mfa::enroll(&mut conn, user_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(TotpEnrollmentResponse {
+            otpauth_uri: mfa::otpauth_uri(&secret, &claims.username),
+            secret,
+        }),
+    ))
+}
+
+/// Enable TOTP with the first code from the authenticator app and return the recovery codes.
+pub async fn confirm_totp(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Json(request): Json<MfaCodeRequest>,
+) -> Result<Json<RecoveryCodesResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let recovery_codes = mfa::confirm(&mut conn, user_id, request.code.trim())
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// Turn TOTP off with a current code or a recovery code.
+pub async fn disable_totp(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Json(request): Json<MfaCodeRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let enabled =
+        mfa::is_enabled(&mut conn, user_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !enabled {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let is_valid = mfa::verify(&mut conn, user_id, &request.code)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !is_valid {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    mfa::disable(&mut conn, user_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Replace the recovery codes, confirmed with a current code or a recovery code.
+pub async fn regenerate_recovery_codes(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Json(request): Json<MfaCodeRequest>,
+) -> Result<Json<RecoveryCodesResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let enabled =
+        mfa::is_enabled(&mut conn, user_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !enabled {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let is_valid = mfa::verify(&mut conn, user_id, &request.code)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !is_valid {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let recovery_codes = mfa::replace_recovery_codes(&mut conn, user_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// Turn off a user's TOTP, e.g. after they lost their authenticator and recovery codes.
+pub async fn reset_user_mfa(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = find_user(&mut conn, user_id)?;
+    ensure_can_manage(&mut conn, &claims, &user.role)?;
+
+    let disabled =
+        mfa::disable(&mut conn, user_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !disabled {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
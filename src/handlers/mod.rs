@@ -1,2 +1,42 @@
+use axum::http::StatusCode;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
 pub mod user;
 pub mod llm_provider;
+pub mod agent;
+pub mod analysis;
+pub mod stock;
+pub mod committee;
+pub mod mcp;
+pub mod watchlist;
+pub mod portfolio;
+pub mod portfolio_import;
+pub mod alert;
+pub mod notification;
+pub mod session;
+pub mod jwks;
+pub mod account;
+pub mod mfa;
+pub mod oidc;
+pub mod api_token;
+pub mod role;
+
+/// Longest name of a watchlist, portfolio, import mapping or API token, in characters.
+const MAX_NAME_LEN: usize = 100;
+
+/// The trimmed name, or 400 if it is empty or longer than `MAX_NAME_LEN`.
+fn validate_name(name: &str) -> Result<String, StatusCode> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(name.to_string())
+}
+
+/// 409 for a unique violation, such as a name the user already uses, otherwise 500.
+fn conflict_or_internal(error: DieselError) -> StatusCode {
+    match error {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => StatusCode::CONFLICT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
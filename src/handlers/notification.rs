@@ -0,0 +1,264 @@
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::NaiveDateTime;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    auth::Claims,
+    database::DbPool,
+    models::{
+        NewNotificationSettings, Notification, NotificationDelivery, NotificationListQuery,
+        NotificationSettings, NotificationsResponse, UpdateNotificationRequest,
+        UpdateNotificationSettingsRequest,
+    },
+    notifications::{generate_webhook_secret, is_public_webhook_host},
+    schema::{notification_outbox, notification_settings, notifications},
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+const MAX_WEBHOOK_URL_LEN: usize = 2048;
+
+/// The inbox, newest first, with the number of unread notifications.
+pub async fn list_notifications(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Query(query): Query<NotificationListQuery>,
+) -> Result<Json<NotificationsResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let unread_count: i64 = notifications::table
+        .filter(notifications::user_id.eq(user_id))
+        .filter(notifications::read_at.is_null())
+        .count()
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut statement = notifications::table
+        .filter(notifications::user_id.eq(user_id))
+        .into_boxed();
+    if query.unread == Some(true) {
+        statement = statement.filter(notifications::read_at.is_null());
+    }
+
+    let notifications = statement
+        .order(notifications::created_at.desc())
+        .limit(
+            query
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        )
+        .offset(query.offset.unwrap_or(0).max(0))
+        .select(Notification::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(NotificationsResponse {
+        unread_count,
+        notifications,
+    }))
+}
+
+/// Mark a notification read or unread.
+pub async fn update_notification(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(notification_id): Path<Uuid>,
+    Json(request): Json<UpdateNotificationRequest>,
+) -> Result<Json<Notification>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let own_notification = notifications::table
+        .filter(notifications::id.eq(notification_id))
+        .filter(notifications::user_id.eq(user_id));
+    let updated = if request.read {
+        // Keep the time it was first read
+        diesel::update(own_notification.filter(notifications::read_at.is_null()))
+            .set(notifications::read_at.eq(now))
+            .execute(&mut conn)
+    } else {
+        diesel::update(own_notification)
+            .set(notifications::read_at.eq(None::<NaiveDateTime>))
+            .execute(&mut conn)
+    };
+    updated.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let notification = own_notification
+        .select(Notification::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(notification))
+}
+
+pub async fn mark_all_notifications_read(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let updated = diesel::update(
+        notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::read_at.is_null()),
+    )
+    .set(notifications::read_at.eq(now))
+    .execute(&mut conn)
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(serde_json::json!({ "updated": updated })))
+}
+
+pub async fn delete_notification(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(notification_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let deleted_count = diesel::delete(
+        notifications::table
+            .filter(notifications::id.eq(notification_id))
+            .filter(notifications::user_id.eq(user_id)),
+    )
+    .execute(&mut conn)
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted_count == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Email and webhook delivery attempts of a notification.
+pub async fn list_notification_deliveries(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(notification_id): Path<Uuid>,
+) -> Result<Json<Vec<NotificationDelivery>>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let exists: i64 = notifications::table
+        .filter(notifications::id.eq(notification_id))
+        .filter(notifications::user_id.eq(user_id))
+        .count()
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if exists == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let deliveries = notification_outbox::table
+        .filter(notification_outbox::notification_id.eq(notification_id))
+        .order(notification_outbox::created_at.asc())
+        .select(NotificationDelivery::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(deliveries))
+}
+
+/// Delivery settings; users without saved settings only get in-app notifications.
+pub async fn get_notification_settings(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+) -> Result<Json<NotificationSettings>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let settings = load_settings(&mut conn, user_id)?;
+    Ok(Json(settings))
+}
+
+/// Turn email on or off and set or remove the webhook. A signing secret is generated with
+/// the first webhook URL and returned in the response.
+pub async fn update_notification_settings(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Json(request): Json<UpdateNotificationSettingsRequest>,
+) -> Result<Json<NotificationSettings>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let webhook_url = request
+        .webhook_url
+        .as_deref()
+        .map(validate_webhook_url)
+        .transpose()?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let current = load_settings(&mut conn, user_id)?;
+    let webhook_url = webhook_url.unwrap_or(current.webhook_url);
+    let webhook_secret = match &webhook_url {
+        None => None,
+        Some(_) if request.rotate_webhook_secret == Some(true) => Some(generate_webhook_secret()),
+        Some(_) => current
+            .webhook_secret
+            .or_else(|| Some(generate_webhook_secret())),
+    };
+    let settings = NewNotificationSettings {
+        user_id,
+        email_enabled: request.email_enabled.unwrap_or(current.email_enabled),
+        webhook_url,
+        webhook_secret,
+    };
+
+    let settings = diesel::insert_into(notification_settings::table)
+        .values(&settings)
+        .on_conflict(notification_settings::user_id)
+        .do_update()
+        .set((&settings, notification_settings::updated_at.eq(now)))
+        .returning(NotificationSettings::as_select())
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(settings))
+}
+
+fn load_settings(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<NotificationSettings, StatusCode> {
+    let settings = notification_settings::table
+        .find(user_id)
+        .select(NotificationSettings::as_select())
+        .first(conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(settings.unwrap_or_else(|| NotificationSettings {
+        user_id,
+        email_enabled: false,
+        webhook_url: None,
+        webhook_secret: None,
+        updated_at: chrono::Utc::now().naive_utc(),
+    }))
+}
+
+/// `Some(url)` for an http(s) URL of a public host, `None` for an empty string, which removes
+/// the webhook.
+fn validate_webhook_url(url: &str) -> Result<Option<String>, StatusCode> {
+    let url = url.trim();
+    if url.is_empty() {
+        return Ok(None);
+    }
+    let parsed = reqwest::Url::parse(url).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if !matches!(parsed.scheme(), "http" | "https")
+        || !is_public_webhook_host(&parsed)
+        || url.len() > MAX_WEBHOOK_URL_LEN
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(Some(url.to_string()))
+}
@@ -0,0 +1,197 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Extension, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{Json, Redirect},
+};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    auth::Claims,
+    database::DbPool,
+    handlers::user::start_login,
+    models::{
+        AuthorizationUrlResponse, CompleteOidcLinkRequest, LoginResult, OidcCallbackQuery,
+        UserIdentity,
+    },
+    oidc::{self, IdTokenClaims, OidcClient, SignInError},
+    schema::user_identities,
+    sessions::ClientInfo,
+};
+
+/// Send the browser to the identity provider to sign in. 404 when single sign-on is not
+/// configured.
+pub async fn oidc_login(
+    Extension(oidc): Extension<Option<OidcClient>>,
+    State(pool): State<DbPool>,
+) -> Result<Redirect, StatusCode> {
+    let oidc = oidc.ok_or(StatusCode::NOT_FOUND)?;
+
+    let login = {
+        let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        oidc::begin_login(&mut conn, None).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    };
+    let url = oidc.authorization_url(&login).await.map_err(|e| {
+        tracing::error!("OpenID Connect login failed: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    Ok(Redirect::to(&url))
+}
+
+/// Where the identity provider sends the browser back after `oidc_login`. Signs in like
+/// `/api/auth/login`, including its two-factor challenge. Links are finished by
+/// `complete_oidc_link` instead.
+pub async fn oidc_callback(
+    Extension(oidc): Extension<Option<OidcClient>>,
+    State(pool): State<DbPool>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<Json<LoginResult>, StatusCode> {
+    let oidc = oidc.ok_or(StatusCode::NOT_FOUND)?;
+    if let Some(error) = query.error {
+        tracing::info!("OpenID Connect login refused by the provider: {}", error);
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    let (Some(code), Some(state)) = (query.code, query.state) else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    let claims = verify_login(&pool, &oidc, &code, &state, None).await?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let user = oidc::sign_in(&mut conn, &oidc, &claims).map_err(sign_in_status)?;
+
+    start_login(&mut conn, user, ClientInfo::new(&headers, peer)).map(Json)
+}
+
+/// Start linking an identity at the provider to the caller's account. The browser signs in at
+/// the returned URL, and the caller sends the `code` and `state` the provider redirects back
+/// with to `complete_oidc_link`.
+pub async fn link_oidc_identity(
+    Extension(claims): Extension<Claims>,
+    Extension(oidc): Extension<Option<OidcClient>>,
+    State(pool): State<DbPool>,
+) -> Result<Json<AuthorizationUrlResponse>, StatusCode> {
+    let oidc = oidc.ok_or(StatusCode::NOT_FOUND)?;
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let login = {
+        let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        oidc::begin_login(&mut conn, Some(user_id))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    };
+    let authorization_url = oidc.authorization_url(&login).await.map_err(|e| {
+        tracing::error!("OpenID Connect login failed: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    Ok(Json(AuthorizationUrlResponse { authorization_url }))
+}
+
+/// Link the identity the provider redirected back with to the caller's account. Only the user
+/// who started the link can finish it.
+pub async fn complete_oidc_link(
+    Extension(claims): Extension<Claims>,
+    Extension(oidc): Extension<Option<OidcClient>>,
+    State(pool): State<DbPool>,
+    Json(request): Json<CompleteOidcLinkRequest>,
+) -> Result<(StatusCode, Json<UserIdentity>), StatusCode> {
+    let oidc = oidc.ok_or(StatusCode::NOT_FOUND)?;
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let id_claims =
+        verify_login(&pool, &oidc, &request.code, &request.state, Some(user_id)).await?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let identity = oidc::link(&mut conn, &oidc.config().issuer, &id_claims, user_id)
+        .map_err(sign_in_status)?;
+
+    Ok((StatusCode::CREATED, Json(identity)))
+}
+
+pub async fn list_identities(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<UserIdentity>>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let identities = user_identities::table
+        .filter(user_identities::user_id.eq(user_id))
+        .order(user_identities::created_at.asc())
+        .select(UserIdentity::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(identities))
+}
+
+pub async fn unlink_identity(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(identity_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let deleted = diesel::delete(
+        user_identities::table
+            .filter(user_identities::id.eq(identity_id))
+            .filter(user_identities::user_id.eq(user_id)),
+    )
+    .execute(&mut conn)
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Take the login started with `state` for `link_user_id` and redeem the provider's `code` for
+/// the verified ID token claims.
+async fn verify_login(
+    pool: &DbPool,
+    oidc: &OidcClient,
+    code: &str,
+    state: &str,
+    link_user_id: Option<Uuid>,
+) -> Result<IdTokenClaims, StatusCode> {
+    let login = {
+        let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        oidc::take_login(&mut conn, state, link_user_id)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::BAD_REQUEST)?
+    };
+    oidc.exchange_code(code, &login).await.map_err(|e| {
+        tracing::warn!("OpenID Connect login failed: {}", e);
+        match e {
+            oidc::OidcError::Provider(_) => StatusCode::BAD_GATEWAY,
+            oidc::OidcError::CodeRejected(_) | oidc::OidcError::InvalidIdToken(_) => {
+                StatusCode::UNAUTHORIZED
+            }
+        }
+    })
+}
+
+fn sign_in_status(error: SignInError) -> StatusCode {
+    if let SignInError::UnknownRole(_) = error {
+        tracing::error!("OpenID Connect sign-in refused: {}", error);
+    } else {
+        tracing::info!("OpenID Connect sign-in refused: {}", error);
+    }
+    match error {
+        SignInError::MissingEmail => StatusCode::BAD_REQUEST,
+        SignInError::EmailTaken | SignInError::IdentityTaken | SignInError::LastAdmin => {
+            StatusCode::CONFLICT
+        }
+        SignInError::NoRole | SignInError::UnknownRole(_) | SignInError::Inactive => {
+            StatusCode::FORBIDDEN
+        }
+        SignInError::PasswordHash(_) | SignInError::Database(_) => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
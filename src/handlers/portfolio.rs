@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Days;
+use diesel::dsl::{count_star, now};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use uuid::Uuid;
+
+use crate::{
+    auth::Claims,
+    database::DbPool,
+    handlers::{conflict_or_internal, validate_name},
+    models::{
+        CreatePortfolioRequest, CreatePortfolioTransactionRequest, NewPortfolio, Portfolio,
+        PortfolioResponse, PortfolioRiskQuery, PortfolioSummary, PortfolioTransaction, PriceBar,
+        UpdatePortfolioRequest,
+    },
+    portfolio::{self, Performance, PriceHistory, COST_BASIS_METHODS, METHOD_FIFO},
+    risk::{
+        self, RiskReport, RiskSettings, DEFAULT_BENCHMARK, DEFAULT_CONFIDENCE,
+        DEFAULT_LOOKBACK_DAYS, MAX_LOOKBACK_DAYS,
+    },
+    schema::{portfolio_transactions, portfolios, price_bars, stock_profiles},
+    stocks::normalize_ticker,
+};
+
+/// Prices loaded from before the first trade so it can be valued on a non-trading day.
+const PRICE_LOOKBACK_DAYS: u64 = 10;
+
+pub async fn list_portfolios(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<PortfolioSummary>>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let portfolios = portfolios::table
+        .filter(portfolios::user_id.eq(user_id))
+        .order(portfolios::created_at.asc())
+        .select(Portfolio::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let ids: Vec<Uuid> = portfolios.iter().map(|portfolio| portfolio.id).collect();
+    let counts: HashMap<Uuid, i64> = portfolio_transactions::table
+        .filter(portfolio_transactions::portfolio_id.eq_any(&ids))
+        .group_by(portfolio_transactions::portfolio_id)
+        .select((portfolio_transactions::portfolio_id, count_star()))
+        .load::<(Uuid, i64)>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .collect();
+
+    let response = portfolios
+        .into_iter()
+        .map(|portfolio| PortfolioSummary {
+            transaction_count: counts.get(&portfolio.id).copied().unwrap_or(0),
+            id: portfolio.id,
+            name: portfolio.name,
+            description: portfolio.description,
+            cost_basis_method: portfolio.cost_basis_method,
+            created_at: portfolio.created_at,
+            updated_at: portfolio.updated_at,
+        })
+        .collect();
+    Ok(Json(response))
+}
+
+pub async fn create_portfolio(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Json(request): Json<CreatePortfolioRequest>,
+) -> Result<(StatusCode, Json<PortfolioResponse>), StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let name = validate_name(&request.name)?;
+    let cost_basis_method = request
+        .cost_basis_method
+        .unwrap_or_else(|| METHOD_FIFO.to_string());
+    validate_method(&cost_basis_method)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let portfolio: Portfolio = diesel::insert_into(portfolios::table)
+        .values(&NewPortfolio {
+            user_id,
+            name,
+            description: request.description,
+            cost_basis_method,
+        })
+        .returning(Portfolio::as_select())
+        .get_result(&mut conn)
+        .map_err(conflict_or_internal)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(portfolio_response(portfolio, Performance::default())),
+    ))
+}
+
+/// The portfolio with its positions, profit and loss and returns at the latest stored prices.
+pub async fn get_portfolio(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(portfolio_id): Path<Uuid>,
+) -> Result<Json<PortfolioResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let portfolio = find_portfolio(&mut conn, user_id, portfolio_id)?;
+    let performance = evaluate_portfolio(&mut conn, &portfolio)?;
+
+    Ok(Json(portfolio_response(portfolio, performance)))
+}
+
+pub async fn update_portfolio(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(portfolio_id): Path<Uuid>,
+    Json(mut request): Json<UpdatePortfolioRequest>,
+) -> Result<Json<PortfolioResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(name) = &request.name {
+        request.name = Some(validate_name(name)?);
+    }
+    if let Some(method) = &request.cost_basis_method {
+        validate_method(method)?;
+    }
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let portfolio: Portfolio = diesel::update(
+        portfolios::table
+            .filter(portfolios::id.eq(portfolio_id))
+            .filter(portfolios::user_id.eq(user_id)),
+    )
+    .set((&request, portfolios::updated_at.eq(now)))
+    .returning(Portfolio::as_select())
+    .get_result(&mut conn)
+    .optional()
+    .map_err(conflict_or_internal)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    let performance = evaluate_portfolio(&mut conn, &portfolio)?;
+
+    Ok(Json(portfolio_response(portfolio, performance)))
+}
+
+pub async fn delete_portfolio(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(portfolio_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let deleted_count = diesel::delete(
+        portfolios::table
+            .filter(portfolios::id.eq(portfolio_id))
+            .filter(portfolios::user_id.eq(user_id)),
+    )
+    .execute(&mut conn)
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted_count == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Volatility, beta, Sharpe and Sortino ratios, drawdown, VaR/CVaR, sector concentration
+/// and correlations of the current holdings over the stored price history.
+pub async fn get_portfolio_risk(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(portfolio_id): Path<Uuid>,
+    Query(query): Query<PortfolioRiskQuery>,
+) -> Result<Json<RiskReport>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let benchmark = normalize_ticker(query.benchmark.as_deref().unwrap_or(DEFAULT_BENCHMARK))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let days = query.days.unwrap_or(DEFAULT_LOOKBACK_DAYS);
+    let confidence = query.confidence.unwrap_or(DEFAULT_CONFIDENCE);
+    let risk_free_rate = query.risk_free_rate.unwrap_or(0.0);
+    if !(1..=MAX_LOOKBACK_DAYS).contains(&days)
+        || !(0.5..1.0).contains(&confidence)
+        || !(-1.0..1.0).contains(&risk_free_rate)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let portfolio = find_portfolio(&mut conn, user_id, portfolio_id)?;
+    let performance = evaluate_portfolio(&mut conn, &portfolio)?;
+
+    let mut tickers: Vec<&String> = performance
+        .positions
+        .iter()
+        .map(|position| &position.ticker)
+        .collect();
+    tickers.push(&benchmark);
+    let bars = match performance.as_of {
+        Some(as_of) => {
+            let since = as_of
+                .checked_sub_days(Days::new(days as u64))
+                .ok_or(StatusCode::BAD_REQUEST)?;
+            price_bars::table
+                .filter(price_bars::ticker.eq_any(&tickers))
+                .filter(price_bars::trade_date.ge(since))
+                .filter(price_bars::trade_date.le(as_of))
+                .select(PriceBar::as_select())
+                .load(&mut conn)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        }
+        None => Vec::new(),
+    };
+    let sectors: HashMap<String, String> = stock_profiles::table
+        .filter(stock_profiles::ticker.eq_any(&tickers))
+        .filter(stock_profiles::sector.is_not_null())
+        .select((
+            stock_profiles::ticker,
+            stock_profiles::sector.assume_not_null(),
+        ))
+        .load::<(String, String)>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .collect();
+
+    Ok(Json(risk::analyze(
+        &performance.positions,
+        &sectors,
+        &PriceHistory::new(&bars),
+        RiskSettings {
+            benchmark,
+            confidence,
+            risk_free_rate,
+        },
+    )))
+}
+
+/// Transactions oldest first.
+pub async fn list_transactions(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(portfolio_id): Path<Uuid>,
+) -> Result<Json<Vec<PortfolioTransaction>>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let portfolio = find_portfolio(&mut conn, user_id, portfolio_id)?;
+    let transactions = load_transactions(&mut conn, portfolio.id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(transactions))
+}
+
+/// Record a buy, sell or dividend. A sell that would exceed the shares held at that date,
+/// counting every other transaction, is rejected.
+pub async fn create_transaction(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(portfolio_id): Path<Uuid>,
+    Json(request): Json<CreatePortfolioTransactionRequest>,
+) -> Result<(StatusCode, Json<PortfolioTransaction>), StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let portfolio = find_portfolio(&mut conn, user_id, portfolio_id)?;
+    let new_transaction = portfolio::validate_transaction(portfolio.id, request)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let transaction = conn
+        .transaction(|conn| {
+            let portfolio = lock_portfolio(conn, portfolio.id)?;
+            let transaction: PortfolioTransaction =
+                diesel::insert_into(portfolio_transactions::table)
+                    .values(&new_transaction)
+                    .returning(PortfolioTransaction::as_select())
+                    .get_result(conn)?;
+            check_ledger(conn, &portfolio)?;
+            Ok(transaction)
+        })
+        .map_err(rejected_or_internal)?;
+
+    Ok((StatusCode::CREATED, Json(transaction)))
+}
+
+/// Delete a transaction unless that leaves a later sell without the shares it sold.
+pub async fn delete_transaction(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path((portfolio_id, transaction_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let portfolio = find_portfolio(&mut conn, user_id, portfolio_id)?;
+    let deleted_count = conn
+        .transaction(|conn| {
+            let portfolio = lock_portfolio(conn, portfolio.id)?;
+            let deleted_count = diesel::delete(
+                portfolio_transactions::table
+                    .filter(portfolio_transactions::id.eq(transaction_id))
+                    .filter(portfolio_transactions::portfolio_id.eq(portfolio.id)),
+            )
+            .execute(conn)?;
+            check_ledger(conn, &portfolio)?;
+            Ok(deleted_count)
+        })
+        .map_err(rejected_or_internal)?;
+
+    if deleted_count == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) fn find_portfolio(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    portfolio_id: Uuid,
+) -> Result<Portfolio, StatusCode> {
+    portfolios::table
+        .filter(portfolios::id.eq(portfolio_id))
+        .filter(portfolios::user_id.eq(user_id))
+        .select(Portfolio::as_select())
+        .first(conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Serializes changes to a portfolio's transactions so each is checked against the others.
+pub(crate) fn lock_portfolio(
+    conn: &mut PgConnection,
+    portfolio_id: Uuid,
+) -> QueryResult<Portfolio> {
+    portfolios::table
+        .find(portfolio_id)
+        .select(Portfolio::as_select())
+        .for_update()
+        .first(conn)
+}
+
+pub(crate) fn load_transactions(
+    conn: &mut PgConnection,
+    portfolio_id: Uuid,
+) -> QueryResult<Vec<PortfolioTransaction>> {
+    portfolio_transactions::table
+        .filter(portfolio_transactions::portfolio_id.eq(portfolio_id))
+        .order((
+            portfolio_transactions::trade_date.asc(),
+            portfolio_transactions::created_at.asc(),
+        ))
+        .select(PortfolioTransaction::as_select())
+        .load(conn)
+}
+
+/// Roll back with `RollbackTransaction` when the history no longer adds up.
+fn check_ledger(conn: &mut PgConnection, portfolio: &Portfolio) -> QueryResult<()> {
+    let transactions = load_transactions(conn, portfolio.id)?;
+    portfolio::replay(&portfolio.cost_basis_method, &transactions)
+        .map(|_| ())
+        .map_err(|_| DieselError::RollbackTransaction)
+}
+
+fn evaluate_portfolio(
+    conn: &mut PgConnection,
+    portfolio: &Portfolio,
+) -> Result<Performance, StatusCode> {
+    let transactions =
+        load_transactions(conn, portfolio.id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some(first_trade_date) = transactions
+        .iter()
+        .map(|transaction| transaction.trade_date)
+        .min()
+    else {
+        return Ok(Performance::default());
+    };
+
+    let mut tickers: Vec<&String> = transactions
+        .iter()
+        .map(|transaction| &transaction.ticker)
+        .collect();
+    tickers.sort();
+    tickers.dedup();
+    let since = first_trade_date
+        .checked_sub_days(Days::new(PRICE_LOOKBACK_DAYS))
+        .unwrap_or(first_trade_date);
+    let bars = price_bars::table
+        .filter(price_bars::ticker.eq_any(tickers))
+        .filter(price_bars::trade_date.ge(since))
+        .select(PriceBar::as_select())
+        .load(conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    portfolio::evaluate(
+        &portfolio.cost_basis_method,
+        &transactions,
+        &PriceHistory::new(&bars),
+    )
+    .map_err(|e| {
+        tracing::error!("Portfolio {} has an invalid history: {}", portfolio.id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+fn portfolio_response(portfolio: Portfolio, performance: Performance) -> PortfolioResponse {
+    PortfolioResponse {
+        id: portfolio.id,
+        name: portfolio.name,
+        description: portfolio.description,
+        cost_basis_method: portfolio.cost_basis_method,
+        performance,
+        created_at: portfolio.created_at,
+        updated_at: portfolio.updated_at,
+    }
+}
+
+fn validate_method(method: &str) -> Result<(), StatusCode> {
+    if !COST_BASIS_METHODS.contains(&method) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(())
+}
+
+
+/// A rolled back change would have sold shares the portfolio did not hold.
+fn rejected_or_internal(error: DieselError) -> StatusCode {
+    match error {
+        DieselError::RollbackTransaction => StatusCode::BAD_REQUEST,
+        DieselError::NotFound => StatusCode::NOT_FOUND,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
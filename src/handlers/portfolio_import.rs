@@ -0,0 +1,377 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use uuid::Uuid;
+
+use crate::{
+    auth::Claims,
+    csv_import::{self, ColumnMapping, ImportError, RowOutcome},
+    database::DbPool,
+    handlers::{
+        conflict_or_internal,
+        portfolio::{find_portfolio, load_transactions, lock_portfolio},
+        validate_name,
+    },
+    models::{
+        CreateImportMappingRequest, CreatePortfolioTransactionRequest, ImportMapping,
+        ImportMappingsResponse, ImportReport, ImportRowReport, ImportTransactionsRequest,
+        NewImportMapping, NewPortfolioTransaction, Portfolio, PortfolioTransaction,
+    },
+    portfolio,
+    schema::{import_mappings, portfolio_transactions},
+};
+
+
+const ROW_VALID: &str = "valid";
+const ROW_IMPORTED: &str = "imported";
+const ROW_DUPLICATE: &str = "duplicate";
+const ROW_SKIPPED: &str = "skipped";
+const ROW_INVALID: &str = "invalid";
+
+/// Why an import could not be planned.
+enum ImportFailure {
+    File(ImportError),
+    Database(DieselError),
+}
+
+impl From<DieselError> for ImportFailure {
+    fn from(error: DieselError) -> Self {
+        Self::Database(error)
+    }
+}
+
+/// Rows of a CSV checked against the portfolio, and the transactions to insert for them.
+struct ImportPlan {
+    rows: Vec<ImportRowReport>,
+    /// Row index and transaction, in file order.
+    accepted: Vec<(usize, NewPortfolioTransaction)>,
+}
+
+/// Built-in broker presets and the user's saved mappings.
+pub async fn list_import_mappings(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+) -> Result<Json<ImportMappingsResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let saved = import_mappings::table
+        .filter(import_mappings::user_id.eq(user_id))
+        .order(import_mappings::name.asc())
+        .select(ImportMapping::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(ImportMappingsResponse {
+        presets: csv_import::presets(),
+        saved,
+    }))
+}
+
+/// Save a mapping for a broker export the presets don't cover.
+pub async fn create_import_mapping(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Json(request): Json<CreateImportMappingRequest>,
+) -> Result<(StatusCode, Json<ImportMapping>), StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let name = validate_name(&request.name)?;
+    request
+        .mapping
+        .validate()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mapping =
+        serde_json::to_value(&request.mapping).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let saved = diesel::insert_into(import_mappings::table)
+        .values(&NewImportMapping {
+            user_id,
+            name,
+            mapping,
+        })
+        .returning(ImportMapping::as_select())
+        .get_result(&mut conn)
+        .map_err(conflict_or_internal)?;
+
+    Ok((StatusCode::CREATED, Json(saved)))
+}
+
+pub async fn delete_import_mapping(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(mapping_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let deleted_count = diesel::delete(
+        import_mappings::table
+            .filter(import_mappings::id.eq(mapping_id))
+            .filter(import_mappings::user_id.eq(user_id)),
+    )
+    .execute(&mut conn)
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted_count == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Parse a CSV and report what importing it would do, without writing anything.
+pub async fn preview_import(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(portfolio_id): Path<Uuid>,
+    Json(request): Json<ImportTransactionsRequest>,
+) -> Result<(StatusCode, Json<ImportReport>), StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let portfolio = find_portfolio(&mut conn, user_id, portfolio_id)?;
+    let mapping = resolve_mapping(&mut conn, user_id, &request)?;
+
+    match plan_import(&mut conn, &portfolio, &mapping, &request.csv) {
+        Ok(plan) => Ok((StatusCode::OK, Json(report(plan.rows, false)))),
+        Err(ImportFailure::File(error)) => Ok(unreadable(error)),
+        Err(ImportFailure::Database(e)) => {
+            tracing::error!("Failed to import transactions: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Import the rows of a CSV in one database transaction. Duplicates and skipped rows are
+/// left out; invalid rows abort the import unless `skip_invalid` is set.
+pub async fn import_transactions(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(portfolio_id): Path<Uuid>,
+    Json(request): Json<ImportTransactionsRequest>,
+) -> Result<(StatusCode, Json<ImportReport>), StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let portfolio = find_portfolio(&mut conn, user_id, portfolio_id)?;
+    let mapping = resolve_mapping(&mut conn, user_id, &request)?;
+
+    let imported = conn.transaction::<_, ImportFailure, _>(|conn| {
+        let portfolio = lock_portfolio(conn, portfolio.id)?;
+        let mut plan = plan_import(conn, &portfolio, &mapping, &request.csv)?;
+        let has_invalid = plan.rows.iter().any(|row| row.status == ROW_INVALID);
+        if has_invalid && !request.skip_invalid {
+            return Ok(report(plan.rows, false));
+        }
+
+        let (indexes, transactions): (Vec<usize>, Vec<NewPortfolioTransaction>) =
+            plan.accepted.into_iter().unzip();
+        diesel::insert_into(portfolio_transactions::table)
+            .values(&transactions)
+            .execute(conn)?;
+        for index in indexes {
+            plan.rows[index].status = ROW_IMPORTED.to_string();
+        }
+        Ok(report(plan.rows, true))
+    });
+
+    match imported {
+        Ok(report) if !report.committed => Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(report))),
+        Ok(report) if report.imported == 0 => Ok((StatusCode::OK, Json(report))),
+        Ok(report) => Ok((StatusCode::CREATED, Json(report))),
+        Err(ImportFailure::File(error)) => Ok(unreadable(error)),
+        Err(ImportFailure::Database(e)) => {
+            tracing::error!("Failed to import transactions: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Exactly one of a preset, a saved mapping or an inline mapping.
+fn resolve_mapping(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    request: &ImportTransactionsRequest,
+) -> Result<ColumnMapping, StatusCode> {
+    let mapping = match (&request.preset, request.mapping_id, &request.mapping) {
+        (Some(preset), None, None) => csv_import::presets()
+            .remove(preset)
+            .ok_or(StatusCode::BAD_REQUEST)?,
+        (None, Some(mapping_id), None) => {
+            let saved: ImportMapping = import_mappings::table
+                .filter(import_mappings::id.eq(mapping_id))
+                .filter(import_mappings::user_id.eq(user_id))
+                .select(ImportMapping::as_select())
+                .first(conn)
+                .optional()
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or(StatusCode::NOT_FOUND)?;
+            serde_json::from_value(saved.mapping).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        }
+        (None, None, Some(mapping)) => mapping.clone(),
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    mapping.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+    Ok(mapping)
+}
+
+/// Parse the CSV and check every row: field validation, duplicates of existing transactions
+/// (by broker id, or identical fields when there is none) and sells beyond the shares held.
+fn plan_import(
+    conn: &mut PgConnection,
+    portfolio: &Portfolio,
+    mapping: &ColumnMapping,
+    csv: &str,
+) -> Result<ImportPlan, ImportFailure> {
+    let parsed = csv_import::parse(csv, mapping).map_err(ImportFailure::File)?;
+    let existing = load_transactions(conn, portfolio.id)?;
+
+    let mut external_ids: HashSet<String> = existing
+        .iter()
+        .filter_map(|transaction| transaction.external_id.clone())
+        .collect();
+    let mut fingerprints: HashMap<String, usize> = HashMap::new();
+    for transaction in &existing {
+        *fingerprints.entry(fingerprint(transaction)).or_default() += 1;
+    }
+
+    let imported_at = Utc::now().naive_utc();
+    let mut rows = Vec::with_capacity(parsed.len());
+    let mut candidates: Vec<PortfolioTransaction> = Vec::new();
+    let mut candidate_rows: HashMap<Uuid, (usize, NewPortfolioTransaction)> = HashMap::new();
+    for row in parsed {
+        let index = rows.len();
+        let (status, transaction, messages) = match row.outcome {
+            RowOutcome::Skipped(reason) => (ROW_SKIPPED, None, vec![reason]),
+            RowOutcome::Invalid(errors) => (ROW_INVALID, None, errors),
+            RowOutcome::Transaction(request) => {
+                match portfolio::validate_transaction(portfolio.id, request.clone()) {
+                    Err(error) => (ROW_INVALID, Some(request), vec![error.to_string()]),
+                    Ok(new) => {
+                        let candidate = PortfolioTransaction {
+                            id: Uuid::new_v4(),
+                            portfolio_id: new.portfolio_id,
+                            ticker: new.ticker.clone(),
+                            kind: new.kind.clone(),
+                            trade_date: new.trade_date,
+                            quantity: new.quantity,
+                            price: new.price,
+                            amount: new.amount,
+                            fees: new.fees,
+                            notes: new.notes.clone(),
+                            created_at: imported_at,
+                            external_id: new.external_id.clone(),
+                        };
+                        let key = fingerprint(&candidate);
+                        let matches = fingerprints.get_mut(&key).filter(|count| **count > 0);
+
+                        if let Some(external_id) = &new.external_id {
+                            if !external_ids.insert(external_id.clone()) {
+                                let message =
+                                    format!("external id {} already imported", external_id);
+                                rows.push(row_report(
+                                    row.line,
+                                    ROW_DUPLICATE,
+                                    Some(request),
+                                    vec![message],
+                                ));
+                                continue;
+                            }
+                        } else if let Some(count) = matches {
+                            *count -= 1;
+                            let message = "matches an existing transaction".to_string();
+                            rows.push(row_report(
+                                row.line,
+                                ROW_DUPLICATE,
+                                Some(request),
+                                vec![message],
+                            ));
+                            continue;
+                        }
+
+                        candidate_rows.insert(candidate.id, (index, new));
+                        candidates.push(candidate);
+                        (ROW_VALID, Some(request), Vec::new())
+                    }
+                }
+            }
+        };
+        rows.push(row_report(row.line, status, transaction, messages));
+    }
+
+    let (admitted, rejected) =
+        portfolio::admit(&portfolio.cost_basis_method, &existing, candidates);
+    for (transaction, error) in rejected {
+        if let Some((index, _)) = candidate_rows.remove(&transaction.id) {
+            rows[index].status = ROW_INVALID.to_string();
+            rows[index].messages.push(error.to_string());
+        }
+    }
+    let mut accepted: Vec<(usize, NewPortfolioTransaction)> = admitted
+        .iter()
+        .filter_map(|transaction| candidate_rows.remove(&transaction.id))
+        .collect();
+    accepted.sort_by_key(|(index, _)| *index);
+
+    Ok(ImportPlan { rows, accepted })
+}
+
+/// Fields that identify a transaction when the broker gives no id.
+fn fingerprint(transaction: &PortfolioTransaction) -> String {
+    format!(
+        "{}|{}|{}|{:.6}|{:.6}|{:.6}|{:.6}",
+        transaction.ticker,
+        transaction.kind,
+        transaction.trade_date,
+        transaction.quantity.unwrap_or_default(),
+        transaction.price.unwrap_or_default(),
+        transaction.amount.unwrap_or_default(),
+        transaction.fees
+    )
+}
+
+fn row_report(
+    line: u64,
+    status: &str,
+    transaction: Option<CreatePortfolioTransactionRequest>,
+    messages: Vec<String>,
+) -> ImportRowReport {
+    ImportRowReport {
+        line,
+        status: status.to_string(),
+        transaction,
+        messages,
+    }
+}
+
+fn report(rows: Vec<ImportRowReport>, committed: bool) -> ImportReport {
+    let count = |status: &str| rows.iter().filter(|row| row.status == status).count();
+    ImportReport {
+        committed,
+        error: None,
+        valid: count(ROW_VALID),
+        imported: count(ROW_IMPORTED),
+        duplicates: count(ROW_DUPLICATE),
+        skipped: count(ROW_SKIPPED),
+        invalid: count(ROW_INVALID),
+        rows,
+    }
+}
+
+fn unreadable(error: ImportError) -> (StatusCode, Json<ImportReport>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ImportReport {
+            error: Some(error.to_string()),
+            ..Default::default()
+        }),
+    )
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use diesel::prelude::*;
+
+use crate::{
+    auth::Claims,
+    database::DbPool,
+    models::{NewRole, NewRolePermission, Permission, Role, RoleResponse, UpdateRoleRequest},
+    permissions::{self, ADMIN_ROLE, BUILT_IN_ROLES},
+    schema::{permissions as permissions_table, role_permissions, roles, users},
+};
+
+const MAX_ROLE_NAME_LEN: usize = 50;
+
+pub async fn list_roles(State(pool): State<DbPool>) -> Result<Json<Vec<RoleResponse>>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let roles_list = roles::table
+        .order(roles::name.asc())
+        .select(Role::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let grants: Vec<(String, String)> = role_permissions::table
+        .order(role_permissions::permission.asc())
+        .select((role_permissions::role, role_permissions::permission))
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut granted: HashMap<String, Vec<String>> = HashMap::new();
+    for (role, permission) in grants {
+        granted.entry(role).or_default().push(permission);
+    }
+    let response = roles_list
+        .into_iter()
+        .map(|role| {
+            let permissions = granted.remove(&role.name).unwrap_or_default();
+            role_response(role, permissions)
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
+pub async fn list_permissions(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<Permission>>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let permissions_list = permissions_table::table
+        .order(permissions_table::name.asc())
+        .select(Permission::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(permissions_list))
+}
+
+/// Create a role or replace its description and permissions. The `admin` role and the caller's
+/// own role cannot be changed, and callers can only change roles to and from admin permissions
+/// they have themselves.
+pub async fn update_role(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(name): Path<String>,
+    Json(request): Json<UpdateRoleRequest>,
+) -> Result<Json<RoleResponse>, StatusCode> {
+    let is_valid_name = !name.is_empty()
+        && name.len() <= MAX_ROLE_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-'));
+    if !is_valid_name {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if name == ADMIN_ROLE {
+        return Err(StatusCode::CONFLICT);
+    }
+    let mut granted = request.permissions;
+    granted.sort();
+    granted.dedup();
+
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let known: i64 = permissions_table::table
+        .filter(permissions_table::name.eq_any(&granted))
+        .count()
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if known != granted.len() as i64 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if name == claims.role {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let caller = permissions::load(&mut conn, &claims.role)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let current =
+        permissions::load(&mut conn, &name).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !permissions::includes_admin_permissions(&caller, &granted)
+        || !permissions::includes_admin_permissions(&caller, &current)
+    {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let role = conn
+        .transaction(|conn| {
+            let role = diesel::insert_into(roles::table)
+                .values(&NewRole {
+                    name: name.clone(),
+                    description: request.description.clone(),
+                })
+                .on_conflict(roles::name)
+                .do_update()
+                .set(roles::description.eq(&request.description))
+                .returning(Role::as_select())
+                .get_result(conn)?;
+
+            diesel::delete(role_permissions::table.filter(role_permissions::role.eq(&name)))
+                .execute(conn)?;
+            let rows: Vec<NewRolePermission> = granted
+                .iter()
+                .map(|permission| NewRolePermission {
+                    role: name.clone(),
+                    permission: permission.clone(),
+                })
+                .collect();
+            diesel::insert_into(role_permissions::table)
+                .values(&rows)
+                .execute(conn)?;
+
+            Ok::<_, diesel::result::Error>(role)
+        })
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    permissions::forget_all();
+
+    Ok(Json(role_response(role, granted)))
+}
+
+/// Delete a role no user has. Built-in roles cannot be deleted.
+pub async fn delete_role(
+    State(pool): State<DbPool>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    if BUILT_IN_ROLES.contains(&name.as_str()) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let members: i64 = users::table
+        .filter(users::role.eq(&name))
+        .count()
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if members > 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let deleted = diesel::delete(roles::table.find(&name))
+        .execute(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if deleted == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    permissions::forget_all();
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn role_response(role: Role, permissions: Vec<String>) -> RoleResponse {
+    RoleResponse {
+        built_in: BUILT_IN_ROLES.contains(&role.name.as_str()),
+        name: role.name,
+        description: role.description,
+        permissions,
+        created_at: role.created_at,
+    }
+}
@@ -0,0 +1,179 @@
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use diesel::dsl::now;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    auth::Claims,
+    database::DbPool,
+    models::{Session, SessionResponse, User},
+    permissions,
+    schema::{sessions, users},
+    sessions as session_store,
+};
+
+/// The caller's live sessions, most recently seen first.
+pub async fn list_sessions(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<SessionResponse>>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let sessions = load_live_sessions(&mut conn, user_id, &claims)?;
+    Ok(Json(sessions))
+}
+
+/// Sign out one of the caller's sessions, including the current one.
+pub async fn revoke_session(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(session_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    revoke_live_session(&mut conn, user_id, session_id)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sign out every session of the caller except the current one.
+pub async fn revoke_other_sessions(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let current_session_id = Uuid::parse_str(&claims.sid).ok();
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let revoked = session_store::revoke_all(&mut conn, user_id, current_session_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    session_store::forget(&revoked);
+
+    Ok(Json(serde_json::json!({ "revoked": revoked.len() })))
+}
+
+pub async fn list_user_sessions(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Vec<SessionResponse>>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    ensure_user_exists(&mut conn, user_id)?;
+    let sessions = load_live_sessions(&mut conn, user_id, &claims)?;
+    Ok(Json(sessions))
+}
+
+pub async fn revoke_user_session(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path((user_id, session_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = find_user(&mut conn, user_id)?;
+    ensure_can_manage(&mut conn, &claims, &user.role)?;
+    revoke_live_session(&mut conn, user_id, session_id)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sign a user out everywhere.
+pub async fn revoke_user_sessions(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = find_user(&mut conn, user_id)?;
+    ensure_can_manage(&mut conn, &claims, &user.role)?;
+    let revoked = session_store::revoke_all(&mut conn, user_id, None)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    session_store::forget(&revoked);
+
+    Ok(Json(serde_json::json!({ "revoked": revoked.len() })))
+}
+
+fn load_live_sessions(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    claims: &Claims,
+) -> Result<Vec<SessionResponse>, StatusCode> {
+    let current_session_id = Uuid::parse_str(&claims.sid).ok();
+
+    let sessions = sessions::table
+        .filter(sessions::user_id.eq(user_id))
+        .filter(sessions::revoked_at.is_null())
+        .filter(sessions::expires_at.gt(now))
+        .order(sessions::last_seen_at.desc())
+        .select(Session::as_select())
+        .load(conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(sessions
+        .into_iter()
+        .map(|session| SessionResponse::new(session, current_session_id))
+        .collect())
+}
+
+fn revoke_live_session(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<(), StatusCode> {
+    let exists: i64 = sessions::table
+        .filter(sessions::id.eq(session_id))
+        .filter(sessions::user_id.eq(user_id))
+        .filter(sessions::revoked_at.is_null())
+        .count()
+        .get_result(conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if exists == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    session_store::revoke(conn, session_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+pub fn ensure_user_exists(conn: &mut PgConnection, user_id: Uuid) -> Result<(), StatusCode> {
+    let exists: i64 = users::table
+        .find(user_id)
+        .count()
+        .get_result(conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if exists == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(())
+}
+
+pub fn find_user(conn: &mut PgConnection, user_id: Uuid) -> Result<User, StatusCode> {
+    users::table
+        .find(user_id)
+        .select(User::as_select())
+        .first(conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Fail with 403 unless the caller's role has every admin permission of `role`, so managing
+/// users cannot be used to gain admin permissions or to act on users with more of them.
+pub fn ensure_can_manage(
+    conn: &mut PgConnection,
+    claims: &Claims,
+    role: &str,
+) -> Result<(), StatusCode> {
+    let granted =
+        permissions::load(conn, &claims.role).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let required = permissions::load(conn, role).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !permissions::includes_admin_permissions(&granted, &required) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(())
+}
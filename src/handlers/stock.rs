@@ -0,0 +1,152 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use diesel::{dsl::now, pg::upsert::excluded, prelude::*};
+
+use crate::{
+    alerts,
+    database::DbPool,
+    models::{
+        FundamentalInput, NewFundamental, NewPriceBar, NewStockProfile, PriceBarInput,
+        StockProfile, StockProfileInput,
+    },
+    schema::{fundamentals, price_bars, stock_profiles},
+    stocks::normalize_ticker,
+};
+
+pub async fn upsert_price_bars(
+    State(pool): State<DbPool>,
+    Path(ticker): Path<String>,
+    Json(bars): Json<Vec<PriceBarInput>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let ticker = normalize_ticker(&ticker).ok_or(StatusCode::BAD_REQUEST)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let new_bars: Vec<NewPriceBar> = bars
+        .into_iter()
+        .map(|bar| NewPriceBar {
+            ticker: ticker.clone(),
+            trade_date: bar.trade_date,
+            open: bar.open,
+            high: bar.high,
+            low: bar.low,
+            close: bar.close,
+            volume: bar.volume,
+        })
+        .collect();
+
+    let upserted = diesel::insert_into(price_bars::table)
+        .values(&new_bars)
+        .on_conflict((price_bars::ticker, price_bars::trade_date))
+        .do_update()
+        .set((
+            price_bars::open.eq(excluded(price_bars::open)),
+            price_bars::high.eq(excluded(price_bars::high)),
+            price_bars::low.eq(excluded(price_bars::low)),
+            price_bars::close.eq(excluded(price_bars::close)),
+            price_bars::volume.eq(excluded(price_bars::volume)),
+        ))
+        .execute(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // The prices are stored either way; a failed evaluation only delays alerts to the next ingestion
+    let alerts_triggered = match alerts::evaluate_ticker(&mut conn, &ticker) {
+        Ok(triggers) => triggers.len(),
+        Err(e) => {
+            tracing::error!("Failed to evaluate alert rules for {}: {}", ticker, e);
+            0
+        }
+    };
+
+    Ok(Json(serde_json::json!({
+        "ticker": ticker,
+        "upserted": upserted,
+        "alerts_triggered": alerts_triggered,
+    })))
+}
+
+pub async fn upsert_fundamentals(
+    State(pool): State<DbPool>,
+    Path(ticker): Path<String>,
+    Json(periods): Json<Vec<FundamentalInput>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let ticker = normalize_ticker(&ticker).ok_or(StatusCode::BAD_REQUEST)?;
+
+    if periods
+        .iter()
+        .any(|p| p.period_type != "annual" && p.period_type != "quarterly")
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let upserted = conn
+        .transaction(|conn| {
+            let mut count = 0;
+            for period in periods {
+                let new_period = NewFundamental {
+                    ticker: ticker.clone(),
+                    period_end: period.period_end,
+                    period_type: period.period_type,
+                    revenue: period.revenue,
+                    net_income: period.net_income,
+                    eps: period.eps,
+                    free_cash_flow: period.free_cash_flow,
+                    book_value_per_share: period.book_value_per_share,
+                    total_assets: period.total_assets,
+                    total_liabilities: period.total_liabilities,
+                    current_assets: period.current_assets,
+                    total_debt: period.total_debt,
+                    shares_outstanding: period.shares_outstanding,
+                    dividends_per_share: period.dividends_per_share,
+                };
+
+                count += diesel::insert_into(fundamentals::table)
+                    .values(&new_period)
+                    .on_conflict((
+                        fundamentals::ticker,
+                        fundamentals::period_end,
+                        fundamentals::period_type,
+                    ))
+                    .do_update()
+                    .set(&new_period)
+                    .execute(conn)?;
+            }
+            diesel::QueryResult::Ok(count)
+        })
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(
+        serde_json::json!({ "ticker": ticker, "upserted": upserted }),
+    ))
+}
+
+/// Replace a ticker's name, sector and industry.
+pub async fn upsert_stock_profile(
+    State(pool): State<DbPool>,
+    Path(ticker): Path<String>,
+    Json(profile): Json<StockProfileInput>,
+) -> Result<Json<StockProfile>, StatusCode> {
+    let ticker = normalize_ticker(&ticker).ok_or(StatusCode::BAD_REQUEST)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let profile = NewStockProfile {
+        ticker,
+        name: profile.name,
+        sector: profile.sector,
+        industry: profile.industry,
+    };
+    let profile = diesel::insert_into(stock_profiles::table)
+        .values(&profile)
+        .on_conflict(stock_profiles::ticker)
+        .do_update()
+        .set((&profile, stock_profiles::updated_at.eq(now)))
+        .returning(StockProfile::as_select())
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(profile))
+}
@@ -1,19 +1,43 @@
 use axum::{
-    extract::{Extension, State},
-    http::StatusCode,
+    extract::{ConnectInfo, Extension, Path, Query, State},
+    http::{HeaderMap, StatusCode},
     response::Json,
 };
+use chrono::Utc;
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use uuid::Uuid;
 
 use crate::{
-    auth::{create_jwt, hash_password, verify_password, Claims},
+    account_tokens::{self, MFA_CHALLENGE_TTL, PURPOSE_MFA_CHALLENGE, PURPOSE_PASSWORD_RESET},
+    api_tokens,
+    auth::{create_jwt, generate_opaque_token, hash_password, verify_password, Claims},
     database::DbPool,
-    models::{CreateUserRequest, LoginRequest, LoginResponse, NewUser, User, UserResponse},
-    schema::users,
+    environments::get_jwt_expiration_minutes,
+    handlers::{
+        account::send_verification_email,
+        session::{ensure_can_manage, find_user},
+    },
+    mailer::{self, Mailer},
+    mfa,
+    models::{
+        CreateUserRequest, LoginRequest, LoginResponse, LoginResult, MfaChallengeResponse,
+        MfaLoginRequest, NewUser, RefreshTokenRequest, UpdateUserRoleRequest, User, UserListQuery,
+        UserListResponse, UserResponse,
+    },
+    permissions::{self, ADMIN_ROLE, DEFAULT_ROLE},
+    refresh_tokens,
+    schema::{roles, users},
+    sessions::{self, ClientInfo},
 };
 
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
 pub async fn register_user(
+    Extension(mailer): Extension<Arc<dyn Mailer>>,
     State(pool): State<DbPool>,
     Json(request): Json<CreateUserRequest>,
 ) -> Result<Json<UserResponse>, StatusCode> {
@@ -41,7 +65,7 @@ pub async fn register_user(
         username: request.username,
         email: request.email,
         password_hash,
-        role: "user".to_string(), // Default role
+        role: DEFAULT_ROLE.to_string(),
     };
 
     let user: User = diesel::insert_into(users::table)
@@ -50,13 +74,20 @@ pub async fn register_user(
         .get_result(&mut conn)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    send_verification_email(&mut conn, mailer, &user)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
     Ok(Json(user.into()))
 }
 
+/// Check the password and start a session, or with two-factor authentication enabled return a
+/// challenge to finish at `/api/auth/mfa`.
 pub async fn login_user(
     State(pool): State<DbPool>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(request): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, StatusCode> {
+) -> Result<Json<LoginResult>, StatusCode> {
     let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // Find user
@@ -77,14 +108,123 @@ pub async fn login_user(
         return Err(StatusCode::UNAUTHORIZED);
     }
 
-    // Create JWT token
-    let token = create_jwt(user.id, &user.username, &user.role)
+    start_login(&mut conn, user, ClientInfo::new(&headers, peer)).map(Json)
+}
+
+/// Start a session for a user who proved who they are, or with two-factor authentication
+/// enabled return a challenge to finish at `/api/auth/mfa` instead.
+pub fn start_login(
+    conn: &mut PgConnection,
+    user: User,
+    client: ClientInfo,
+) -> Result<LoginResult, StatusCode> {
+    let mfa_enabled =
+        mfa::is_enabled(conn, user.id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if mfa_enabled {
+        let mfa_token = account_tokens::issue(conn, &user, PURPOSE_MFA_CHALLENGE)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        return Ok(LoginResult::MfaRequired(MfaChallengeResponse {
+            mfa_required: true,
+            mfa_token,
+            expires_in: MFA_CHALLENGE_TTL.num_seconds(),
+        }));
+    }
+
+    // Every login starts a new session with its own refresh token family
+    let (session_id, refresh_token) =
+        sessions::start(conn, user.id, client).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    login_response(user, session_id, refresh_token).map(LoginResult::Tokens)
+}
+
+/// Finish a login with the challenge token from `/api/auth/login` and a code from the
+/// authenticator app or a recovery code. The challenge works once, so a wrong code means
+/// logging in again.
+pub async fn verify_mfa_login(
+    State(pool): State<DbPool>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<MfaLoginRequest>,
+) -> Result<Json<LoginResponse>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let challenge = account_tokens::consume(&mut conn, PURPOSE_MFA_CHALLENGE, &request.mfa_token)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let user = users::table
+        .find(challenge.user_id)
+        .filter(users::is_active.eq(true))
+        .select(User::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let is_valid = mfa::verify(&mut conn, user.id, &request.code)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !is_valid {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let (session_id, refresh_token) =
+        sessions::start(&mut conn, user.id, ClientInfo::new(&headers, peer))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    login_response(user, session_id, refresh_token).map(Json)
+}
 
-    Ok(Json(LoginResponse {
+/// Exchange a refresh token for a new access token and refresh token. The presented refresh
+/// token stops working; presenting it again revokes every token issued from the same login.
+pub async fn refresh_token(
+    State(pool): State<DbPool>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<RefreshTokenRequest>,
+) -> Result<Json<LoginResponse>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (user, session_id, refresh_token) = sessions::refresh(
+        &mut conn,
+        &request.refresh_token,
+        ClientInfo::new(&headers, peer),
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    login_response(user, session_id, refresh_token).map(Json)
+}
+
+/// End the session of a refresh token, revoking its refresh and access tokens.
+pub async fn logout(
+    State(pool): State<DbPool>,
+    Json(request): Json<RefreshTokenRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Unknown tokens are not reported so logout cannot be used to probe for valid ones
+    let session_id = refresh_tokens::family_of(&mut conn, &request.refresh_token)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(session_id) = session_id {
+        sessions::revoke(&mut conn, session_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn login_response(
+    user: User,
+    session_id: Uuid,
+    refresh_token: String,
+) -> Result<LoginResponse, StatusCode> {
+    let token = create_jwt(user.id, &user.username, &user.role, session_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(LoginResponse {
         token,
+        expires_in: get_jwt_expiration_minutes() * 60,
+        refresh_token,
         user: user.into(),
-    }))
+    })
 }
 
 pub async fn get_current_user(
@@ -106,22 +246,242 @@ pub async fn get_current_user(
     Ok(Json(user.into()))
 }
 
+/// Users ordered by username, filtered by `search`, `role` and `is_active`.
 pub async fn list_users(
+    State(pool): State<DbPool>,
+    Query(query): Query<UserListQuery>,
+) -> Result<Json<UserListResponse>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let total: i64 = filtered_users(&query)
+        .count()
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let users_list = filtered_users(&query)
+        .order(users::username.asc())
+        .limit(
+            query
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        )
+        .offset(query.offset.unwrap_or(0).max(0))
+        .select(User::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(UserListResponse {
+        total,
+        users: users_list.into_iter().map(UserResponse::from).collect(),
+    }))
+}
+
+pub async fn get_user(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<UserResponse>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = find_user(&mut conn, user_id)?;
+    Ok(Json(user.into()))
+}
+
+/// Give a user another role. A user whose role changes is signed out everywhere, so access
+/// tokens carrying the old role stop working. Fails with 409 if it would leave no active admin.
+pub async fn update_user_role(
     Extension(claims): Extension<Claims>,
     State(pool): State<DbPool>,
-) -> Result<Json<Vec<UserResponse>>, StatusCode> {
-    // Only admin can list users
-    if claims.role != "admin" {
-        return Err(StatusCode::FORBIDDEN);
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<UpdateUserRoleRequest>,
+) -> Result<Json<UserResponse>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let role_exists: i64 = roles::table
+        .find(&request.role)
+        .count()
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if role_exists == 0 {
+        return Err(StatusCode::BAD_REQUEST);
     }
+    let user = find_user(&mut conn, user_id)?;
+    ensure_can_manage(&mut conn, &claims, &user.role)?;
+    ensure_can_manage(&mut conn, &claims, &request.role)?;
+
+    let (user, revoked) = conn
+        .transaction(|conn| {
+            if request.role != ADMIN_ROLE && permissions::is_last_active_admin(conn, user.id)? {
+                return Ok(None);
+            }
+            if request.role == user.role {
+                return Ok(Some((user, Vec::new())));
+            }
+            let user = diesel::update(users::table.find(user.id))
+                .set((
+                    users::role.eq(&request.role),
+                    users::updated_at.eq(Utc::now().naive_utc()),
+                ))
+                .returning(User::as_select())
+                .get_result(conn)?;
+            let revoked = sessions::revoke_all(conn, user.id, None)?;
+            Ok(Some((user, revoked)))
+        })
+        .map_err(|_: diesel::result::Error| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::CONFLICT)?;
+    sessions::forget(&revoked);
+
+    Ok(Json(user.into()))
+}
 
+pub async fn activate_user(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<UserResponse>, StatusCode> {
     let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let users_list = users::table
-        .select(User::as_select())
-        .load(&mut conn)
+    let user = find_user(&mut conn, user_id)?;
+    ensure_can_manage(&mut conn, &claims, &user.role)?;
+
+    let user = diesel::update(users::table.find(user.id))
+        .set((
+            users::is_active.eq(true),
+            users::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .returning(User::as_select())
+        .get_result(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(user.into()))
+}
+
+/// Lock a user out: logins fail and every session and API token stops working. Fails with 409
+/// for the last active admin.
+pub async fn deactivate_user(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<UserResponse>, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = find_user(&mut conn, user_id)?;
+    ensure_can_manage(&mut conn, &claims, &user.role)?;
+
+    let (user, revoked) = conn
+        .transaction(|conn| {
+            if permissions::is_last_active_admin(conn, user.id)? {
+                return Ok(None);
+            }
+            let user = diesel::update(users::table.find(user.id))
+                .set((
+                    users::is_active.eq(false),
+                    users::updated_at.eq(Utc::now().naive_utc()),
+                ))
+                .returning(User::as_select())
+                .get_result(conn)?;
+            let revoked = sessions::revoke_all(conn, user.id, None)?;
+            Ok(Some((user, revoked)))
+        })
+        .map_err(|_: diesel::result::Error| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::CONFLICT)?;
+    sessions::forget(&revoked);
+
+    Ok(Json(user.into()))
+}
+
+/// Replace a user's password with a random one, sign them out everywhere, revoke their API
+/// tokens and email them a password reset link. Fails with 409 for inactive users, who cannot
+/// reset their password.
+pub async fn force_password_reset(
+    Extension(claims): Extension<Claims>,
+    Extension(mailer): Extension<Arc<dyn Mailer>>,
+    State(pool): State<DbPool>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = find_user(&mut conn, user_id)?;
+    ensure_can_manage(&mut conn, &claims, &user.role)?;
+    if !user.is_active {
+        return Err(StatusCode::CONFLICT);
+    }
+    let password_hash =
+        hash_password(&generate_opaque_token()).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (token, revoked) = conn
+        .transaction(|conn| {
+            diesel::update(users::table.find(user.id))
+                .set((
+                    users::password_hash.eq(&password_hash),
+                    users::updated_at.eq(Utc::now().naive_utc()),
+                ))
+                .execute(conn)?;
+            let revoked = sessions::revoke_all(conn, user.id, None)?;
+            api_tokens::revoke_all(conn, user.id)?;
+            let token = account_tokens::issue(conn, &user, PURPOSE_PASSWORD_RESET)?;
+            Ok::<_, diesel::result::Error>((token, revoked))
+        })
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sessions::forget(&revoked);
+    mailer::send_in_background(mailer, account_tokens::password_reset_email(&user, &token));
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Delete a user and everything they own. Fails with 409 for the last active admin.
+pub async fn delete_user(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let response: Vec<UserResponse> = users_list.into_iter().map(|u| u.into()).collect();
-    Ok(Json(response))
+    let user = find_user(&mut conn, user_id)?;
+    ensure_can_manage(&mut conn, &claims, &user.role)?;
+
+    let revoked = conn
+        .transaction(|conn| {
+            if permissions::is_last_active_admin(conn, user.id)? {
+                return Ok(None);
+            }
+            // Revoking first reports the sessions whose checks must be dropped from the cache
+            let revoked = sessions::revoke_all(conn, user.id, None)?;
+            diesel::delete(users::table.find(user.id)).execute(conn)?;
+            Ok(Some(revoked))
+        })
+        .map_err(|_: diesel::result::Error| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::CONFLICT)?;
+    sessions::forget(&revoked);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn filtered_users(query: &UserListQuery) -> users::BoxedQuery<'_, Pg> {
+    let mut statement = users::table.into_boxed();
+    if let Some(search) = query
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+    {
+        let pattern = format!(
+            "%{}%",
+            search
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        statement = statement.filter(
+            users::username
+                .ilike(pattern.clone())
+                .or(users::email.ilike(pattern)),
+        );
+    }
+    if let Some(role) = &query.role {
+        statement = statement.filter(users::role.eq(role));
+    }
+    if let Some(is_active) = query.is_active {
+        statement = statement.filter(users::is_active.eq(is_active));
+    }
+    statement
 }
@@ -0,0 +1,390 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::NaiveDateTime;
+use diesel::dsl::{count_star, now};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    auth::Claims,
+    database::DbPool,
+    handlers::{conflict_or_internal, validate_name},
+    models::{
+        CreateWatchlistItemRequest, CreateWatchlistRequest, NewWatchlist, NewWatchlistItem,
+        ReorderWatchlistRequest, UpdateWatchlistItemRequest, UpdateWatchlistRequest, Watchlist,
+        WatchlistItem, WatchlistItemView, WatchlistResponse, WatchlistSummary,
+    },
+    schema::{analyses, watchlist_items, watchlists},
+    stocks::{self, normalize_ticker},
+};
+
+
+pub async fn list_watchlists(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<WatchlistSummary>>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let lists = watchlists::table
+        .filter(watchlists::user_id.eq(user_id))
+        .order(watchlists::created_at.asc())
+        .select(Watchlist::as_select())
+        .load(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let ids: Vec<Uuid> = lists.iter().map(|list| list.id).collect();
+    let counts: HashMap<Uuid, i64> = watchlist_items::table
+        .filter(watchlist_items::watchlist_id.eq_any(&ids))
+        .group_by(watchlist_items::watchlist_id)
+        .select((watchlist_items::watchlist_id, count_star()))
+        .load::<(Uuid, i64)>(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .collect();
+
+    let response = lists
+        .into_iter()
+        .map(|list| WatchlistSummary {
+            item_count: counts.get(&list.id).copied().unwrap_or(0),
+            id: list.id,
+            name: list.name,
+            description: list.description,
+            created_at: list.created_at,
+            updated_at: list.updated_at,
+        })
+        .collect();
+    Ok(Json(response))
+}
+
+pub async fn create_watchlist(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Json(request): Json<CreateWatchlistRequest>,
+) -> Result<(StatusCode, Json<WatchlistResponse>), StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let name = validate_name(&request.name)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let list: Watchlist = diesel::insert_into(watchlists::table)
+        .values(&NewWatchlist {
+            user_id,
+            name,
+            description: request.description,
+        })
+        .returning(Watchlist::as_select())
+        .get_result(&mut conn)
+        .map_err(conflict_or_internal)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(watchlist_response(list, Vec::new())),
+    ))
+}
+
+/// The watchlist with each item's latest price, daily change and the user's last verdict.
+pub async fn get_watchlist(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(watchlist_id): Path<Uuid>,
+) -> Result<Json<WatchlistResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let list = find_watchlist(&mut conn, user_id, watchlist_id)?;
+    let items = load_items(&mut conn, user_id, list.id)?;
+
+    Ok(Json(watchlist_response(list, items)))
+}
+
+pub async fn update_watchlist(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(watchlist_id): Path<Uuid>,
+    Json(mut request): Json<UpdateWatchlistRequest>,
+) -> Result<Json<WatchlistResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(name) = &request.name {
+        request.name = Some(validate_name(name)?);
+    }
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let list: Watchlist = diesel::update(
+        watchlists::table
+            .filter(watchlists::id.eq(watchlist_id))
+            .filter(watchlists::user_id.eq(user_id)),
+    )
+    .set((&request, watchlists::updated_at.eq(now)))
+    .returning(Watchlist::as_select())
+    .get_result(&mut conn)
+    .optional()
+    .map_err(conflict_or_internal)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    let items = load_items(&mut conn, user_id, list.id)?;
+
+    Ok(Json(watchlist_response(list, items)))
+}
+
+pub async fn delete_watchlist(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(watchlist_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let deleted_count = diesel::delete(
+        watchlists::table
+            .filter(watchlists::id.eq(watchlist_id))
+            .filter(watchlists::user_id.eq(user_id)),
+    )
+    .execute(&mut conn)
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted_count == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Add a ticker at the end of the watchlist.
+pub async fn add_watchlist_item(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(watchlist_id): Path<Uuid>,
+    Json(request): Json<CreateWatchlistItemRequest>,
+) -> Result<(StatusCode, Json<WatchlistItemView>), StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let ticker = normalize_ticker(&request.ticker).ok_or(StatusCode::BAD_REQUEST)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let list = find_watchlist(&mut conn, user_id, watchlist_id)?;
+    let last_position: Option<i32> = watchlist_items::table
+        .filter(watchlist_items::watchlist_id.eq(list.id))
+        .select(diesel::dsl::max(watchlist_items::position))
+        .first(&mut conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let item: WatchlistItem = diesel::insert_into(watchlist_items::table)
+        .values(&NewWatchlistItem {
+            watchlist_id: list.id,
+            ticker,
+            notes: request.notes,
+            position: last_position.map_or(0, |position| position + 1),
+        })
+        .returning(WatchlistItem::as_select())
+        .get_result(&mut conn)
+        .map_err(conflict_or_internal)?;
+
+    let view =
+        item_view(&mut conn, user_id, item).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((StatusCode::CREATED, Json(view)))
+}
+
+/// Replace an item's notes; `null` clears them.
+pub async fn update_watchlist_item(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path((watchlist_id, ticker)): Path<(Uuid, String)>,
+    Json(request): Json<UpdateWatchlistItemRequest>,
+) -> Result<Json<WatchlistItemView>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let ticker = normalize_ticker(&ticker).ok_or(StatusCode::NOT_FOUND)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let list = find_watchlist(&mut conn, user_id, watchlist_id)?;
+    let item: WatchlistItem = diesel::update(
+        watchlist_items::table
+            .filter(watchlist_items::watchlist_id.eq(list.id))
+            .filter(watchlist_items::ticker.eq(ticker)),
+    )
+    .set((
+        watchlist_items::notes.eq(request.notes),
+        watchlist_items::updated_at.eq(now),
+    ))
+    .returning(WatchlistItem::as_select())
+    .get_result(&mut conn)
+    .optional()
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let view =
+        item_view(&mut conn, user_id, item).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(view))
+}
+
+pub async fn remove_watchlist_item(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path((watchlist_id, ticker)): Path<(Uuid, String)>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let ticker = normalize_ticker(&ticker).ok_or(StatusCode::NOT_FOUND)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let list = find_watchlist(&mut conn, user_id, watchlist_id)?;
+    let deleted_count = diesel::delete(
+        watchlist_items::table
+            .filter(watchlist_items::watchlist_id.eq(list.id))
+            .filter(watchlist_items::ticker.eq(ticker)),
+    )
+    .execute(&mut conn)
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted_count == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Reorder items. The request must list every ticker on the watchlist exactly once.
+pub async fn reorder_watchlist(
+    Extension(claims): Extension<Claims>,
+    State(pool): State<DbPool>,
+    Path(watchlist_id): Path<Uuid>,
+    Json(request): Json<ReorderWatchlistRequest>,
+) -> Result<Json<WatchlistResponse>, StatusCode> {
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let tickers = request
+        .tickers
+        .iter()
+        .map(|ticker| normalize_ticker(ticker))
+        .collect::<Option<Vec<String>>>()
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let list = find_watchlist(&mut conn, user_id, watchlist_id)?;
+    conn.transaction(|conn| {
+        let current: HashSet<String> = watchlist_items::table
+            .filter(watchlist_items::watchlist_id.eq(list.id))
+            .select(watchlist_items::ticker)
+            .for_update()
+            .load::<String>(conn)?
+            .into_iter()
+            .collect();
+        let requested: HashSet<&String> = tickers.iter().collect();
+        if requested.len() != tickers.len()
+            || requested.len() != current.len()
+            || !requested.iter().all(|ticker| current.contains(*ticker))
+        {
+            return Ok(Err(StatusCode::BAD_REQUEST));
+        }
+
+        for (position, ticker) in tickers.iter().enumerate() {
+            diesel::update(
+                watchlist_items::table
+                    .filter(watchlist_items::watchlist_id.eq(list.id))
+                    .filter(watchlist_items::ticker.eq(ticker)),
+            )
+            .set((
+                watchlist_items::position.eq(position as i32),
+                watchlist_items::updated_at.eq(now),
+            ))
+            .execute(conn)?;
+        }
+
+        diesel::QueryResult::Ok(Ok(()))
+    })
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+
+    let items = load_items(&mut conn, user_id, list.id)?;
+    Ok(Json(watchlist_response(list, items)))
+}
+
+fn find_watchlist(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    watchlist_id: Uuid,
+) -> Result<Watchlist, StatusCode> {
+    watchlists::table
+        .filter(watchlists::id.eq(watchlist_id))
+        .filter(watchlists::user_id.eq(user_id))
+        .select(Watchlist::as_select())
+        .first(conn)
+        .optional()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+fn load_items(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    watchlist_id: Uuid,
+) -> Result<Vec<WatchlistItemView>, StatusCode> {
+    let items = watchlist_items::table
+        .filter(watchlist_items::watchlist_id.eq(watchlist_id))
+        .order((
+            watchlist_items::position.asc(),
+            watchlist_items::created_at.asc(),
+        ))
+        .select(WatchlistItem::as_select())
+        .load(conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    items
+        .into_iter()
+        .map(|item| item_view(conn, user_id, item))
+        .collect::<QueryResult<_>>()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Add the latest close, the change from the previous close and the user's latest analysis.
+fn item_view(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    item: WatchlistItem,
+) -> QueryResult<WatchlistItemView> {
+    let bars = stocks::load_price_history(conn, &item.ticker, 2)?;
+    let latest = bars.first();
+    let previous_close = bars.get(1).map(|bar| bar.close);
+    let daily_change = latest
+        .zip(previous_close)
+        .map(|(latest, previous)| latest.close - previous);
+    let daily_change_percent = previous_close
+        .zip(daily_change)
+        .filter(|(previous, _)| *previous != 0.0)
+        .map(|(previous, change)| change / previous * 100.0);
+
+    let last_analysis: Option<(Uuid, Option<String>, NaiveDateTime)> = analyses::table
+        .filter(analyses::user_id.eq(user_id))
+        .filter(analyses::ticker.eq(&item.ticker))
+        .order(analyses::created_at.desc())
+        .select((analyses::id, analyses::verdict, analyses::created_at))
+        .first(conn)
+        .optional()?;
+
+    Ok(WatchlistItemView {
+        price_date: latest.map(|bar| bar.trade_date),
+        last_price: latest.map(|bar| bar.close),
+        daily_change,
+        daily_change_percent,
+        last_analysis_id: last_analysis.as_ref().map(|(id, ..)| *id),
+        last_verdict: last_analysis
+            .as_ref()
+            .and_then(|(_, verdict, _)| verdict.clone()),
+        last_analyzed_at: last_analysis.map(|(.., created_at)| created_at),
+        ticker: item.ticker,
+        notes: item.notes,
+        position: item.position,
+        added_at: item.created_at,
+    })
+}
+
+fn watchlist_response(list: Watchlist, items: Vec<WatchlistItemView>) -> WatchlistResponse {
+    WatchlistResponse {
+        id: list.id,
+        name: list.name,
+        description: list.description,
+        items,
+        created_at: list.created_at,
+        updated_at: list.updated_at,
+    }
+}
+
@@ -0,0 +1,341 @@
+use std::time::{Duration, Instant};
+
+use diesel::dsl::{now, IntervalDsl};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    analysis::{
+        perform_analysis, record_usage, save_analysis, AnalysisError, REQUEST_TYPE_STOCK_ANALYSIS,
+    },
+    committee::execute_committee_job,
+    database::DbPool,
+    mcp::client::McpClient,
+    models::{Agent, AnalysisJob, LlmProvider},
+    notifications::{self, KIND_JOB_FAILED, KIND_JOB_SUCCEEDED},
+    schema::{agents, analysis_jobs, llm_providers},
+};
+
+pub const STATUS_QUEUED: &str = "queued";
+pub const STATUS_RUNNING: &str = "running";
+pub const STATUS_SUCCEEDED: &str = "succeeded";
+pub const STATUS_FAILED: &str = "failed";
+pub const STATUS_CANCELLED: &str = "cancelled";
+
+pub const KIND_ANALYSIS: &str = "analysis";
+pub const KIND_COMMITTEE: &str = "committee";
+
+/// How long an idle worker waits before polling the queue again.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How often each worker looks for jobs abandoned by a crashed process.
+const STALE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Running jobs older than this are assumed to belong to a worker that died.
+const STALE_JOB_MINUTES: i32 = 15;
+/// Retry delay is `RETRY_BASE_DELAY_SECS * 2^(attempt - 1)`, capped at `RETRY_MAX_DELAY_SECS`.
+const RETRY_BASE_DELAY_SECS: i32 = 30;
+const RETRY_MAX_DELAY_SECS: i32 = 600;
+
+/// Start `count` queue workers on the current Tokio runtime.
+///
+/// Jobs with `use_tools` set give their agents the tools of `mcp`.
+pub fn spawn_workers(pool: DbPool, mcp: McpClient, count: usize) {
+    for worker_id in 0..count {
+        tokio::spawn(worker_loop(pool.clone(), mcp.clone(), worker_id));
+    }
+    tracing::info!("Started {} analysis workers", count);
+}
+
+async fn worker_loop(pool: DbPool, mcp: McpClient, worker_id: usize) {
+    let mut last_stale_check: Option<Instant> = None;
+
+    loop {
+        if last_stale_check.is_none_or(|checked| checked.elapsed() >= STALE_CHECK_INTERVAL) {
+            last_stale_check = Some(Instant::now());
+            match pool.get().map(|mut conn| requeue_stale_jobs(&mut conn)) {
+                Ok(Ok(0)) => {}
+                Ok(Ok(count)) => tracing::warn!("Recovered {} stale analysis jobs", count),
+                Ok(Err(e)) => tracing::error!("Failed to recover stale analysis jobs: {}", e),
+                Err(e) => tracing::error!("Worker {} could not get a connection: {}", worker_id, e),
+            }
+        }
+
+        let claimed = match pool.get() {
+            Ok(mut conn) => claim_next_job(&mut conn),
+            Err(e) => {
+                tracing::error!("Worker {} could not get a connection: {}", worker_id, e);
+                tokio::time::sleep(POLL_INTERVAL).await;
+                continue;
+            }
+        };
+
+        match claimed {
+            Ok(Some(job)) => {
+                tracing::info!(
+                    "Worker {} running analysis job {} ({}, attempt {})",
+                    worker_id,
+                    job.id,
+                    job.ticker,
+                    job.attempts
+                );
+                run_job(&pool, &mcp, job).await;
+            }
+            Ok(None) => tokio::time::sleep(POLL_INTERVAL).await,
+            Err(e) => {
+                tracing::error!("Worker {} failed to claim a job: {}", worker_id, e);
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// Lock the oldest due job with `FOR UPDATE SKIP LOCKED` and mark it running.
+pub fn claim_next_job(conn: &mut PgConnection) -> QueryResult<Option<AnalysisJob>> {
+    conn.transaction(|conn| {
+        let job_id = analysis_jobs::table
+            .filter(analysis_jobs::status.eq(STATUS_QUEUED))
+            .filter(analysis_jobs::run_after.le(now))
+            .order((
+                analysis_jobs::run_after.asc(),
+                analysis_jobs::created_at.asc(),
+            ))
+            .select(analysis_jobs::id)
+            .for_update()
+            .skip_locked()
+            .first::<Uuid>(conn)
+            .optional()?;
+
+        let Some(job_id) = job_id else {
+            return Ok(None);
+        };
+
+        diesel::update(analysis_jobs::table.find(job_id))
+            .set((
+                analysis_jobs::status.eq(STATUS_RUNNING),
+                analysis_jobs::attempts.eq(analysis_jobs::attempts + 1),
+                analysis_jobs::started_at.eq(now),
+                analysis_jobs::updated_at.eq(now),
+            ))
+            .returning(AnalysisJob::as_select())
+            .get_result(conn)
+            .map(Some)
+    })
+}
+
+/// Put jobs left running by a crashed worker back on the queue, or fail them when they
+/// have no attempts left.
+pub fn requeue_stale_jobs(conn: &mut PgConnection) -> QueryResult<usize> {
+    let stale = analysis_jobs::table
+        .filter(analysis_jobs::status.eq(STATUS_RUNNING))
+        .filter(analysis_jobs::started_at.lt((now - STALE_JOB_MINUTES.minutes()).nullable()));
+
+    let failed = conn.transaction(|conn| {
+        let failed: Vec<AnalysisJob> =
+            diesel::update(stale.filter(analysis_jobs::attempts.ge(analysis_jobs::max_attempts)))
+                .set((
+                    analysis_jobs::status.eq(STATUS_FAILED),
+                    analysis_jobs::last_error.eq("worker stopped before the job finished"),
+                    analysis_jobs::finished_at.eq(now),
+                    analysis_jobs::updated_at.eq(now),
+                ))
+                .returning(AnalysisJob::as_select())
+                .get_results(conn)?;
+        for job in &failed {
+            notify_finished(conn, job, Some("worker stopped before the job finished"))?;
+        }
+        QueryResult::Ok(failed.len())
+    })?;
+
+    let requeued = diesel::update(stale)
+        .set((
+            analysis_jobs::status.eq(STATUS_QUEUED),
+            analysis_jobs::last_error.eq("worker stopped before the job finished"),
+            analysis_jobs::run_after.eq(now),
+            analysis_jobs::updated_at.eq(now),
+        ))
+        .execute(conn)?;
+
+    Ok(failed + requeued)
+}
+
+async fn run_job(pool: &DbPool, mcp: &McpClient, job: AnalysisJob) {
+    let mcp = job.use_tools.then_some(mcp);
+    let result = match job.kind.as_str() {
+        KIND_COMMITTEE => execute_committee_job(pool, mcp, &job).await,
+        _ => execute_job(pool, mcp, &job).await,
+    };
+
+    let recorded = match &result {
+        Ok(()) => Ok(()),
+        Err(error) => {
+            tracing::warn!("Analysis job {} failed: {}", job.id, error);
+            pool.get()
+                .map_err(AnalysisError::from)
+                .and_then(|mut conn| Ok(fail_or_retry(&mut conn, &job, error)?))
+        }
+    };
+
+    if let Err(e) = recorded {
+        tracing::error!("Failed to record outcome of analysis job {}: {}", job.id, e);
+    }
+}
+
+async fn execute_job(
+    pool: &DbPool,
+    mcp: Option<&McpClient>,
+    job: &AnalysisJob,
+) -> Result<(), AnalysisError> {
+    let (agent, provider) = {
+        let mut conn = pool.get()?;
+        let agent = agents::table
+            .find(job.agent_id)
+            .select(Agent::as_select())
+            .first(&mut conn)?;
+        let provider = llm_providers::table
+            .find(job.provider_id)
+            .select(LlmProvider::as_select())
+            .first(&mut conn)?;
+        (agent, provider)
+    };
+
+    let completed = perform_analysis(
+        pool,
+        job.user_id,
+        &agent,
+        &provider,
+        &job.ticker,
+        REQUEST_TYPE_STOCK_ANALYSIS,
+        mcp,
+    )
+    .await?;
+
+    let mut conn = pool.get()?;
+    conn.transaction(|conn| {
+        let status: String = analysis_jobs::table
+            .find(job.id)
+            .select(analysis_jobs::status)
+            .for_update()
+            .first(conn)?;
+
+        // Cancelled while the model was working: keep the usage, drop the result
+        if status != STATUS_RUNNING {
+            record_usage(
+                conn,
+                provider.id,
+                job.user_id,
+                completed.output.tokens_used,
+                REQUEST_TYPE_STOCK_ANALYSIS,
+            )?;
+            return Ok(());
+        }
+
+        save_analysis(
+            conn,
+            Some(job.id),
+            job.user_id,
+            &agent,
+            &provider,
+            completed,
+            REQUEST_TYPE_STOCK_ANALYSIS,
+        )?;
+
+        diesel::update(analysis_jobs::table.find(job.id))
+            .set((
+                analysis_jobs::status.eq(STATUS_SUCCEEDED),
+                analysis_jobs::last_error.eq(None::<String>),
+                analysis_jobs::finished_at.eq(now),
+                analysis_jobs::updated_at.eq(now),
+            ))
+            .execute(conn)?;
+        notify_finished(conn, job, None)?;
+
+        diesel::QueryResult::Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// Schedule another attempt with exponential backoff, or fail the job for good.
+fn fail_or_retry(
+    conn: &mut PgConnection,
+    job: &AnalysisJob,
+    error: &AnalysisError,
+) -> QueryResult<()> {
+    let retryable = !matches!(error, AnalysisError::NoMarketData(_));
+    let running_job = analysis_jobs::table
+        .filter(analysis_jobs::id.eq(job.id))
+        .filter(analysis_jobs::status.eq(STATUS_RUNNING));
+
+    if retryable && job.attempts < job.max_attempts {
+        let delay = RETRY_BASE_DELAY_SECS
+            .saturating_mul(1 << (job.attempts - 1).clamp(0, 16))
+            .min(RETRY_MAX_DELAY_SECS);
+
+        diesel::update(running_job)
+            .set((
+                analysis_jobs::status.eq(STATUS_QUEUED),
+                analysis_jobs::last_error.eq(error.to_string()),
+                analysis_jobs::run_after.eq(now + delay.seconds()),
+                analysis_jobs::updated_at.eq(now),
+            ))
+            .execute(conn)?;
+    } else {
+        conn.transaction(|conn| {
+            let failed = diesel::update(running_job)
+                .set((
+                    analysis_jobs::status.eq(STATUS_FAILED),
+                    analysis_jobs::last_error.eq(error.to_string()),
+                    analysis_jobs::finished_at.eq(now),
+                    analysis_jobs::updated_at.eq(now),
+                ))
+                .execute(conn)?;
+            // Not when the job was cancelled while it ran
+            if failed > 0 {
+                notify_finished(conn, job, Some(&error.to_string()))?;
+            }
+            QueryResult::Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Tell the job's owner it has finished for good, in the transaction that records it.
+pub(crate) fn notify_finished(
+    conn: &mut PgConnection,
+    job: &AnalysisJob,
+    error: Option<&str>,
+) -> QueryResult<()> {
+    let subject = match job.kind.as_str() {
+        KIND_COMMITTEE => "Committee review",
+        _ => "Analysis",
+    };
+    let (kind, title, body) = match error {
+        None => (
+            KIND_JOB_SUCCEEDED,
+            format!("{} of {} is ready", subject, job.ticker),
+            format!("{} of {} finished successfully.", subject, job.ticker),
+        ),
+        Some(error) => (
+            KIND_JOB_FAILED,
+            format!("{} of {} failed", subject, job.ticker),
+            format!(
+                "{} of {} failed after {} attempts: {}",
+                subject, job.ticker, job.attempts, error
+            ),
+        ),
+    };
+
+    notifications::notify(
+        conn,
+        job.user_id,
+        kind,
+        title,
+        body,
+        serde_json::json!({
+            "job_id": job.id,
+            "job_kind": job.kind,
+            "ticker": job.ticker,
+        }),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,342 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    errors::ErrorKind,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use simple_asn1::{oid, ASN1Block, OID};
+
+use crate::environments;
+
+static KEYS: OnceLock<JwtKeys> = OnceLock::new();
+
+#[derive(Debug, thiserror::Error)]
+pub enum JwtKeyError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{path}: {reason}")]
+    InvalidKey { path: PathBuf, reason: String },
+    #[error("JWT_SECRET must be set when no JWT_PRIVATE_KEY_PATH is configured")]
+    MissingSecret,
+}
+
+/// Where the signing and verification keys come from.
+pub struct JwtKeyConfig {
+    /// PEM RSA or Ed25519 private key that signs tokens with RS256 or EdDSA. Tokens are
+    /// signed with HS256 and `secret` without one.
+    pub private_key_path: Option<PathBuf>,
+    /// PEM public keys whose tokens are still accepted, e.g. the previous signing key while
+    /// its tokens expire after a rotation.
+    pub public_key_paths: Vec<PathBuf>,
+    pub secret: Option<String>,
+}
+
+impl JwtKeyConfig {
+    pub fn from_env() -> Self {
+        Self {
+            private_key_path: environments::get_jwt_private_key_path(),
+            public_key_paths: environments::get_jwt_public_key_paths(),
+            secret: environments::get_jwt_secret(),
+        }
+    }
+}
+
+struct VerificationKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+/// The key that signs access tokens and every key that verifies them, by `kid`.
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    kid: Option<String>,
+    signing_key: EncodingKey,
+    verification_keys: Vec<VerificationKey>,
+    jwks: JwkSet,
+}
+
+impl JwtKeys {
+    pub fn load(config: &JwtKeyConfig) -> Result<Self, JwtKeyError> {
+        let Some(private_key_path) = &config.private_key_path else {
+            let secret = config.secret.as_deref().ok_or(JwtKeyError::MissingSecret)?;
+            return Ok(Self {
+                algorithm: Algorithm::HS256,
+                kid: None,
+                signing_key: EncodingKey::from_secret(secret.as_bytes()),
+                verification_keys: vec![VerificationKey {
+                    kid: None,
+                    algorithm: Algorithm::HS256,
+                    key: DecodingKey::from_secret(secret.as_bytes()),
+                }],
+                // A shared secret is never published
+                jwks: JwkSet { keys: Vec::new() },
+            });
+        };
+
+        let (signing_key, signing_jwk) = read_private_key(private_key_path)?;
+        let algorithm = jwk_algorithm(&signing_jwk);
+        let kid = signing_jwk.common.key_id.clone();
+
+        let mut keys = vec![(private_key_path, signing_jwk)];
+        for path in &config.public_key_paths {
+            let jwk = read_public_key(path)?;
+            if !keys
+                .iter()
+                .any(|(_, known)| known.common.key_id == jwk.common.key_id)
+            {
+                keys.push((path, jwk));
+            }
+        }
+        let verification_keys = keys
+            .iter()
+            .map(|(path, jwk)| {
+                Ok(VerificationKey {
+                    kid: jwk.common.key_id.clone(),
+                    algorithm: jwk_algorithm(jwk),
+                    key: DecodingKey::from_jwk(jwk)
+                        .map_err(|error| invalid_key(path, error.to_string()))?,
+                })
+            })
+            .collect::<Result<Vec<_>, JwtKeyError>>()?;
+
+        Ok(Self {
+            algorithm,
+            kid,
+            signing_key,
+            verification_keys,
+            jwks: JwkSet {
+                keys: keys.into_iter().map(|(_, jwk)| jwk).collect(),
+            },
+        })
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> jsonwebtoken::errors::Result<String> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.kid.clone();
+        encode(&header, claims, &self.signing_key)
+    }
+
+    /// Verify a token with the key named by its `kid` header.
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> jsonwebtoken::errors::Result<T> {
+        let header = decode_header(token)?;
+        let key = self
+            .verification_keys
+            .iter()
+            .find(|key| key.kid == header.kid)
+            .ok_or(ErrorKind::InvalidToken)?;
+
+        decode::<T>(token, &key.key, &Validation::new(key.algorithm)).map(|data| data.claims)
+    }
+
+    /// Public verification keys for `/.well-known/jwks.json`; empty with HS256.
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+/// Load the keys configured in the environment. Called at startup so that bad keys stop the
+/// server instead of failing its first login.
+pub fn init() -> Result<&'static JwtKeys, JwtKeyError> {
+    if let Some(keys) = KEYS.get() {
+        return Ok(keys);
+    }
+    let keys = JwtKeys::load(&JwtKeyConfig::from_env())?;
+    Ok(KEYS.get_or_init(|| keys))
+}
+
+pub fn keys() -> &'static JwtKeys {
+    init().expect("Invalid JWT key configuration")
+}
+
+/// Public parts of a key, from which its JWK is built.
+enum PublicKey {
+    Rsa { modulus: Vec<u8>, exponent: Vec<u8> },
+    Ed25519 { x: Vec<u8> },
+}
+
+fn rsa_encryption() -> OID {
+    oid!(1, 2, 840, 113549, 1, 1, 1)
+}
+
+fn ed25519() -> OID {
+    oid!(1, 3, 101, 112)
+}
+
+fn read_private_key(path: &Path) -> Result<(EncodingKey, Jwk), JwtKeyError> {
+    let (pem, bytes) = read_pem(path)?;
+    let public_key = private_key_public_part(&pem).map_err(|reason| invalid_key(path, reason))?;
+
+    let signing_key = match public_key {
+        PublicKey::Rsa { .. } => EncodingKey::from_rsa_pem(&bytes),
+        PublicKey::Ed25519 { .. } => EncodingKey::from_ed_pem(&bytes),
+    }
+    .map_err(|error| invalid_key(path, error.to_string()))?;
+
+    Ok((signing_key, to_jwk(public_key)))
+}
+
+fn read_public_key(path: &Path) -> Result<Jwk, JwtKeyError> {
+    let (pem, _) = read_pem(path)?;
+    let public_key = public_key_info(&pem).map_err(|reason| invalid_key(path, reason))?;
+
+    Ok(to_jwk(public_key))
+}
+
+/// The public key of a PKCS#1 RSA or PKCS#8 RSA or Ed25519 private key.
+fn private_key_public_part(pem: &pem::Pem) -> Result<PublicKey, String> {
+    match pem.tag() {
+        "RSA PRIVATE KEY" => return rsa_public_key(pem.contents()),
+        "PRIVATE KEY" => {}
+        tag => return Err(format!("expected a PEM private key, found {}", tag)),
+    }
+
+    let blocks = parse_der(pem.contents())?;
+    let Some([_, ASN1Block::Sequence(_, algorithm), ASN1Block::OctetString(_, key), ..]) =
+        sequence(&blocks)
+    else {
+        return Err("malformed PKCS#8 private key".to_string());
+    };
+    match algorithm.first() {
+        Some(ASN1Block::ObjectIdentifier(_, oid)) if oid == rsa_encryption() => rsa_public_key(key),
+        Some(ASN1Block::ObjectIdentifier(_, oid)) if oid == ed25519() => {
+            let pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(pem.contents())
+                .map_err(|error| error.to_string())?;
+            Ok(PublicKey::Ed25519 {
+                x: pair.public_key().as_ref().to_vec(),
+            })
+        }
+        _ => Err("only RSA and Ed25519 keys are supported".to_string()),
+    }
+}
+
+/// A PKCS#1 RSA or SubjectPublicKeyInfo RSA or Ed25519 public key.
+fn public_key_info(pem: &pem::Pem) -> Result<PublicKey, String> {
+    match pem.tag() {
+        "RSA PUBLIC KEY" => return rsa_public_key(pem.contents()),
+        "PUBLIC KEY" => {}
+        tag => return Err(format!("expected a PEM public key, found {}", tag)),
+    }
+
+    let blocks = parse_der(pem.contents())?;
+    let Some([ASN1Block::Sequence(_, algorithm), ASN1Block::BitString(_, _, key)]) =
+        sequence(&blocks)
+    else {
+        return Err("malformed public key".to_string());
+    };
+    match algorithm.first() {
+        Some(ASN1Block::ObjectIdentifier(_, oid)) if oid == rsa_encryption() => rsa_public_key(key),
+        Some(ASN1Block::ObjectIdentifier(_, oid)) if oid == ed25519() => {
+            Ok(PublicKey::Ed25519 { x: key.clone() })
+        }
+        _ => Err("only RSA and Ed25519 keys are supported".to_string()),
+    }
+}
+
+fn read_pem(path: &Path) -> Result<(pem::Pem, Vec<u8>), JwtKeyError> {
+    let bytes = fs::read(path).map_err(|source| JwtKeyError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let pem = pem::parse(&bytes).map_err(|error| invalid_key(path, error.to_string()))?;
+    Ok((pem, bytes))
+}
+
+fn parse_der(der: &[u8]) -> Result<Vec<ASN1Block>, String> {
+    simple_asn1::from_der(der).map_err(|error| error.to_string())
+}
+
+/// The fields of a DER document that is a single sequence.
+fn sequence(blocks: &[ASN1Block]) -> Option<&[ASN1Block]> {
+    match blocks {
+        [ASN1Block::Sequence(_, fields)] => Some(fields),
+        _ => None,
+    }
+}
+
+fn invalid_key(path: &Path, reason: String) -> JwtKeyError {
+    JwtKeyError::InvalidKey {
+        path: path.to_path_buf(),
+        reason,
+    }
+}
+
+/// Modulus and public exponent of a PKCS#1 RSA private or public key, which both start
+/// with them (after the version of a private key).
+fn rsa_public_key(der: &[u8]) -> Result<PublicKey, String> {
+    let blocks = parse_der(der)?;
+    let (modulus, exponent) = match sequence(&blocks) {
+        Some(
+            [ASN1Block::Integer(_, _), ASN1Block::Integer(_, n), ASN1Block::Integer(_, e), _, ..],
+        ) => (n, e),
+        Some([ASN1Block::Integer(_, n), ASN1Block::Integer(_, e)]) => (n, e),
+        _ => return Err("malformed RSA key".to_string()),
+    };
+
+    Ok(PublicKey::Rsa {
+        modulus: modulus.to_bytes_be().1,
+        exponent: exponent.to_bytes_be().1,
+    })
+}
+
+/// The JWK of a public key, identified by its RFC 7638 thumbprint.
+fn to_jwk(public_key: PublicKey) -> Jwk {
+    let (key_algorithm, thumbprint_input, algorithm) = match public_key {
+        PublicKey::Rsa { modulus, exponent } => {
+            let n = URL_SAFE_NO_PAD.encode(modulus);
+            let e = URL_SAFE_NO_PAD.encode(exponent);
+            (
+                KeyAlgorithm::RS256,
+                format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, e, n),
+                AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n,
+                    e,
+                }),
+            )
+        }
+        PublicKey::Ed25519 { x } => {
+            let x = URL_SAFE_NO_PAD.encode(x);
+            (
+                KeyAlgorithm::EdDSA,
+                format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, x),
+                AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x,
+                }),
+            )
+        }
+    };
+
+    Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(URL_SAFE_NO_PAD.encode(Sha256::digest(thumbprint_input))),
+            ..Default::default()
+        },
+        algorithm,
+    }
+}
+
+fn jwk_algorithm(jwk: &Jwk) -> Algorithm {
+    match jwk.algorithm {
+        AlgorithmParameters::OctetKeyPair(_) => Algorithm::EdDSA,
+        _ => Algorithm::RS256,
+    }
+}
@@ -0,0 +1,33 @@
+pub mod account_tokens;
+pub mod alerts;
+pub mod analysis;
+pub mod api_tokens;
+pub mod auth;
+pub mod committee;
+pub mod comparison;
+pub mod csv_import;
+pub mod database;
+pub mod environments;
+pub mod handlers;
+pub mod jobs;
+pub mod jwt_keys;
+pub mod llm;
+pub mod mailer;
+pub mod mcp;
+pub mod mfa;
+pub mod middleware;
+pub mod models;
+pub mod notifications;
+pub mod oidc;
+pub mod permissions;
+pub mod portfolio;
+pub mod refresh_tokens;
+pub mod risk;
+pub mod routes;
+pub mod schema;
+pub mod sessions;
+pub mod stocks;
+pub mod structured_output;
+pub mod tool_calling;
+pub mod ttl_cache;
+pub mod valuation;
@@ -0,0 +1,614 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{handlers::llm_provider::decrypt_api_key, models::LlmProvider};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(180);
+const MAX_OUTPUT_TOKENS: u32 = 4096;
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Keywords of JSON schema that Gemini's OpenAPI-style function parameters accept.
+const GEMINI_SCHEMA_KEYWORDS: [&str; 11] = [
+    "type",
+    "description",
+    "nullable",
+    "enum",
+    "properties",
+    "required",
+    "items",
+    "minItems",
+    "maxItems",
+    "minimum",
+    "maximum",
+];
+
+#[derive(Debug, Error)]
+pub enum LlmError {
+    #[error("unsupported provider type: {0}")]
+    UnsupportedProvider(String),
+    #[error("stored API key could not be decoded")]
+    InvalidApiKey,
+    #[error("request to provider failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("provider returned {status}: {body}")]
+    Api { status: u16, body: String },
+    #[error("unexpected provider response: {0}")]
+    InvalidResponse(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderType {
+    OpenAi,
+    Anthropic,
+    Gemini,
+}
+
+impl ProviderType {
+    pub fn parse(value: &str) -> Result<Self, LlmError> {
+        match value.to_ascii_lowercase().as_str() {
+            "openai" => Ok(Self::OpenAi),
+            "anthropic" => Ok(Self::Anthropic),
+            "gemini" => Ok(Self::Gemini),
+            other => Err(LlmError::UnsupportedProvider(other.to_string())),
+        }
+    }
+
+    fn default_endpoint(self) -> &'static str {
+        match self {
+            Self::OpenAi => "https://api.openai.com/v1",
+            Self::Anthropic => "https://api.anthropic.com/v1",
+            Self::Gemini => "https://generativelanguage.googleapis.com/v1beta",
+        }
+    }
+
+    fn default_model(self) -> &'static str {
+        match self {
+            Self::OpenAi => "gpt-4o",
+            Self::Anthropic => "claude-3-5-sonnet-latest",
+            Self::Gemini => "gemini-1.5-pro",
+        }
+    }
+}
+
+/// Text returned by a provider together with the tokens it billed for the call.
+#[derive(Debug, Clone)]
+pub struct LlmCompletion {
+    pub text: String,
+    pub tool_calls: Vec<ToolCall>,
+    pub tokens_used: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
+    Tool,
+}
+
+/// A tool the model may call, described by a JSON schema for its arguments.
+#[derive(Debug, Clone)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+}
+
+/// Identifies the call a `Role::Tool` message answers.
+#[derive(Debug, Clone)]
+pub struct ToolResult {
+    pub call_id: String,
+    pub name: String,
+    pub is_error: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+    /// Calls requested by an assistant message.
+    pub tool_calls: Vec<ToolCall>,
+    /// Set on `Role::Tool` messages, whose `content` is the tool output.
+    pub tool_result: Option<ToolResult>,
+}
+
+impl ChatMessage {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_result: None,
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_result: None,
+        }
+    }
+
+    pub fn assistant_tool_calls(content: impl Into<String>, tool_calls: Vec<ToolCall>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: content.into(),
+            tool_calls,
+            tool_result: None,
+        }
+    }
+
+    pub fn tool_result(call: &ToolCall, content: impl Into<String>, is_error: bool) -> Self {
+        Self {
+            role: Role::Tool,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_result: Some(ToolResult {
+                call_id: call.id.clone(),
+                name: call.name.clone(),
+                is_error,
+            }),
+        }
+    }
+}
+
+/// JSON schema the model must answer with, using the provider's native structured output.
+#[derive(Debug, Clone, Copy)]
+pub struct ResponseSchema<'a> {
+    pub name: &'a str,
+    pub schema: &'a Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatRequest<'a> {
+    pub system: &'a str,
+    pub messages: &'a [ChatMessage],
+    /// Tools the model may call. With a `response_schema` they are only declared, so that
+    /// earlier calls in `messages` stay valid, and the model is made to answer instead.
+    pub tools: &'a [ToolDefinition],
+    pub response_schema: Option<ResponseSchema<'a>>,
+}
+
+/// Thin HTTP client for a configured LLM provider.
+pub struct LlmClient {
+    http: reqwest::Client,
+    provider_type: ProviderType,
+    api_key: String,
+    endpoint: String,
+    model: String,
+}
+
+impl LlmClient {
+    /// Build a client from a stored provider, falling back to the provider's default
+    /// endpoint and model when they are not configured.
+    pub fn from_provider(provider: &LlmProvider) -> Result<Self, LlmError> {
+        let provider_type = ProviderType::parse(&provider.provider_type)?;
+        let api_key =
+            decrypt_api_key(&provider.api_key_encrypted).ok_or(LlmError::InvalidApiKey)?;
+
+        let endpoint = provider
+            .api_endpoint
+            .as_deref()
+            .filter(|endpoint| !endpoint.is_empty())
+            .unwrap_or(provider_type.default_endpoint())
+            .trim_end_matches('/')
+            .to_string();
+        let model = provider
+            .model_name
+            .as_deref()
+            .filter(|model| !model.is_empty())
+            .unwrap_or(provider_type.default_model())
+            .to_string();
+
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        Ok(Self {
+            http,
+            provider_type,
+            api_key,
+            endpoint,
+            model,
+        })
+    }
+
+    /// Send a conversation and return the model's next message.
+    ///
+    /// With a `response_schema` the returned text is the JSON document produced by the
+    /// provider's structured output mode (a forced tool call for Anthropic). Otherwise the
+    /// model may answer with `tool_calls` for the caller to run.
+    pub async fn chat(&self, request: &ChatRequest<'_>) -> Result<LlmCompletion, LlmError> {
+        match self.provider_type {
+            ProviderType::OpenAi => self.chat_openai(request).await,
+            ProviderType::Anthropic => self.chat_anthropic(request).await,
+            ProviderType::Gemini => self.chat_gemini(request).await,
+        }
+    }
+
+    async fn chat_openai(&self, request: &ChatRequest<'_>) -> Result<LlmCompletion, LlmError> {
+        let mut messages = vec![json!({ "role": "system", "content": request.system })];
+        messages.extend(request.messages.iter().map(|message| match message.role {
+            Role::User => json!({ "role": "user", "content": message.content }),
+            Role::Assistant if message.tool_calls.is_empty() => {
+                json!({ "role": "assistant", "content": message.content })
+            }
+            Role::Assistant => json!({
+                "role": "assistant",
+                "content": (!message.content.is_empty()).then_some(&message.content),
+                "tool_calls": message.tool_calls.iter().map(|call| json!({
+                    "id": call.id,
+                    "type": "function",
+                    "function": { "name": call.name, "arguments": call.arguments.to_string() },
+                })).collect::<Vec<_>>(),
+            }),
+            Role::Tool => json!({
+                "role": "tool",
+                "tool_call_id": message.tool_result.as_ref().map(|result| &result.call_id),
+                "content": message.content,
+            }),
+        }));
+
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+        });
+        if !request.tools.is_empty() {
+            body["tools"] = request
+                .tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": tool.name,
+                            "description": tool.description,
+                            "parameters": tool.parameters,
+                        },
+                    })
+                })
+                .collect();
+            if request.response_schema.is_some() {
+                body["tool_choice"] = json!("none");
+            }
+        }
+        if let Some(schema) = request.response_schema {
+            body["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": { "name": schema.name, "schema": schema.schema },
+            });
+        }
+
+        let request = self
+            .http
+            .post(format!("{}/chat/completions", self.endpoint))
+            .bearer_auth(&self.api_key)
+            .json(&body);
+        let response = send(request).await?;
+
+        let message = &response["choices"][0]["message"];
+        let tool_calls: Vec<ToolCall> = message["tool_calls"]
+            .as_array()
+            .map(|calls| {
+                calls
+                    .iter()
+                    .map(|call| ToolCall {
+                        id: call["id"].as_str().unwrap_or_default().to_string(),
+                        name: call["function"]["name"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        arguments: parse_arguments(&call["function"]["arguments"]),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let text = match message["content"].as_str() {
+            Some(content) => content.to_string(),
+            None if !tool_calls.is_empty() => String::new(),
+            None => {
+                return Err(LlmError::InvalidResponse(
+                    "missing choices[0].message.content".into(),
+                ))
+            }
+        };
+        let tokens_used = token_count(&response["usage"]["total_tokens"]);
+
+        Ok(LlmCompletion {
+            text,
+            tool_calls,
+            tokens_used,
+        })
+    }
+
+    async fn chat_anthropic(&self, request: &ChatRequest<'_>) -> Result<LlmCompletion, LlmError> {
+        let mut messages: Vec<Value> = Vec::new();
+        for message in request.messages {
+            match message.role {
+                Role::User => messages.push(json!({ "role": "user", "content": message.content })),
+                Role::Assistant => {
+                    let mut blocks = Vec::new();
+                    if !message.content.is_empty() {
+                        blocks.push(json!({ "type": "text", "text": message.content }));
+                    }
+                    blocks.extend(message.tool_calls.iter().map(|call| {
+                        json!({
+                            "type": "tool_use",
+                            "id": call.id,
+                            "name": call.name,
+                            "input": call.arguments,
+                        })
+                    }));
+                    messages.push(json!({ "role": "assistant", "content": blocks }));
+                }
+                Role::Tool => {
+                    let result = message.tool_result.as_ref();
+                    let block = json!({
+                        "type": "tool_result",
+                        "tool_use_id": result.map(|result| &result.call_id),
+                        "content": message.content,
+                        "is_error": result.is_some_and(|result| result.is_error),
+                    });
+                    // Results of parallel calls go back together in a single user turn
+                    match messages.last_mut() {
+                        Some(last) if last["role"] == "user" && last["content"].is_array() => {
+                            last["content"].as_array_mut().unwrap().push(block);
+                        }
+                        _ => messages.push(json!({ "role": "user", "content": [block] })),
+                    }
+                }
+            }
+        }
+
+        let mut body = json!({
+            "model": self.model,
+            "max_tokens": MAX_OUTPUT_TOKENS,
+            "system": request.system,
+            "messages": messages,
+        });
+        let mut tools: Vec<Value> = request
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "input_schema": tool.parameters,
+                })
+            })
+            .collect();
+        if let Some(schema) = request.response_schema {
+            tools.push(json!({
+                "name": schema.name,
+                "description": "Submit the final answer in the required structure.",
+                "input_schema": schema.schema,
+            }));
+            body["tool_choice"] = json!({ "type": "tool", "name": schema.name });
+        }
+        if !tools.is_empty() {
+            body["tools"] = Value::Array(tools);
+        }
+        let schema_name = request.response_schema.map(|schema| schema.name);
+
+        let request = self
+            .http
+            .post(format!("{}/messages", self.endpoint))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&body);
+        let response = send(request).await?;
+
+        let blocks = response["content"]
+            .as_array()
+            .ok_or_else(|| LlmError::InvalidResponse("missing content blocks".into()))?;
+        let tool_uses = blocks.iter().filter(|block| block["type"] == "tool_use");
+        let answer = schema_name
+            .and_then(|name| tool_uses.clone().find(|tool_use| tool_use["name"] == name));
+
+        let (text, tool_calls) = match answer {
+            Some(tool_use) => (tool_use["input"].to_string(), Vec::new()),
+            None => (
+                blocks
+                    .iter()
+                    .filter(|block| block["type"] == "text")
+                    .filter_map(|block| block["text"].as_str())
+                    .collect::<Vec<_>>()
+                    .join(""),
+                tool_uses
+                    .map(|tool_use| ToolCall {
+                        id: tool_use["id"].as_str().unwrap_or_default().to_string(),
+                        name: tool_use["name"].as_str().unwrap_or_default().to_string(),
+                        arguments: tool_use["input"].clone(),
+                    })
+                    .collect(),
+            ),
+        };
+        let tokens_used = token_count(&response["usage"]["input_tokens"])
+            + token_count(&response["usage"]["output_tokens"]);
+
+        Ok(LlmCompletion {
+            text,
+            tool_calls,
+            tokens_used,
+        })
+    }
+
+    async fn chat_gemini(&self, request: &ChatRequest<'_>) -> Result<LlmCompletion, LlmError> {
+        let mut contents: Vec<Value> = Vec::new();
+        for message in request.messages {
+            match message.role {
+                Role::User => contents.push(json!({
+                    "role": "user",
+                    "parts": [{ "text": message.content }],
+                })),
+                Role::Assistant => {
+                    let mut parts = Vec::new();
+                    if !message.content.is_empty() {
+                        parts.push(json!({ "text": message.content }));
+                    }
+                    parts.extend(message.tool_calls.iter().map(|call| {
+                        json!({ "functionCall": { "name": call.name, "args": call.arguments } })
+                    }));
+                    contents.push(json!({ "role": "model", "parts": parts }));
+                }
+                Role::Tool => {
+                    let result = message.tool_result.as_ref();
+                    let key = if result.is_some_and(|result| result.is_error) {
+                        "error"
+                    } else {
+                        "result"
+                    };
+                    let part = json!({
+                        "functionResponse": {
+                            "name": result.map(|result| &result.name),
+                            "response": { key: message.content },
+                        },
+                    });
+                    // Responses to parallel calls go back together in a single turn
+                    match contents.last_mut() {
+                        Some(last) if last["parts"][0].get("functionResponse").is_some() => {
+                            last["parts"].as_array_mut().unwrap().push(part);
+                        }
+                        _ => contents.push(json!({ "role": "user", "parts": [part] })),
+                    }
+                }
+            }
+        }
+
+        let mut body = json!({
+            "systemInstruction": { "parts": [{ "text": request.system }] },
+            "contents": contents,
+        });
+        if !request.tools.is_empty() {
+            body["tools"] = json!([{
+                "functionDeclarations": request.tools.iter().map(|tool| json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": gemini_schema(&tool.parameters),
+                })).collect::<Vec<_>>(),
+            }]);
+        }
+        match request.response_schema {
+            // Gemini rejects a JSON response type alongside function declarations, so the
+            // answer is requested as plain text and validated by the caller
+            Some(_) if !request.tools.is_empty() => {
+                body["toolConfig"] = json!({ "functionCallingConfig": { "mode": "NONE" } });
+            }
+            Some(schema) => {
+                body["generationConfig"] = json!({
+                    "responseMimeType": "application/json",
+                    "responseSchema": schema.schema,
+                });
+            }
+            None => {}
+        }
+
+        let request = self
+            .http
+            .post(format!(
+                "{}/models/{}:generateContent",
+                self.endpoint, self.model
+            ))
+            .query(&[("key", &self.api_key)])
+            .json(&body);
+        let response = send(request).await?;
+
+        let parts = response["candidates"][0]["content"]["parts"]
+            .as_array()
+            .ok_or_else(|| LlmError::InvalidResponse("missing candidates[0].content".into()))?;
+        let text = parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("");
+        let tool_calls = parts
+            .iter()
+            .filter_map(|part| part.get("functionCall"))
+            .map(|call| ToolCall {
+                // Older Gemini models do not return call ids
+                id: call["id"]
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| Uuid::new_v4().to_string()),
+                name: call["name"].as_str().unwrap_or_default().to_string(),
+                arguments: call["args"].clone(),
+            })
+            .collect();
+        let tokens_used = token_count(&response["usageMetadata"]["totalTokenCount"]);
+
+        Ok(LlmCompletion {
+            text,
+            tool_calls,
+            tokens_used,
+        })
+    }
+}
+
+async fn send(request: reqwest::RequestBuilder) -> Result<Value, LlmError> {
+    let response = request.send().await?;
+    let status = response.status();
+
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(LlmError::Api {
+            status: status.as_u16(),
+            body,
+        });
+    }
+
+    Ok(response.json().await?)
+}
+
+/// OpenAI sends tool arguments as a JSON string. Unparseable arguments are passed through as
+/// a string so the tool rejects them and the model sees why.
+fn parse_arguments(arguments: &Value) -> Value {
+    match arguments.as_str() {
+        Some(arguments) => {
+            serde_json::from_str(arguments).unwrap_or_else(|_| Value::String(arguments.to_string()))
+        }
+        None => arguments.clone(),
+    }
+}
+
+/// Reduce a JSON schema to the keywords Gemini function declarations accept.
+fn gemini_schema(schema: &Value) -> Value {
+    let Some(object) = schema.as_object() else {
+        return schema.clone();
+    };
+
+    let reduced = object
+        .iter()
+        .filter(|(keyword, _)| GEMINI_SCHEMA_KEYWORDS.contains(&keyword.as_str()))
+        .map(|(keyword, value)| {
+            let value = match keyword.as_str() {
+                "properties" => Value::Object(
+                    value
+                        .as_object()
+                        .into_iter()
+                        .flatten()
+                        .map(|(name, property)| (name.clone(), gemini_schema(property)))
+                        .collect(),
+                ),
+                "items" => gemini_schema(value),
+                _ => value.clone(),
+            };
+            (keyword.clone(), value)
+        })
+        .collect();
+
+    Value::Object(reduced)
+}
+
+fn token_count(value: &Value) -> i32 {
+    value.as_i64().unwrap_or(0) as i32
+}
@@ -0,0 +1,233 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::Utc;
+use futures::future::BoxFuture;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use uuid::Uuid;
+
+/// A plain text email.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MailError {
+    /// Sending again later may succeed, e.g. the server was unreachable.
+    #[error("{0}")]
+    Temporary(String),
+    /// Sending again will fail the same way, e.g. the address was rejected.
+    #[error("{0}")]
+    Permanent(String),
+}
+
+/// Sends notification and account emails.
+pub trait Mailer: Send + Sync {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), MailError>>;
+}
+
+/// How email leaves the server.
+pub enum MailTransport {
+    Smtp(SmtpConfig),
+    /// Write each email as an `.eml` file into a directory, for local development.
+    File(PathBuf),
+    /// Write each email to the log, for local development.
+    Log,
+    /// Every email fails permanently.
+    Disabled,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SmtpSecurity {
+    /// Plain connection upgraded with STARTTLS, usually on port 587.
+    StartTls,
+    /// TLS from the first byte, usually on port 465.
+    Tls,
+    /// No encryption, for local relays and test sinks.
+    None,
+}
+
+impl SmtpSecurity {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "starttls" => Some(Self::StartTls),
+            "tls" => Some(Self::Tls),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// Defaults to the usual port of `security`.
+    pub port: Option<u16>,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Build the mailer for `transport`. Emails are sent from `from`; a mailer that cannot be
+/// built is logged and replaced by a disabled one.
+pub fn build(transport: MailTransport, from: &str) -> Arc<dyn Mailer> {
+    let from: Mailbox = match from.parse() {
+        Ok(from) => from,
+        Err(e) => {
+            tracing::error!("Email disabled, invalid sender address {}: {}", from, e);
+            return Arc::new(DisabledMailer);
+        }
+    };
+
+    match transport {
+        MailTransport::Smtp(config) => match SmtpMailer::new(config, from) {
+            Ok(mailer) => Arc::new(mailer),
+            Err(e) => {
+                tracing::error!("Email disabled: {}", e);
+                Arc::new(DisabledMailer)
+            }
+        },
+        MailTransport::File(dir) => Arc::new(FileMailer { dir, from }),
+        MailTransport::Log => Arc::new(LogMailer),
+        MailTransport::Disabled => Arc::new(DisabledMailer),
+    }
+}
+
+/// Send `email` on a background task and log the outcome, for requests that must not wait
+/// for the mail server or reveal whether anything was sent.
+pub fn send_in_background(mailer: Arc<dyn Mailer>, email: Email) {
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(&email).await {
+            tracing::error!("Failed to send \"{}\" email: {}", email.subject, e);
+        }
+    });
+}
+
+struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    fn new(config: SmtpConfig, from: Mailbox) -> Result<Self, String> {
+        let builder = match config.security {
+            SmtpSecurity::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+                    .map_err(|e| e.to_string())?
+            }
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)
+                .map_err(|e| e.to_string())?,
+            SmtpSecurity::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+            }
+        };
+        let builder = match config.port {
+            Some(port) => builder.port(port),
+            None => builder,
+        };
+        let builder = match (config.username, config.password) {
+            (Some(username), Some(password)) => {
+                builder.credentials(Credentials::new(username, password))
+            }
+            _ => builder,
+        };
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), MailError>> {
+        Box::pin(async move {
+            let message = build_message(&self.from, email)?;
+            self.transport.send(message).await.map_err(|e| {
+                if e.is_permanent() {
+                    MailError::Permanent(e.to_string())
+                } else {
+                    MailError::Temporary(e.to_string())
+                }
+            })?;
+            Ok(())
+        })
+    }
+}
+
+struct FileMailer {
+    dir: PathBuf,
+    from: Mailbox,
+}
+
+impl Mailer for FileMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), MailError>> {
+        Box::pin(async move {
+            let message = build_message(&self.from, email)?;
+            let path = self.dir.join(format!(
+                "{}-{}.eml",
+                Utc::now().format("%Y%m%dT%H%M%S%.3f"),
+                Uuid::new_v4().simple()
+            ));
+
+            tokio::fs::create_dir_all(&self.dir)
+                .await
+                .map_err(|e| MailError::Temporary(e.to_string()))?;
+            tokio::fs::write(&path, message.formatted())
+                .await
+                .map_err(|e| MailError::Temporary(e.to_string()))?;
+            tracing::info!("Wrote email to {} to {}", email.to, path.display());
+            Ok(())
+        })
+    }
+}
+
+struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), MailError>> {
+        Box::pin(async move {
+            tracing::info!(
+                "Email to {}\nSubject: {}\n\n{}",
+                email.to,
+                email.subject,
+                email.body
+            );
+            Ok(())
+        })
+    }
+}
+
+struct DisabledMailer;
+
+impl Mailer for DisabledMailer {
+    fn send<'a>(&'a self, _email: &'a Email) -> BoxFuture<'a, Result<(), MailError>> {
+        Box::pin(async {
+            Err(MailError::Permanent(
+                "email delivery is not configured".to_string(),
+            ))
+        })
+    }
+}
+
+fn build_message(from: &Mailbox, email: &Email) -> Result<Message, MailError> {
+    let to: Mailbox = email
+        .to
+        .parse()
+        .map_err(|e| MailError::Permanent(format!("invalid email address: {}", e)))?;
+
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(&email.subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(email.body.clone())
+        .map_err(|e| MailError::Permanent(e.to_string()))
+}
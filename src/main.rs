@@ -1,19 +1,20 @@
-mod auth;
-mod database;
-mod environments;
-mod handlers;
-mod middleware;
-mod models;
-mod routes;
-mod schema;
-
 use axum::Router;
 use diesel_migrations::{FileBasedMigrations, MigrationHarness};
 use std::net::SocketAddr;
+use std::time::Duration;
 use tower_http::cors::CorsLayer;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{self, fmt, EnvFilter};
 
+use r_stock_analyzer::{
+    database, environments, jobs, jwt_keys,
+    mailer::{self, MailTransport, SmtpConfig, SmtpSecurity},
+    mcp::client::{McpClient, McpClientConfig},
+    notifications::{self, DispatcherConfig},
+    oidc::{OidcClient, OidcConfig},
+    routes,
+};
+
 #[tokio::main]
 async fn main() {
     // Load environment variables
@@ -26,6 +27,12 @@ async fn main() {
         .with(fmt::layer().with_target(false))
         .init();
 
+    // Load the keys that sign and verify access tokens
+    if let Err(e) = jwt_keys::init() {
+        tracing::error!("Failed to load JWT keys: {}", e);
+        return;
+    }
+
     // Create database connection pool
     let pool = database::create_connection_pool();
 
@@ -42,8 +49,69 @@ async fn main() {
         }
     }
 
+    // Start and supervise the MCP server
+    let mcp_client = McpClient::spawn(McpClientConfig {
+        program: environments::get_mcp_server_command(),
+        args: Vec::new(),
+        call_timeout: Duration::from_secs(environments::get_mcp_call_timeout_secs()),
+    });
+
+    // Start background analysis workers
+    jobs::spawn_workers(
+        pool.clone(),
+        mcp_client.clone(),
+        environments::get_analysis_worker_count(),
+    );
+
+    // Set up outgoing email for notifications and account emails
+    let transport = match environments::get_mail_transport().as_deref() {
+        Some("smtp") => {
+            let security = environments::get_smtp_security();
+            match (environments::get_smtp_host(), SmtpSecurity::parse(&security)) {
+                (Some(host), Some(security)) => MailTransport::Smtp(SmtpConfig {
+                    host,
+                    port: environments::get_smtp_port(),
+                    security,
+                    username: environments::get_smtp_username(),
+                    password: environments::get_smtp_password(),
+                }),
+                (None, _) => {
+                    tracing::error!("SMTP_HOST is not set, email disabled");
+                    MailTransport::Disabled
+                }
+                (_, None) => {
+                    tracing::error!("Unknown SMTP_SECURITY {}, email disabled", security);
+                    MailTransport::Disabled
+                }
+            }
+        }
+        Some("file") => MailTransport::File(environments::get_mail_file_dir()),
+        Some("log") => MailTransport::Log,
+        Some("disabled") => MailTransport::Disabled,
+        Some(transport) => {
+            tracing::error!("Unknown MAIL_TRANSPORT {}, email disabled", transport);
+            MailTransport::Disabled
+        }
+        None => MailTransport::Disabled,
+    };
+    let mailer = mailer::build(transport, &environments::get_smtp_from());
+
+    // Start delivering queued email and webhook notifications
+    notifications::spawn_dispatcher(
+        pool.clone(),
+        DispatcherConfig {
+            mailer: mailer.clone(),
+        },
+    );
+
+    // Single sign-on through an OpenID Connect provider, when configured
+    let oidc = OidcConfig::from_env().map(|config| {
+        tracing::info!("OpenID Connect sign-in enabled with {}", config.issuer);
+        OidcClient::new(config)
+    });
+
     // Create application router
-    let mut app = Router::new().merge(routes::create_routes(pool));
+    let mut app = Router::new().merge(routes::create_routes(pool, mcp_client, mailer, oidc));
     if is_development {
         // Enable CORS in development mode, permitting all origins
         app = app.layer(CorsLayer::permissive());
@@ -57,7 +125,7 @@ async fn main() {
         .await
         .expect("Failed to bind to address");
 
-    axum::serve(listener, app)
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .expect("Server failed to start");
 }
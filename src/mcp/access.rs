@@ -0,0 +1,29 @@
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+
+use crate::{models::McpToolAccess, schema::mcp_tool_access};
+
+/// Roles allowed to call restricted tools. Tools without a rule are open to every role.
+pub struct ToolAccess {
+    rules: HashMap<String, Vec<String>>,
+}
+
+impl ToolAccess {
+    pub fn load(conn: &mut PgConnection) -> QueryResult<Self> {
+        let rules = mcp_tool_access::table
+            .select(McpToolAccess::as_select())
+            .load(conn)?
+            .into_iter()
+            .map(|rule| (rule.tool_name, rule.allowed_roles))
+            .collect();
+
+        Ok(Self { rules })
+    }
+
+    pub fn allows(&self, tool: &str, role: &str) -> bool {
+        self.rules
+            .get(tool)
+            .is_none_or(|roles| roles.iter().any(|allowed| allowed == role))
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::{oneshot, watch};
+
+use crate::mcp::{
+    protocol::{CallToolResult, Request, Response, RpcError, Tool},
+    PROTOCOL_VERSION, SUPPORTED_PROTOCOL_VERSIONS,
+};
+
+const CLIENT_NAME: &str = "r-stock-analyzer-backend";
+/// Delay before the first restart of a crashed server, doubled on every consecutive crash.
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(30);
+/// A server that stayed up this long is considered healthy and resets the restart delay.
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub enum McpError {
+    #[error("MCP server is not running")]
+    NotConnected,
+    #[error("MCP request {0} timed out")]
+    Timeout(String),
+    #[error("MCP server exited before answering")]
+    Disconnected,
+    #[error("MCP server returned an error: {0}")]
+    Rpc(#[from] RpcError),
+    #[error("MCP server returned an invalid response: {0}")]
+    InvalidResponse(String),
+    #[error("MCP transport error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct McpClientConfig {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// Longest time a single request may take, handshake included.
+    pub call_timeout: Duration,
+}
+
+#[derive(Debug, Serialize)]
+pub struct McpStatus {
+    pub connected: bool,
+    pub restarts: u64,
+    pub protocol_version: Option<String>,
+    pub tools: Vec<String>,
+}
+
+/// Handle to a supervised MCP server child process. Cheap to clone.
+#[derive(Clone)]
+pub struct McpClient {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    config: McpClientConfig,
+    next_id: AtomicU64,
+    restarts: AtomicU64,
+    /// The current session, `None` while the server is starting or restarting.
+    session: watch::Sender<Option<Arc<Session>>>,
+}
+
+/// A running server that completed the handshake.
+struct Session {
+    connection: Arc<Connection>,
+    protocol_version: String,
+    tools: Vec<Tool>,
+}
+
+struct Connection {
+    stdin: tokio::sync::Mutex<ChildStdin>,
+    /// Requests waiting for a response, by JSON-RPC id.
+    pending: Mutex<HashMap<u64, oneshot::Sender<Response>>>,
+}
+
+impl McpClient {
+    /// Start the server in the background and keep it running for the life of the runtime.
+    pub fn spawn(config: McpClientConfig) -> Self {
+        let (session, _) = watch::channel(None);
+        let client = Self {
+            shared: Arc::new(Shared {
+                config,
+                next_id: AtomicU64::new(1),
+                restarts: AtomicU64::new(0),
+                session,
+            }),
+        };
+
+        tokio::spawn(client.clone().supervise());
+        client
+    }
+
+    /// Tools discovered during the handshake with the current server.
+    pub async fn tools(&self) -> Result<Vec<Tool>, McpError> {
+        Ok(self.session().await?.tools.clone())
+    }
+
+    pub async fn call_tool(
+        &self,
+        name: &str,
+        arguments: Value,
+    ) -> Result<CallToolResult, McpError> {
+        let result = self
+            .request(
+                "tools/call",
+                Some(json!({ "name": name, "arguments": arguments })),
+            )
+            .await?;
+
+        serde_json::from_value(result).map_err(|e| McpError::InvalidResponse(e.to_string()))
+    }
+
+    /// Send a request to the current server, waiting for it to come up if it is restarting.
+    pub async fn request(&self, method: &str, params: Option<Value>) -> Result<Value, McpError> {
+        let session = self.session().await?;
+        self.send(&session.connection, method, params).await
+    }
+
+    pub fn status(&self) -> McpStatus {
+        let session = self.shared.session.borrow().clone();
+
+        McpStatus {
+            connected: session.is_some(),
+            restarts: self.shared.restarts.load(Ordering::Relaxed),
+            protocol_version: session
+                .as_ref()
+                .map(|session| session.protocol_version.clone()),
+            tools: session
+                .map(|session| session.tools.iter().map(|tool| tool.name.clone()).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    async fn session(&self) -> Result<Arc<Session>, McpError> {
+        let mut receiver = self.shared.session.subscribe();
+        let session = tokio::time::timeout(
+            self.shared.config.call_timeout,
+            receiver.wait_for(Option::is_some),
+        )
+        .await
+        .map_err(|_| McpError::NotConnected)?
+        .map_err(|_| McpError::NotConnected)?;
+
+        session.clone().ok_or(McpError::NotConnected)
+    }
+
+    async fn send(
+        &self,
+        connection: &Connection,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<Value, McpError> {
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        connection.pending.lock().unwrap().insert(id, sender);
+
+        if let Err(e) = connection.write(&Request::new(id, method, params)).await {
+            connection.pending.lock().unwrap().remove(&id);
+            return Err(e.into());
+        }
+
+        match tokio::time::timeout(self.shared.config.call_timeout, receiver).await {
+            Ok(Ok(response)) => Ok(response.into_result()?),
+            // The reader drops every pending sender when the server's stdout closes
+            Ok(Err(_)) => Err(McpError::Disconnected),
+            Err(_) => {
+                connection.pending.lock().unwrap().remove(&id);
+                let cancelled = Request::notification(
+                    "notifications/cancelled",
+                    Some(json!({ "requestId": id, "reason": "timed out" })),
+                );
+                let _ = connection.write(&cancelled).await;
+                Err(McpError::Timeout(method.to_string()))
+            }
+        }
+    }
+
+    /// Run the server, restarting it with exponential backoff whenever it exits.
+    async fn supervise(self) {
+        let mut delay = MIN_RESTART_DELAY;
+
+        loop {
+            let started = Instant::now();
+            match self.start().await {
+                Ok((mut child, session)) => {
+                    tracing::info!(
+                        "MCP server started with {} tools (protocol {})",
+                        session.tools.len(),
+                        session.protocol_version
+                    );
+                    self.shared.session.send_replace(Some(session));
+
+                    match child.wait().await {
+                        Ok(status) => tracing::warn!("MCP server exited: {}", status),
+                        Err(e) => tracing::error!("Failed to wait for MCP server: {}", e),
+                    }
+                    self.shared.session.send_replace(None);
+                }
+                Err(e) => tracing::error!("Failed to start MCP server: {}", e),
+            }
+
+            if started.elapsed() >= HEALTHY_RUN {
+                delay = MIN_RESTART_DELAY;
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RESTART_DELAY);
+            self.shared.restarts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Spawn the child and complete the `initialize` handshake and tool discovery.
+    async fn start(&self) -> Result<(Child, Arc<Session>), McpError> {
+        let config = &self.shared.config;
+        let mut child = Command::new(&config.program)
+            .args(&config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // The server logs to stderr, which simply joins ours
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(McpError::NotConnected);
+        };
+        let connection = Arc::new(Connection {
+            stdin: tokio::sync::Mutex::new(stdin),
+            pending: Mutex::new(HashMap::new()),
+        });
+        tokio::spawn(read_responses(connection.clone(), stdout));
+
+        // Dropping `child` on a failed handshake kills it
+        let initialized = self
+            .send(
+                &connection,
+                "initialize",
+                Some(json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {
+                        "name": CLIENT_NAME,
+                        "version": env!("CARGO_PKG_VERSION")
+                    }
+                })),
+            )
+            .await?;
+
+        let protocol_version = initialized["protocolVersion"]
+            .as_str()
+            .filter(|version| SUPPORTED_PROTOCOL_VERSIONS.contains(version))
+            .ok_or_else(|| {
+                McpError::InvalidResponse(format!(
+                    "unsupported protocol version {}",
+                    initialized["protocolVersion"]
+                ))
+            })?
+            .to_string();
+
+        connection
+            .write(&Request::notification("notifications/initialized", None))
+            .await?;
+
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = cursor.map(|cursor| json!({ "cursor": cursor }));
+            let page = self.send(&connection, "tools/list", params).await?;
+            let page_tools: Vec<Tool> = serde_json::from_value(page["tools"].clone())
+                .map_err(|e| McpError::InvalidResponse(e.to_string()))?;
+            tools.extend(page_tools);
+
+            cursor = page["nextCursor"].as_str().map(str::to_string);
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        let session = Session {
+            connection,
+            protocol_version,
+            tools,
+        };
+        Ok((child, Arc::new(session)))
+    }
+}
+
+impl Connection {
+    async fn write(&self, request: &Request) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+
+        let mut stdin = self.stdin.lock().await;
+        stdin.write_all(&line).await?;
+        stdin.flush().await
+    }
+}
+
+/// Route each response line to the request waiting for its id.
+async fn read_responses(connection: Arc<Connection>, stdout: ChildStdout) {
+    let mut lines = BufReader::new(stdout).lines();
+
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                tracing::error!("Failed to read from MCP server: {}", e);
+                break;
+            }
+        };
+
+        let response: Response = match serde_json::from_str::<Response>(&line) {
+            Ok(response) if response.result.is_some() || response.error.is_some() => response,
+            _ => {
+                // Server-initiated requests and notifications are not used by our server
+                tracing::debug!("Ignoring MCP message: {}", line);
+                continue;
+            }
+        };
+
+        let waiting = response
+            .id
+            .as_u64()
+            .and_then(|id| connection.pending.lock().unwrap().remove(&id));
+        match waiting {
+            Some(sender) => {
+                let _ = sender.send(response);
+            }
+            None => tracing::debug!("Dropping MCP response for unknown id {}", response.id),
+        }
+    }
+
+    // Fail everything still in flight
+    connection.pending.lock().unwrap().clear();
+}
@@ -0,0 +1,16 @@
+//! Model Context Protocol server exposing market data and valuation tools, stock data
+//! resources and agent persona prompts, and the client the API server uses to talk to it.
+
+pub mod access;
+pub mod client;
+pub mod prompts;
+pub mod protocol;
+pub mod resources;
+pub mod server;
+pub mod tools;
+
+/// Newest protocol revision this server implements.
+pub const PROTOCOL_VERSION: &str = "2025-06-18";
+/// Revisions a client may negotiate; the feature surface is the same in all of them.
+pub const SUPPORTED_PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+pub const SERVER_NAME: &str = "r-stock-analyzer";
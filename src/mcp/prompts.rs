@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    analysis::{gather_market_data, render_prompt, render_tool_prompt, AnalysisError},
+    committee::AGENT_KIND_ANALYST,
+    mcp::protocol::{Content, GetPromptResult, Prompt, PromptArgument, PromptMessage},
+    models::Agent,
+    schema::agents,
+    stocks::normalize_ticker,
+};
+
+/// `data` argument value that leaves market data out and points the model at our tools.
+const DATA_TOOLS: &str = "tools";
+const DATA_EMBED: &str = "embed";
+
+#[derive(Debug, Error)]
+pub enum PromptError {
+    #[error("unknown prompt: {0}")]
+    UnknownPrompt(String),
+    #[error("invalid arguments: {0}")]
+    InvalidArguments(String),
+    #[error("failed to load market data: {0}")]
+    MarketData(#[from] AnalysisError),
+    #[error("database error: {0}")]
+    Database(#[from] diesel::result::Error),
+}
+
+/// One prompt per active analyst persona, named by the agent's slug.
+pub fn list(conn: &mut PgConnection) -> Result<Vec<Prompt>, PromptError> {
+    let personas = agents::table
+        .filter(agents::is_active.eq(true))
+        .filter(agents::kind.eq(AGENT_KIND_ANALYST))
+        .order(agents::name.asc())
+        .select(Agent::as_select())
+        .load(conn)?;
+
+    Ok(personas
+        .into_iter()
+        .map(|agent| Prompt {
+            name: agent.slug,
+            title: agent.name,
+            description: agent.description,
+            arguments: vec![
+                PromptArgument {
+                    name: "ticker".to_string(),
+                    description: "Ticker symbol to analyse, e.g. AAPL.".to_string(),
+                    required: true,
+                },
+                PromptArgument {
+                    name: "data".to_string(),
+                    description: "\"embed\" (default) includes the stored market data in the \
+                                  prompt, \"tools\" asks the model to look it up with this \
+                                  server's tools instead."
+                        .to_string(),
+                    required: false,
+                },
+            ],
+        })
+        .collect())
+}
+
+/// Render a persona's analysis prompt for a ticker.
+///
+/// MCP prompts have no system role, so the persona's system prompt opens the user message.
+/// Tickers without stored data fall back to the tool-based prompt.
+pub fn get(
+    conn: &mut PgConnection,
+    name: &str,
+    arguments: &HashMap<String, String>,
+) -> Result<GetPromptResult, PromptError> {
+    let agent = agents::table
+        .filter(agents::slug.eq(name))
+        .filter(agents::is_active.eq(true))
+        .filter(agents::kind.eq(AGENT_KIND_ANALYST))
+        .select(Agent::as_select())
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| PromptError::UnknownPrompt(name.to_string()))?;
+
+    let ticker = arguments
+        .get("ticker")
+        .ok_or_else(|| PromptError::InvalidArguments("ticker is required".to_string()))?;
+    let ticker = normalize_ticker(ticker)
+        .ok_or_else(|| PromptError::InvalidArguments(format!("invalid ticker: {}", ticker)))?;
+
+    let prompt = match arguments.get("data").map(String::as_str) {
+        None | Some(DATA_EMBED) => match gather_market_data(conn, &ticker) {
+            Ok(market_data) => {
+                let market_data = serde_json::to_value(&market_data).unwrap_or_default();
+                render_prompt(&agent, &ticker, &market_data)
+            }
+            Err(AnalysisError::NoMarketData(_)) => render_tool_prompt(&agent, &ticker),
+            Err(error) => return Err(error.into()),
+        },
+        Some(DATA_TOOLS) => render_tool_prompt(&agent, &ticker),
+        Some(other) => {
+            return Err(PromptError::InvalidArguments(format!(
+                "data must be {} or {}, got {}",
+                DATA_EMBED, DATA_TOOLS, other
+            )))
+        }
+    };
+
+    let schema = serde_json::to_string_pretty(&agent.output_schema)
+        .unwrap_or_else(|_| agent.output_schema.to_string());
+    let text = format!(
+        "{}\n\n{}\n\nAnswer with only a JSON object matching this schema:\n{}",
+        agent.system_prompt, prompt, schema
+    );
+
+    Ok(GetPromptResult {
+        description: Some(format!("{} analysis of {}", agent.name, ticker)),
+        messages: vec![PromptMessage {
+            role: "user".to_string(),
+            content: Content::Text { text },
+        }],
+    })
+}
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+
+use diesel::prelude::*;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{
+    mcp::{
+        protocol::{Resource, ResourceContents, ResourceTemplate},
+        tools::{self, ToolError},
+    },
+    schema::{fundamentals, price_bars},
+};
+
+const URI_SCHEME: &str = "stock://";
+const MIME_TYPE: &str = "application/json";
+/// Tickers covered by one page of `resources/list`; each contributes several resources.
+const TICKERS_PER_PAGE: i64 = 50;
+
+#[derive(Debug, Error)]
+pub enum ResourceError {
+    #[error("invalid resource URI: {0}")]
+    InvalidUri(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("database error: {0}")]
+    Database(#[from] diesel::result::Error),
+}
+
+/// Data a ticker has stored, which decides the resources listed for it.
+#[derive(Default)]
+struct Coverage {
+    prices: bool,
+    fundamentals: bool,
+}
+
+/// URI templates for every kind of resource, including tickers not listed yet.
+pub fn templates() -> Vec<ResourceTemplate> {
+    let template = |uri_template: &str, name: &str, description: &str| ResourceTemplate {
+        uri_template: uri_template.to_string(),
+        name: name.to_string(),
+        description: description.to_string(),
+        mime_type: MIME_TYPE.to_string(),
+    };
+
+    vec![
+        template(
+            "stock://{ticker}/quote",
+            "Quote",
+            "Latest daily close with the change from the previous close and 52-week range.",
+        ),
+        template(
+            "stock://{ticker}/prices",
+            "Price history",
+            "The last year of daily OHLCV bars, oldest first.",
+        ),
+        template(
+            "stock://{ticker}/financials/{period_type}",
+            "Financials",
+            "Reported fundamentals, newest first. `period_type` is annual or quarterly.",
+        ),
+        template(
+            "stock://{ticker}/valuation",
+            "Valuation",
+            "Valuation ratios, intrinsic value estimates and margins of safety.",
+        ),
+    ]
+}
+
+/// One page of resources for stored tickers in ticker order. `cursor` is the last ticker of
+/// the previous page and the returned cursor is `None` on the last page.
+pub fn list(
+    conn: &mut PgConnection,
+    cursor: Option<&str>,
+) -> Result<(Vec<Resource>, Option<String>), ResourceError> {
+    let after = cursor.unwrap_or_default();
+    let priced: Vec<String> = price_bars::table
+        .filter(price_bars::ticker.gt(after))
+        .select(price_bars::ticker)
+        .distinct()
+        .order(price_bars::ticker.asc())
+        .limit(TICKERS_PER_PAGE + 1)
+        .load(conn)?;
+    let reported: Vec<String> = fundamentals::table
+        .filter(fundamentals::ticker.gt(after))
+        .select(fundamentals::ticker)
+        .distinct()
+        .order(fundamentals::ticker.asc())
+        .limit(TICKERS_PER_PAGE + 1)
+        .load(conn)?;
+
+    let mut tickers: BTreeMap<String, Coverage> = BTreeMap::new();
+    for ticker in priced {
+        tickers.entry(ticker).or_default().prices = true;
+    }
+    for ticker in reported {
+        tickers.entry(ticker).or_default().fundamentals = true;
+    }
+
+    let has_more = tickers.len() > TICKERS_PER_PAGE as usize;
+    let page: Vec<(String, Coverage)> = tickers
+        .into_iter()
+        .take(TICKERS_PER_PAGE as usize)
+        .collect();
+    let next_cursor = has_more
+        .then(|| page.last().map(|(ticker, _)| ticker.clone()))
+        .flatten();
+
+    let mut resources = Vec::new();
+    for (ticker, coverage) in &page {
+        let mut resource = |path: &str, name: String, description: &str| {
+            resources.push(Resource {
+                uri: format!("{}{}/{}", URI_SCHEME, ticker, path),
+                name,
+                description: description.to_string(),
+                mime_type: MIME_TYPE.to_string(),
+            })
+        };
+
+        if coverage.prices {
+            resource(
+                "quote",
+                format!("{} quote", ticker),
+                "Latest daily close and 52-week range.",
+            );
+            resource(
+                "prices",
+                format!("{} price history", ticker),
+                "The last year of daily bars.",
+            );
+        }
+        if coverage.fundamentals {
+            resource(
+                "financials/annual",
+                format!("{} annual financials", ticker),
+                "Reported annual fundamentals.",
+            );
+            resource(
+                "financials/quarterly",
+                format!("{} quarterly financials", ticker),
+                "Reported quarterly fundamentals.",
+            );
+        }
+        resource(
+            "valuation",
+            format!("{} valuation", ticker),
+            "Valuation ratios and intrinsic value estimates.",
+        );
+    }
+
+    Ok((resources, next_cursor))
+}
+
+/// Read a `stock://` resource. Contents are the JSON the matching tool returns.
+pub fn read(conn: &mut PgConnection, uri: &str) -> Result<ResourceContents, ResourceError> {
+    let invalid = || ResourceError::InvalidUri(uri.to_string());
+    let path = uri.strip_prefix(URI_SCHEME).ok_or_else(invalid)?;
+    let segments: Vec<&str> = path.split('/').collect();
+
+    let (tool, arguments) = match segments.as_slice() {
+        [ticker, "quote"] => ("get_quote", json!({ "ticker": ticker })),
+        [ticker, "prices"] => ("get_price_history", json!({ "ticker": ticker })),
+        [ticker, "financials", period_type @ ("annual" | "quarterly")] => (
+            "get_financials",
+            json!({ "ticker": ticker, "period_type": period_type }),
+        ),
+        [ticker, "valuation"] => ("compute_valuation", json!({ "ticker": ticker })),
+        _ => return Err(invalid()),
+    };
+
+    let output: Value = tools::call(conn, tool, arguments).map_err(|error| match error {
+        ToolError::NotFound(message) => ResourceError::NotFound(message),
+        ToolError::Database(error) => ResourceError::Database(error),
+        ToolError::UnknownTool(_) | ToolError::InvalidArguments(_) => invalid(),
+    })?;
+
+    Ok(ResourceContents {
+        uri: uri.to_string(),
+        mime_type: MIME_TYPE.to_string(),
+        text: output.to_string(),
+    })
+}
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::PgConnection;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+use crate::{
+    database::DbPool,
+    mcp::{
+        access::ToolAccess,
+        prompts::{self, PromptError},
+        protocol::{
+            CallToolResult, Request, Response, RpcError, INTERNAL_ERROR, INVALID_PARAMS,
+            INVALID_REQUEST, JSONRPC_VERSION, METHOD_NOT_FOUND, PARSE_ERROR, RESOURCE_NOT_FOUND,
+        },
+        resources::{self, ResourceError},
+        tools::{self, ToolError},
+        PROTOCOL_VERSION, SERVER_NAME, SUPPORTED_PROTOCOL_VERSIONS,
+    },
+};
+
+#[derive(Deserialize)]
+struct InitializeParams {
+    #[serde(rename = "protocolVersion")]
+    protocol_version: String,
+}
+
+#[derive(Deserialize)]
+struct CallToolParams {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Default, Deserialize)]
+struct ListParams {
+    cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ReadResourceParams {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+struct GetPromptParams {
+    name: String,
+    #[serde(default)]
+    arguments: HashMap<String, String>,
+}
+
+/// Transport independent MCP request handling.
+#[derive(Clone)]
+pub struct McpServer {
+    pool: DbPool,
+}
+
+impl McpServer {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Handle one request. Notifications get no response.
+    ///
+    /// `role` is the caller's role on authenticated transports and limits the tools it sees
+    /// and may call. Local stdio clients pass `None` and get every tool.
+    ///
+    /// Tools query the database synchronously, so call this from a blocking context.
+    pub fn handle(&self, request: Request, role: Option<&str>) -> Option<Response> {
+        let Some(id) = request.id else {
+            // `notifications/initialized` and `notifications/cancelled` need no action
+            tracing::debug!("Received notification {}", request.method);
+            return None;
+        };
+
+        if request.jsonrpc != JSONRPC_VERSION {
+            return Some(Response::failure(
+                id,
+                RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""),
+            ));
+        }
+
+        let params = request.params.unwrap_or(Value::Null);
+        let result = match request.method.as_str() {
+            "initialize" => self.initialize(params),
+            "ping" => Ok(json!({})),
+            "tools/list" => self.list_tools(role),
+            "tools/call" => self.call_tool(params, role),
+            "resources/list" => self.list_resources(params),
+            "resources/templates/list" => {
+                Ok(json!({ "resourceTemplates": resources::templates() }))
+            }
+            "resources/read" => self.read_resource(params),
+            "prompts/list" => self.list_prompts(),
+            "prompts/get" => self.get_prompt(params),
+            method => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("method not found: {}", method),
+            )),
+        };
+
+        Some(match result {
+            Ok(result) => Response::success(id, result),
+            Err(error) => Response::failure(id, error),
+        })
+    }
+
+    fn initialize(&self, params: Value) -> Result<Value, RpcError> {
+        let params: InitializeParams = serde_json::from_value(params)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+
+        // Echo the client's version when we speak it, otherwise offer our latest
+        let protocol_version =
+            if SUPPORTED_PROTOCOL_VERSIONS.contains(&params.protocol_version.as_str()) {
+                params.protocol_version
+            } else {
+                PROTOCOL_VERSION.to_string()
+            };
+
+        Ok(json!({
+            "protocolVersion": protocol_version,
+            "capabilities": {
+                "tools": { "listChanged": false },
+                "resources": { "subscribe": false, "listChanged": false },
+                "prompts": { "listChanged": false }
+            },
+            "serverInfo": {
+                "name": SERVER_NAME,
+                "version": env!("CARGO_PKG_VERSION")
+            },
+            "instructions": "Stock market data and valuation tools backed by the analyzer database. \
+                             Prices are daily bars; fundamentals are reported annual and quarterly periods. \
+                             The same data is readable as stock:// resources, and each analyst \
+                             persona is available as a prompt."
+        }))
+    }
+
+    fn list_tools(&self, role: Option<&str>) -> Result<Value, RpcError> {
+        let mut tools = tools::list();
+        if let Some(role) = role {
+            let access = self.tool_access()?;
+            tools.retain(|tool| access.allows(&tool.name, role));
+        }
+
+        Ok(json!({ "tools": tools }))
+    }
+
+    fn call_tool(&self, params: Value, role: Option<&str>) -> Result<Value, RpcError> {
+        let params: CallToolParams = serde_json::from_value(params)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+
+        if let Some(role) = role {
+            if !self.tool_access()?.allows(&params.name, role) {
+                return Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!("tool {} is not available to role {}", params.name, role),
+                ));
+            }
+        }
+
+        let mut conn = self.connection()?;
+
+        let result = match tools::call(&mut conn, &params.name, params.arguments) {
+            Ok(output) => CallToolResult::success(output),
+            Err(error @ (ToolError::UnknownTool(_) | ToolError::InvalidArguments(_))) => {
+                return Err(RpcError::new(INVALID_PARAMS, error.to_string()));
+            }
+            Err(error @ ToolError::NotFound(_)) => CallToolResult::error(error.to_string()),
+            Err(error @ ToolError::Database(_)) => {
+                tracing::error!("Tool {} failed: {}", params.name, error);
+                CallToolResult::error(error.to_string())
+            }
+        };
+
+        serde_json::to_value(result).map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+    }
+
+    fn list_resources(&self, params: Value) -> Result<Value, RpcError> {
+        let params: ListParams = parse_optional_params(params)?;
+        let mut conn = self.connection()?;
+
+        let (resources, next_cursor) =
+            resources::list(&mut conn, params.cursor.as_deref()).map_err(resource_error)?;
+
+        let mut result = json!({ "resources": resources });
+        if let Some(next_cursor) = next_cursor {
+            result["nextCursor"] = json!(next_cursor);
+        }
+        Ok(result)
+    }
+
+    fn read_resource(&self, params: Value) -> Result<Value, RpcError> {
+        let params: ReadResourceParams = serde_json::from_value(params)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+        let mut conn = self.connection()?;
+
+        let contents = resources::read(&mut conn, &params.uri).map_err(resource_error)?;
+        Ok(json!({ "contents": [contents] }))
+    }
+
+    fn list_prompts(&self) -> Result<Value, RpcError> {
+        let mut conn = self.connection()?;
+
+        let prompts = prompts::list(&mut conn).map_err(prompt_error)?;
+        Ok(json!({ "prompts": prompts }))
+    }
+
+    fn get_prompt(&self, params: Value) -> Result<Value, RpcError> {
+        let params: GetPromptParams = serde_json::from_value(params)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+        let mut conn = self.connection()?;
+
+        let result =
+            prompts::get(&mut conn, &params.name, &params.arguments).map_err(prompt_error)?;
+        serde_json::to_value(result).map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+    }
+
+    fn tool_access(&self) -> Result<ToolAccess, RpcError> {
+        let mut conn = self.connection()?;
+        ToolAccess::load(&mut conn).map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+    }
+
+    fn connection(&self) -> Result<PooledConnection<ConnectionManager<PgConnection>>, RpcError> {
+        self.pool
+            .get()
+            .map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+    }
+}
+
+/// Params of list methods may be omitted entirely.
+fn parse_optional_params<T: Default + DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    if params.is_null() {
+        return Ok(T::default());
+    }
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn resource_error(error: ResourceError) -> RpcError {
+    match error {
+        ResourceError::InvalidUri(_) => RpcError::new(INVALID_PARAMS, error.to_string()),
+        ResourceError::NotFound(_) => RpcError::new(RESOURCE_NOT_FOUND, error.to_string()),
+        ResourceError::Database(_) => {
+            tracing::error!("Resource request failed: {}", error);
+            RpcError::new(INTERNAL_ERROR, error.to_string())
+        }
+    }
+}
+
+fn prompt_error(error: PromptError) -> RpcError {
+    match error {
+        PromptError::UnknownPrompt(_) | PromptError::InvalidArguments(_) => {
+            RpcError::new(INVALID_PARAMS, error.to_string())
+        }
+        PromptError::MarketData(_) | PromptError::Database(_) => {
+            tracing::error!("Prompt request failed: {}", error);
+            RpcError::new(INTERNAL_ERROR, error.to_string())
+        }
+    }
+}
+
+/// Serve newline-delimited JSON-RPC on stdin/stdout until stdin closes.
+///
+/// Requests are handled concurrently, so responses may be written out of order.
+pub async fn serve_stdio(server: McpServer) -> std::io::Result<()> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Response>();
+
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(response) = receiver.recv().await {
+            let mut line = serde_json::to_vec(&response)?;
+            line.push(b'\n');
+            stdout.write_all(&line).await?;
+            stdout.flush().await?;
+        }
+        std::io::Result::Ok(())
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request = match parse_request(&line) {
+            Ok(request) => request,
+            Err(response) => {
+                let _ = sender.send(*response);
+                continue;
+            }
+        };
+
+        let server = server.clone();
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            let method = request.method.clone();
+            match tokio::task::spawn_blocking(move || server.handle(request, None)).await {
+                Ok(Some(response)) => {
+                    let _ = sender.send(response);
+                }
+                Ok(None) => {}
+                Err(e) => tracing::error!("Handler for {} panicked: {}", method, e),
+            }
+        });
+    }
+
+    // Let in-flight requests finish before stdout closes
+    drop(sender);
+    writer.await.map_err(std::io::Error::other)?
+}
+
+/// Parse one JSON-RPC message, or build the error response to send back when it is invalid.
+pub fn parse_request(line: &str) -> Result<Request, Box<Response>> {
+    let value: Value = serde_json::from_str(line).map_err(|e| {
+        Box::new(Response::failure(
+            Value::Null,
+            RpcError::new(PARSE_ERROR, e.to_string()),
+        ))
+    })?;
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+
+    serde_json::from_value(value).map_err(|e| {
+        Box::new(Response::failure(
+            id,
+            RpcError::new(INVALID_REQUEST, e.to_string()),
+        ))
+    })
+}
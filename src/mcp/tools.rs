@@ -0,0 +1,384 @@
+use std::cmp::Ordering;
+
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{
+    analysis::ReportedPeriod,
+    mcp::protocol::Tool,
+    models::PriceBar,
+    schema::price_bars,
+    stocks::{self, normalize_ticker},
+    valuation::{self, Valuation},
+};
+
+/// Trading days in a year; one year of bars covers the quote's 52-week range and the valuation.
+const TRADING_DAYS_PER_YEAR: i64 = 252;
+const MAX_PRICE_HISTORY: i64 = 5 * TRADING_DAYS_PER_YEAR;
+const DEFAULT_FINANCIAL_PERIODS: i64 = 5;
+const MAX_FINANCIAL_PERIODS: i64 = 20;
+/// Annual periods used for valuations, matching what analyses see.
+const VALUATION_PERIODS: i64 = 5;
+const DEFAULT_SCREEN_RESULTS: usize = 20;
+const MAX_SCREEN_RESULTS: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum ToolError {
+    #[error("unknown tool: {0}")]
+    UnknownTool(String),
+    #[error("invalid arguments: {0}")]
+    InvalidArguments(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("database error: {0}")]
+    Database(#[from] diesel::result::Error),
+}
+
+/// Every tool the server offers, in the order `tools/list` reports them.
+pub fn list() -> Vec<Tool> {
+    let ticker = json!({
+        "type": "string",
+        "description": "Ticker symbol, e.g. AAPL or BRK.B."
+    });
+
+    vec![
+        Tool {
+            name: "get_quote".to_string(),
+            description: "Latest daily close for a ticker with the change from the previous \
+                          close, volume and 52-week range."
+                .to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": { "ticker": ticker },
+                "required": ["ticker"]
+            }),
+        },
+        Tool {
+            name: "get_price_history".to_string(),
+            description: "Daily OHLCV bars for a ticker, oldest first. Defaults to the last year."
+                .to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "ticker": ticker,
+                    "start_date": { "type": "string", "format": "date" },
+                    "end_date": { "type": "string", "format": "date" },
+                    "limit": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": MAX_PRICE_HISTORY,
+                        "description": "Most recent bars to return within the date range."
+                    }
+                },
+                "required": ["ticker"]
+            }),
+        },
+        Tool {
+            name: "get_financials".to_string(),
+            description: "Reported annual or quarterly fundamentals for a ticker, newest first."
+                .to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "ticker": ticker,
+                    "period_type": { "type": "string", "enum": ["annual", "quarterly"] },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": MAX_FINANCIAL_PERIODS }
+                },
+                "required": ["ticker"]
+            }),
+        },
+        Tool {
+            name: "compute_valuation".to_string(),
+            description: "Valuation ratios and intrinsic value estimates for a ticker: P/E, P/B, \
+                          yields, ROE, Graham number, NCAV, owner-earnings DCF and margins of \
+                          safety."
+                .to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": { "ticker": ticker },
+                "required": ["ticker"]
+            }),
+        },
+        Tool {
+            name: "screen_stocks".to_string(),
+            description: "Find stored tickers whose valuation passes every given filter, \
+                          ordered by DCF margin of safety. Tickers missing a filtered metric \
+                          are excluded."
+                .to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "max_pe_ratio": { "type": "number" },
+                    "max_pb_ratio": { "type": "number" },
+                    "min_earnings_yield": { "type": "number" },
+                    "min_dividend_yield": { "type": "number" },
+                    "min_return_on_equity": { "type": "number" },
+                    "max_debt_to_equity": { "type": "number" },
+                    "min_margin_of_safety": {
+                        "type": "number",
+                        "description": "Minimum DCF margin of safety as a fraction, e.g. 0.3."
+                    },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": MAX_SCREEN_RESULTS }
+                }
+            }),
+        },
+    ]
+}
+
+/// Run a tool by name. The returned value is always a JSON object.
+pub fn call(conn: &mut PgConnection, name: &str, arguments: Value) -> Result<Value, ToolError> {
+    match name {
+        "get_quote" => get_quote(conn, parse_arguments(arguments)?),
+        "get_price_history" => get_price_history(conn, parse_arguments(arguments)?),
+        "get_financials" => get_financials(conn, parse_arguments(arguments)?),
+        "compute_valuation" => compute_valuation(conn, parse_arguments(arguments)?),
+        "screen_stocks" => screen_stocks(conn, parse_arguments(arguments)?),
+        _ => Err(ToolError::UnknownTool(name.to_string())),
+    }
+}
+
+fn parse_arguments<T: DeserializeOwned>(arguments: Value) -> Result<T, ToolError> {
+    // Clients may omit `arguments` entirely for tools without required parameters
+    let arguments = if arguments.is_null() {
+        json!({})
+    } else {
+        arguments
+    };
+    serde_json::from_value(arguments).map_err(|e| ToolError::InvalidArguments(e.to_string()))
+}
+
+fn ticker_argument(ticker: &str) -> Result<String, ToolError> {
+    normalize_ticker(ticker)
+        .ok_or_else(|| ToolError::InvalidArguments(format!("invalid ticker: {}", ticker)))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TickerArguments {
+    ticker: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PriceHistoryArguments {
+    ticker: String,
+    start_date: Option<NaiveDate>,
+    end_date: Option<NaiveDate>,
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FinancialsArguments {
+    ticker: String,
+    period_type: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScreenArguments {
+    max_pe_ratio: Option<f64>,
+    max_pb_ratio: Option<f64>,
+    min_earnings_yield: Option<f64>,
+    min_dividend_yield: Option<f64>,
+    min_return_on_equity: Option<f64>,
+    max_debt_to_equity: Option<f64>,
+    min_margin_of_safety: Option<f64>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct Bar {
+    date: NaiveDate,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: i64,
+}
+
+impl From<PriceBar> for Bar {
+    fn from(bar: PriceBar) -> Self {
+        Self {
+            date: bar.trade_date,
+            open: bar.open,
+            high: bar.high,
+            low: bar.low,
+            close: bar.close,
+            volume: bar.volume,
+        }
+    }
+}
+
+fn get_quote(conn: &mut PgConnection, arguments: TickerArguments) -> Result<Value, ToolError> {
+    let ticker = ticker_argument(&arguments.ticker)?;
+    let bars = stocks::load_price_history(conn, &ticker, TRADING_DAYS_PER_YEAR)?;
+    let Some(latest) = bars.first() else {
+        return Err(ToolError::NotFound(format!("no price data for {}", ticker)));
+    };
+
+    let previous_close = bars.get(1).map(|bar| bar.close);
+    let change = previous_close.map(|previous| latest.close - previous);
+    let change_percent = previous_close
+        .zip(change)
+        .filter(|(previous, _)| *previous != 0.0)
+        .map(|(previous, change)| change / previous * 100.0);
+
+    Ok(json!({
+        "ticker": ticker,
+        "date": latest.trade_date,
+        "open": latest.open,
+        "high": latest.high,
+        "low": latest.low,
+        "close": latest.close,
+        "volume": latest.volume,
+        "previous_close": previous_close,
+        "change": change,
+        "change_percent": change_percent,
+        "fifty_two_week_high": bars.iter().map(|bar| bar.high).reduce(f64::max),
+        "fifty_two_week_low": bars.iter().map(|bar| bar.low).reduce(f64::min),
+    }))
+}
+
+fn get_price_history(
+    conn: &mut PgConnection,
+    arguments: PriceHistoryArguments,
+) -> Result<Value, ToolError> {
+    let ticker = ticker_argument(&arguments.ticker)?;
+    let limit = arguments
+        .limit
+        .unwrap_or(TRADING_DAYS_PER_YEAR)
+        .clamp(1, MAX_PRICE_HISTORY);
+
+    let mut query = price_bars::table
+        .filter(price_bars::ticker.eq(&ticker))
+        .into_boxed();
+    if let Some(start_date) = arguments.start_date {
+        query = query.filter(price_bars::trade_date.ge(start_date));
+    }
+    if let Some(end_date) = arguments.end_date {
+        query = query.filter(price_bars::trade_date.le(end_date));
+    }
+
+    let mut bars = query
+        .order(price_bars::trade_date.desc())
+        .limit(limit)
+        .select(PriceBar::as_select())
+        .load(conn)?;
+    bars.reverse();
+
+    let bars: Vec<Bar> = bars.into_iter().map(Bar::from).collect();
+    Ok(json!({ "ticker": ticker, "bars": bars }))
+}
+
+fn get_financials(
+    conn: &mut PgConnection,
+    arguments: FinancialsArguments,
+) -> Result<Value, ToolError> {
+    let ticker = ticker_argument(&arguments.ticker)?;
+    let period_type = arguments.period_type.as_deref().unwrap_or("annual");
+    if period_type != "annual" && period_type != "quarterly" {
+        return Err(ToolError::InvalidArguments(format!(
+            "period_type must be annual or quarterly, got {}",
+            period_type
+        )));
+    }
+    let limit = arguments
+        .limit
+        .unwrap_or(DEFAULT_FINANCIAL_PERIODS)
+        .clamp(1, MAX_FINANCIAL_PERIODS);
+
+    let periods: Vec<ReportedPeriod> =
+        stocks::load_fundamentals(conn, &ticker, period_type, limit)?
+            .into_iter()
+            .map(ReportedPeriod::from)
+            .collect();
+
+    Ok(json!({ "ticker": ticker, "period_type": period_type, "periods": periods }))
+}
+
+fn compute_valuation(
+    conn: &mut PgConnection,
+    arguments: TickerArguments,
+) -> Result<Value, ToolError> {
+    let ticker = ticker_argument(&arguments.ticker)?;
+    let (as_of, valuation) = load_valuation(conn, &ticker)?
+        .ok_or_else(|| ToolError::NotFound(format!("no market data for {}", ticker)))?;
+
+    Ok(json!({ "ticker": ticker, "as_of": as_of, "valuation": valuation }))
+}
+
+fn screen_stocks(conn: &mut PgConnection, arguments: ScreenArguments) -> Result<Value, ToolError> {
+    let limit = arguments
+        .limit
+        .unwrap_or(DEFAULT_SCREEN_RESULTS)
+        .clamp(1, MAX_SCREEN_RESULTS);
+
+    let tickers: Vec<String> = price_bars::table
+        .select(price_bars::ticker)
+        .distinct()
+        .order(price_bars::ticker.asc())
+        .load(conn)?;
+
+    let mut matches = Vec::new();
+    for ticker in &tickers {
+        if let Some((as_of, valuation)) = load_valuation(conn, ticker)? {
+            if passes_screen(&valuation, &arguments) {
+                matches.push(json!({ "ticker": ticker, "as_of": as_of, "valuation": valuation }));
+            }
+        }
+    }
+
+    // Highest margin of safety first, tickers without a DCF value last
+    let margin = |item: &Value| item["valuation"]["margin_of_safety_dcf"].as_f64();
+    matches.sort_by(|a, b| match (margin(a), margin(b)) {
+        (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    let total_matches = matches.len();
+    matches.truncate(limit);
+
+    Ok(json!({
+        "screened": tickers.len(),
+        "total_matches": total_matches,
+        "matches": matches,
+    }))
+}
+
+fn load_valuation(
+    conn: &mut PgConnection,
+    ticker: &str,
+) -> Result<Option<(Option<NaiveDate>, Valuation)>, ToolError> {
+    let prices = stocks::load_price_history(conn, ticker, TRADING_DAYS_PER_YEAR)?;
+    let annual = stocks::load_fundamentals(conn, ticker, "annual", VALUATION_PERIODS)?;
+
+    if prices.is_empty() && annual.is_empty() {
+        return Ok(None);
+    }
+
+    let as_of = prices.first().map(|bar| bar.trade_date);
+    Ok(Some((as_of, valuation::compute(&prices, &annual))))
+}
+
+fn passes_screen(valuation: &Valuation, screen: &ScreenArguments) -> bool {
+    let at_most = |value: Option<f64>, bound: Option<f64>| {
+        bound.is_none_or(|bound| value.is_some_and(|value| value <= bound))
+    };
+    let at_least = |value: Option<f64>, bound: Option<f64>| {
+        bound.is_none_or(|bound| value.is_some_and(|value| value >= bound))
+    };
+
+    at_most(valuation.pe_ratio, screen.max_pe_ratio)
+        && at_most(valuation.pb_ratio, screen.max_pb_ratio)
+        && at_least(valuation.earnings_yield, screen.min_earnings_yield)
+        && at_least(valuation.dividend_yield, screen.min_dividend_yield)
+        && at_least(valuation.return_on_equity, screen.min_return_on_equity)
+        && at_most(valuation.debt_to_equity, screen.max_debt_to_equity)
+        && at_least(valuation.margin_of_safety_dcf, screen.min_margin_of_safety)
+}
@@ -0,0 +1,225 @@
+use chrono::Utc;
+use diesel::dsl::{count_star, now};
+use diesel::prelude::*;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use ring::rand::{SecureRandom, SystemRandom};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+use crate::{
+    auth::hash_token,
+    models::{NewMfaRecoveryCode, NewTotpCredential, TotpCredential},
+    schema::{mfa_recovery_codes, totp_credentials},
+};
+
+const ISSUER: &str = "Stock Analyzer";
+const DIGITS: usize = 6;
+const STEP_SECS: u64 = 30;
+/// Codes from one step before or after the current one are accepted for clock drift.
+const SKEW_STEPS: i64 = 1;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Start enrolling the user in TOTP with a new secret, replacing an unconfirmed one. Returns the
+/// base32 secret, or `None` if the user already has TOTP enabled.
+pub fn enroll(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<Option<String>> {
+    if find(conn, user_id, true)?.is_some() {
+        return Ok(None);
+    }
+
+    let secret = Secret::Raw(random_bytes::<20>().to_vec())
+        .to_encoded()
+        .to_string();
+    diesel::insert_into(totp_credentials::table)
+        .values(&NewTotpCredential {
+            user_id,
+            secret: secret.clone(),
+        })
+        .on_conflict(totp_credentials::user_id)
+        .do_update()
+        .set((
+            totp_credentials::secret.eq(&secret),
+            totp_credentials::last_used_step.eq(None::<i64>),
+            totp_credentials::created_at.eq(now),
+        ))
+        .execute(conn)?;
+
+    Ok(Some(secret))
+}
+
+/// The `otpauth://` URI authenticator apps scan to add the account.
+pub fn otpauth_uri(secret: &str, username: &str) -> String {
+    let issuer = utf8_percent_encode(ISSUER, NON_ALPHANUMERIC);
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer,
+        utf8_percent_encode(username, NON_ALPHANUMERIC),
+        secret,
+        issuer,
+        DIGITS,
+        STEP_SECS
+    )
+}
+
+/// Finish enrolling with the first code from the authenticator app. Returns new recovery codes,
+/// or `None` if there is no pending enrollment or the code is wrong.
+pub fn confirm(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    code: &str,
+) -> QueryResult<Option<Vec<String>>> {
+    conn.transaction(|conn| {
+        if !use_totp_code(conn, user_id, code, false)? {
+            return Ok(None);
+        }
+        diesel::update(totp_credentials::table.find(user_id))
+            .set(totp_credentials::confirmed_at.eq(now))
+            .execute(conn)?;
+
+        replace_recovery_codes(conn, user_id).map(Some)
+    })
+}
+
+pub fn is_enabled(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<bool> {
+    Ok(find(conn, user_id, true)?.is_some())
+}
+
+/// Check a code from the authenticator app or an unused recovery code of a user with TOTP
+/// enabled. Each code is accepted once.
+pub fn verify(conn: &mut PgConnection, user_id: Uuid, code: &str) -> QueryResult<bool> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() == DIGITS && code.chars().all(|c| c.is_ascii_digit()) {
+        conn.transaction(|conn| use_totp_code(conn, user_id, &code, true))
+    } else {
+        use_recovery_code(conn, user_id, &code)
+    }
+}
+
+/// Turn TOTP off and delete the recovery codes. Returns whether TOTP was enabled or pending.
+pub fn disable(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<bool> {
+    conn.transaction(|conn| {
+        diesel::delete(mfa_recovery_codes::table.filter(mfa_recovery_codes::user_id.eq(user_id)))
+            .execute(conn)?;
+        let deleted = diesel::delete(totp_credentials::table.find(user_id)).execute(conn)?;
+        Ok(deleted > 0)
+    })
+}
+
+/// Replace the user's recovery codes with new ones, which are returned once and stored hashed.
+pub fn replace_recovery_codes(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<Vec<String>> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code = hex::encode(random_bytes::<5>());
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect();
+    let rows: Vec<NewMfaRecoveryCode> = codes
+        .iter()
+        .map(|code| NewMfaRecoveryCode {
+            user_id,
+            code_hash: hash_token(&normalize_recovery_code(code)),
+        })
+        .collect();
+
+    conn.transaction(|conn| {
+        diesel::delete(mfa_recovery_codes::table.filter(mfa_recovery_codes::user_id.eq(user_id)))
+            .execute(conn)?;
+        diesel::insert_into(mfa_recovery_codes::table)
+            .values(&rows)
+            .execute(conn)
+    })?;
+
+    Ok(codes)
+}
+
+pub fn recovery_codes_remaining(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<i64> {
+    mfa_recovery_codes::table
+        .filter(mfa_recovery_codes::user_id.eq(user_id))
+        .filter(mfa_recovery_codes::used_at.is_null())
+        .select(count_star())
+        .get_result(conn)
+}
+
+fn find(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    confirmed: bool,
+) -> QueryResult<Option<TotpCredential>> {
+    let query = totp_credentials::table
+        .find(user_id)
+        .select(TotpCredential::as_select())
+        .into_boxed();
+    let query = if confirmed {
+        query.filter(totp_credentials::confirmed_at.is_not_null())
+    } else {
+        query.filter(totp_credentials::confirmed_at.is_null())
+    };
+    query.first(conn).optional()
+}
+
+/// Check `code` against the user's confirmed (or, with `confirmed` false, pending) credential
+/// and remember its time step so it cannot be used again. Must run in a transaction.
+fn use_totp_code(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    code: &str,
+    confirmed: bool,
+) -> QueryResult<bool> {
+    let credential: Option<TotpCredential> = totp_credentials::table
+        .find(user_id)
+        .select(TotpCredential::as_select())
+        .for_update()
+        .first(conn)
+        .optional()?;
+    let Some(credential) = credential.filter(|c| c.confirmed_at.is_some() == confirmed) else {
+        return Ok(false);
+    };
+    let Ok(secret) = Secret::Encoded(credential.secret).to_bytes() else {
+        return Ok(false);
+    };
+
+    let totp = TOTP::new_unchecked(Algorithm::SHA1, DIGITS, 0, STEP_SECS, secret);
+    let current_step = Utc::now().timestamp() / STEP_SECS as i64;
+    let step = (current_step - SKEW_STEPS..=current_step + SKEW_STEPS)
+        .filter(|step| Some(*step) > credential.last_used_step)
+        .find(|step| totp.check(code, *step as u64 * STEP_SECS));
+    let Some(step) = step else {
+        return Ok(false);
+    };
+
+    diesel::update(totp_credentials::table.find(user_id))
+        .set(totp_credentials::last_used_step.eq(step))
+        .execute(conn)?;
+    Ok(true)
+}
+
+fn use_recovery_code(conn: &mut PgConnection, user_id: Uuid, code: &str) -> QueryResult<bool> {
+    if find(conn, user_id, true)?.is_none() {
+        return Ok(false);
+    }
+
+    let used = diesel::update(
+        mfa_recovery_codes::table
+            .filter(mfa_recovery_codes::user_id.eq(user_id))
+            .filter(mfa_recovery_codes::code_hash.eq(hash_token(&normalize_recovery_code(code))))
+            .filter(mfa_recovery_codes::used_at.is_null()),
+    )
+    .set(mfa_recovery_codes::used_at.eq(now))
+    .execute(conn)?;
+    Ok(used > 0)
+}
+
+/// Recovery codes are accepted in any case and with or without the dash.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system random number generator failed");
+    bytes
+}
@@ -1,59 +1,167 @@
+use std::net::SocketAddr;
+
 use axum::{
-    extract::Request,
+    extract::{ConnectInfo, Extension, Request, State},
     middleware::Next,
     response::Response,
     http::{StatusCode, HeaderMap},
 };
-use crate::auth::verify_jwt;
+use uuid::Uuid;
+
+use crate::{
+    api_tokens,
+    auth::{verify_jwt, Claims},
+    database::DbPool,
+    environments::is_mfa_required_for_admins,
+    mfa,
+    permissions,
+    sessions::{self, ClientInfo},
+};
 
 pub async fn auth_middleware(
+    State(pool): State<DbPool>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     mut request: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    let auth_header = headers
-        .get("Authorization")
-        .and_then(|header| header.to_str().ok())
-        .and_then(|header| header.strip_prefix("Bearer "));
-
-    match auth_header {
-        Some(token) => {
-            match verify_jwt(token) {
-                Ok(claims) => {
-                    // Store claims in request extensions for use in handlers
-                    request.extensions_mut().insert(claims);
-                    Ok(next.run(request).await)
-                }
-                Err(_) => Err(StatusCode::UNAUTHORIZED),
-            }
+    let (claims, token_scopes) = authenticate(&pool, &headers, peer)?;
+    if let Some(scopes) = token_scopes {
+        if !api_tokens::permits(&scopes, request.method()) {
+            return Err(StatusCode::FORBIDDEN);
         }
-        None => Err(StatusCode::UNAUTHORIZED),
     }
+
+    // Store claims in request extensions for use in handlers
+    request.extensions_mut().insert(claims);
+    Ok(next.run(request).await)
+}
+
+/// Like `auth_middleware` but refuses API tokens, for endpoints that manage how the account
+/// signs in: sessions, API tokens, two-factor authentication and linked identities.
+pub async fn session_auth_middleware(
+    State(pool): State<DbPool>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let (claims, token_scopes) = authenticate(&pool, &headers, peer)?;
+    if token_scopes.is_some() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    request.extensions_mut().insert(claims);
+    Ok(next.run(request).await)
 }
 
 pub async fn admin_middleware(
+    State(pool): State<DbPool>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     mut request: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    let auth_header = headers
+    let (claims, token_scopes) = authenticate(&pool, &headers, peer)?;
+    let granted = role_permissions(&pool, &claims.role)?;
+    if !permissions::has_admin_access(&granted) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if let Some(scopes) = token_scopes {
+        if !scopes.iter().any(|scope| scope == api_tokens::SCOPE_ADMIN) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    // Admins who must use two-factor authentication can only reach their own account
+    // endpoints, where they enroll, until it is enabled
+    if is_mfa_required_for_admins() {
+        let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::UNAUTHORIZED)?;
+        let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let enrolled =
+            mfa::is_enabled(&mut conn, user_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !enrolled {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    request.extensions_mut().insert(claims);
+    Ok(next.run(request).await)
+}
+
+/// Reject callers whose role lacks `permission`. Layered on single routes inside
+/// `auth_middleware` or `admin_middleware`, which put the caller's claims in the request.
+pub async fn permission_middleware(
+    State((pool, permission)): State<(DbPool, &'static str)>,
+    Extension(claims): Extension<Claims>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let granted = role_permissions(&pool, &claims.role)?;
+    if !granted.iter().any(|granted| granted == permission) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(next.run(request).await)
+}
+
+fn role_permissions(pool: &DbPool, role: &str) -> Result<Vec<String>, StatusCode> {
+    match permissions::cached(role) {
+        Some(granted) => Ok(granted),
+        None => {
+            let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            permissions::load(&mut conn, role).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Verify the bearer token and that its session is still live, so revoked sessions and
+/// deactivated users are locked out before their access tokens expire. For an API token the
+/// claims are built from its user and the token's scopes are returned alongside.
+fn authenticate(
+    pool: &DbPool,
+    headers: &HeaderMap,
+    peer: SocketAddr,
+) -> Result<(Claims, Option<Vec<String>>), StatusCode> {
+    let token = headers
         .get("Authorization")
         .and_then(|header| header.to_str().ok())
-        .and_then(|header| header.strip_prefix("Bearer "));
-
-    match auth_header {
-        Some(token) => {
-            match verify_jwt(token) {
-                Ok(claims) => {
-                    if claims.role != "admin" {
-                        return Err(StatusCode::FORBIDDEN);
-                    }
-                    request.extensions_mut().insert(claims);
-                    Ok(next.run(request).await)
-                }
-                Err(_) => Err(StatusCode::UNAUTHORIZED),
-            }
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    if token.starts_with(api_tokens::TOKEN_PREFIX) {
+        let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let client = ClientInfo::new(headers, peer);
+        let (user, api_token) = api_tokens::authenticate(&mut conn, token, client.ip_address)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+
+        let claims = Claims {
+            sub: user.id.to_string(),
+            username: user.username,
+            role: user.role,
+            exp: api_token.expires_at.and_utc().timestamp() as usize,
+            iat: api_token.created_at.and_utc().timestamp() as usize,
+            jti: api_token.id.to_string(),
+            // API tokens are not tied to a login session
+            sid: String::new(),
+        };
+        return Ok((claims, Some(api_token.scopes)));
+    }
+
+    let claims = verify_jwt(token).map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let session_id = Uuid::parse_str(&claims.sid).map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let owner = match sessions::cached(session_id) {
+        Some(owner) => owner,
+        None => {
+            let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            sessions::check(&mut conn, session_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         }
-        None => Err(StatusCode::UNAUTHORIZED),
+    };
+    if owner != Some(user_id) {
+        return Err(StatusCode::UNAUTHORIZED);
     }
+
+    Ok((claims, None))
 }
@@ -1,8 +1,12 @@
-use chrono::NaiveDateTime;
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::{csv_import::ColumnMapping, portfolio::Performance};
+
 #[derive(Queryable, Selectable, Serialize, Deserialize, Debug)]
 #[diesel(table_name = crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -15,6 +19,7 @@ pub struct User {
     pub is_active: bool,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub email_verified_at: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable, Deserialize)]
@@ -46,6 +51,7 @@ pub struct UserResponse {
     pub email: String,
     pub role: String,
     pub is_active: bool,
+    pub email_verified_at: Option<NaiveDateTime>,
     pub created_at: NaiveDateTime,
 }
 
@@ -57,18 +63,427 @@ impl From<User> for UserResponse {
             email: user.email,
             role: user.role,
             is_active: user.is_active,
+            email_verified_at: user.email_verified_at,
             created_at: user.created_at,
         }
     }
 }
 
+#[derive(Deserialize)]
+pub struct UserListQuery {
+    /// Part of the username or email, case-insensitive.
+    pub search: Option<String>,
+    pub role: Option<String>,
+    pub is_active: Option<bool>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct UserListResponse {
+    /// Users matching the filters, across all pages.
+    pub total: i64,
+    pub users: Vec<UserResponse>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateUserRoleRequest {
+    pub role: String,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::roles)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Role {
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::roles)]
+pub struct NewRole {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::permissions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Permission {
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::role_permissions)]
+pub struct NewRolePermission {
+    pub role: String,
+    pub permission: String,
+}
+
+#[derive(Serialize)]
+pub struct RoleResponse {
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+    /// Roles the application relies on cannot be deleted, and `admin` cannot be changed.
+    pub built_in: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateRoleRequest {
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+}
+
 #[derive(Serialize)]
 pub struct LoginResponse {
+    /// Short-lived access JWT for the `Authorization: Bearer` header.
     pub token: String,
+    /// Seconds until `token` expires.
+    pub expires_in: i64,
+    /// Single-use opaque token exchanged at `/api/auth/refresh` for a new pair.
+    pub refresh_token: String,
     pub user: UserResponse,
 }
 
-#[derive(Queryable, Selectable, Serialize, Deserialize, Debug)]
+#[derive(Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::refresh_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RefreshToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::refresh_tokens)]
+pub struct NewRefreshToken {
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::account_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AccountToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub purpose: String,
+    pub email: String,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::account_tokens)]
+pub struct NewAccountToken {
+    pub user_id: Uuid,
+    pub purpose: String,
+    pub email: String,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+/// What `/api/auth/login` answers: tokens, or a challenge when the user has two-factor
+/// authentication enabled.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginResult {
+    Tokens(LoginResponse),
+    MfaRequired(MfaChallengeResponse),
+}
+
+#[derive(Serialize)]
+pub struct MfaChallengeResponse {
+    pub mfa_required: bool,
+    /// Single-use token sent with a code to `/api/auth/mfa` to finish the login.
+    pub mfa_token: String,
+    /// Seconds until `mfa_token` expires.
+    pub expires_in: i64,
+}
+
+#[derive(Deserialize)]
+pub struct MfaLoginRequest {
+    pub mfa_token: String,
+    /// A code from the authenticator app or a recovery code.
+    pub code: String,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::totp_credentials)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TotpCredential {
+    pub user_id: Uuid,
+    pub secret: String,
+    pub confirmed_at: Option<NaiveDateTime>,
+    pub last_used_step: Option<i64>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::totp_credentials)]
+pub struct NewTotpCredential {
+    pub user_id: Uuid,
+    pub secret: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::mfa_recovery_codes)]
+pub struct NewMfaRecoveryCode {
+    pub user_id: Uuid,
+    pub code_hash: String,
+}
+
+#[derive(Serialize)]
+pub struct MfaStatusResponse {
+    pub totp_enabled: bool,
+    pub recovery_codes_remaining: i64,
+    /// Whether the account must enroll before it can use admin endpoints.
+    pub required: bool,
+}
+
+#[derive(Serialize)]
+pub struct TotpEnrollmentResponse {
+    /// Base32 secret for authenticator apps that cannot scan `otpauth_uri`.
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct MfaCodeRequest {
+    pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodesResponse {
+    /// Shown once; each code can replace an authenticator code one time.
+    pub recovery_codes: Vec<String>,
+}
+
+/// An OpenID Connect account that can sign in as a user.
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::user_identities)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct UserIdentity {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_login_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::user_identities)]
+pub struct NewUserIdentity {
+    pub user_id: Uuid,
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub last_login_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::oidc_login_states)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OidcLoginState {
+    pub id: Uuid,
+    pub state_hash: String,
+    pub code_verifier: String,
+    pub nonce: String,
+    pub link_user_id: Option<Uuid>,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::oidc_login_states)]
+pub struct NewOidcLoginState {
+    pub state_hash: String,
+    pub code_verifier: String,
+    pub nonce: String,
+    pub link_user_id: Option<Uuid>,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Query of the identity provider's redirect back to `/api/auth/oidc/callback`.
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+/// The `code` and `state` of the identity provider's redirect after a link was started, sent
+/// by the user who started it.
+#[derive(Deserialize)]
+pub struct CompleteOidcLinkRequest {
+    pub code: String,
+    pub state: String,
+}
+
+#[derive(Serialize)]
+pub struct AuthorizationUrlResponse {
+    /// Where to send the browser to sign in at the identity provider.
+    pub authorization_url: String,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Session {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::sessions)]
+pub struct NewSession {
+    pub user_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct SessionResponse {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    /// Whether this is the session of the access token making the request.
+    pub current: bool,
+}
+
+impl SessionResponse {
+    pub fn new(session: Session, current_session_id: Option<Uuid>) -> Self {
+        Self {
+            current: current_session_id == Some(session.id),
+            id: session.id,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            expires_at: session.expires_at,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::api_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub token_prefix: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub last_used_ip: Option<String>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::api_tokens)]
+pub struct NewApiToken {
+    pub user_id: Uuid,
+    pub name: String,
+    pub token_prefix: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// Days until the token expires; defaults to 90.
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ApiTokenResponse {
+    pub id: Uuid,
+    pub name: String,
+    /// Start of the token, to recognize it without the full value.
+    pub token_prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub last_used_ip: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<ApiToken> for ApiTokenResponse {
+    fn from(token: ApiToken) -> Self {
+        Self {
+            id: token.id,
+            name: token.name,
+            token_prefix: token.token_prefix,
+            scopes: token.scopes,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            last_used_ip: token.last_used_ip,
+            created_at: token.created_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CreatedApiTokenResponse {
+    /// Shown once; send it as `Authorization: Bearer <token>`.
+    pub token: String,
+    #[serde(flatten)]
+    pub api_token: ApiTokenResponse,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
 #[diesel(table_name = crate::schema::llm_providers)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct LlmProvider {
@@ -160,3 +575,989 @@ pub struct NewLlmUsage {
     pub cost: Option<bigdecimal::BigDecimal>,
     pub request_type: String,
 }
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::agents)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Agent {
+    pub id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub system_prompt: String,
+    pub prompt_template: String,
+    pub is_active: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub output_schema: serde_json::Value,
+    pub kind: String,
+}
+
+#[derive(Serialize)]
+pub struct AgentResponse {
+    pub id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+impl From<Agent> for AgentResponse {
+    fn from(agent: Agent) -> Self {
+        Self {
+            id: agent.id,
+            slug: agent.slug,
+            name: agent.name,
+            description: agent.description,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::price_bars)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PriceBar {
+    pub id: Uuid,
+    pub ticker: String,
+    pub trade_date: NaiveDate,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: i64,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Deserialize)]
+#[diesel(table_name = crate::schema::price_bars)]
+pub struct NewPriceBar {
+    pub ticker: String,
+    pub trade_date: NaiveDate,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: i64,
+}
+
+#[derive(Deserialize)]
+pub struct PriceBarInput {
+    pub trade_date: NaiveDate,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: i64,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::stock_profiles)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct StockProfile {
+    pub ticker: String,
+    pub name: Option<String>,
+    pub sector: Option<String>,
+    pub industry: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::stock_profiles)]
+#[diesel(treat_none_as_null = true)]
+pub struct NewStockProfile {
+    pub ticker: String,
+    pub name: Option<String>,
+    pub sector: Option<String>,
+    pub industry: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct StockProfileInput {
+    pub name: Option<String>,
+    pub sector: Option<String>,
+    pub industry: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::fundamentals)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Fundamental {
+    pub id: Uuid,
+    pub ticker: String,
+    pub period_end: NaiveDate,
+    pub period_type: String,
+    pub revenue: Option<f64>,
+    pub net_income: Option<f64>,
+    pub eps: Option<f64>,
+    pub free_cash_flow: Option<f64>,
+    pub book_value_per_share: Option<f64>,
+    pub total_assets: Option<f64>,
+    pub total_liabilities: Option<f64>,
+    pub current_assets: Option<f64>,
+    pub total_debt: Option<f64>,
+    pub shares_outstanding: Option<f64>,
+    pub dividends_per_share: Option<f64>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Deserialize)]
+#[diesel(table_name = crate::schema::fundamentals)]
+pub struct NewFundamental {
+    pub ticker: String,
+    pub period_end: NaiveDate,
+    pub period_type: String,
+    pub revenue: Option<f64>,
+    pub net_income: Option<f64>,
+    pub eps: Option<f64>,
+    pub free_cash_flow: Option<f64>,
+    pub book_value_per_share: Option<f64>,
+    pub total_assets: Option<f64>,
+    pub total_liabilities: Option<f64>,
+    pub current_assets: Option<f64>,
+    pub total_debt: Option<f64>,
+    pub shares_outstanding: Option<f64>,
+    pub dividends_per_share: Option<f64>,
+}
+
+#[derive(Deserialize)]
+pub struct FundamentalInput {
+    pub period_end: NaiveDate,
+    pub period_type: String,
+    pub revenue: Option<f64>,
+    pub net_income: Option<f64>,
+    pub eps: Option<f64>,
+    pub free_cash_flow: Option<f64>,
+    pub book_value_per_share: Option<f64>,
+    pub total_assets: Option<f64>,
+    pub total_liabilities: Option<f64>,
+    pub current_assets: Option<f64>,
+    pub total_debt: Option<f64>,
+    pub shares_outstanding: Option<f64>,
+    pub dividends_per_share: Option<f64>,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::analyses)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Analysis {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub agent_id: Uuid,
+    pub provider_id: Uuid,
+    pub llm_usage_id: Option<Uuid>,
+    pub ticker: String,
+    pub input_data: serde_json::Value,
+    pub prompt: String,
+    pub result_text: String,
+    pub created_at: NaiveDateTime,
+    pub structured_output: Option<serde_json::Value>,
+    pub verdict: Option<String>,
+    pub tool_trace: Option<serde_json::Value>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::analyses)]
+pub struct NewAnalysis {
+    pub id: Option<Uuid>,
+    pub user_id: Uuid,
+    pub agent_id: Uuid,
+    pub provider_id: Uuid,
+    pub llm_usage_id: Option<Uuid>,
+    pub ticker: String,
+    pub input_data: serde_json::Value,
+    pub prompt: String,
+    pub result_text: String,
+    pub structured_output: Option<serde_json::Value>,
+    pub verdict: Option<String>,
+    pub tool_trace: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateAnalysisRequest {
+    pub ticker: String,
+    pub agent_slug: String,
+    pub provider_id: Option<Uuid>,
+    pub use_tools: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct AnalysisResponse {
+    pub id: Uuid,
+    pub ticker: String,
+    pub agent_id: Uuid,
+    pub provider_id: Uuid,
+    pub input_data: serde_json::Value,
+    pub result_text: String,
+    pub structured_output: Option<serde_json::Value>,
+    pub verdict: Option<String>,
+    pub tool_trace: Option<serde_json::Value>,
+    pub tokens_used: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+impl AnalysisResponse {
+    pub fn new(analysis: Analysis, tokens_used: Option<i32>) -> Self {
+        Self {
+            id: analysis.id,
+            ticker: analysis.ticker,
+            agent_id: analysis.agent_id,
+            provider_id: analysis.provider_id,
+            input_data: analysis.input_data,
+            result_text: analysis.result_text,
+            structured_output: analysis.structured_output,
+            verdict: analysis.verdict,
+            tool_trace: analysis.tool_trace,
+            tokens_used,
+            created_at: analysis.created_at,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::analysis_jobs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AnalysisJob {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub agent_id: Uuid,
+    pub provider_id: Uuid,
+    pub ticker: String,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_after: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub kind: String,
+    pub use_tools: bool,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::analysis_jobs)]
+pub struct NewAnalysisJob {
+    pub user_id: Uuid,
+    pub agent_id: Uuid,
+    pub provider_id: Uuid,
+    pub ticker: String,
+    pub kind: String,
+    pub use_tools: bool,
+}
+
+#[derive(Serialize)]
+pub struct AnalysisJobResponse {
+    pub id: Uuid,
+    pub kind: String,
+    pub use_tools: bool,
+    pub ticker: String,
+    pub agent_id: Uuid,
+    pub provider_id: Uuid,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_after: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub analysis: Option<AnalysisResponse>,
+}
+
+impl AnalysisJobResponse {
+    pub fn new(job: AnalysisJob, analysis: Option<AnalysisResponse>) -> Self {
+        Self {
+            id: job.id,
+            kind: job.kind,
+            use_tools: job.use_tools,
+            ticker: job.ticker,
+            agent_id: job.agent_id,
+            provider_id: job.provider_id,
+            status: job.status,
+            attempts: job.attempts,
+            max_attempts: job.max_attempts,
+            run_after: job.run_after,
+            last_error: job.last_error,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            created_at: job.created_at,
+            analysis,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AnalysisListQuery {
+    pub ticker: Option<String>,
+    pub agent: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct CompareAnalysesQuery {
+    pub left: Uuid,
+    pub right: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct AnalysisSummary {
+    pub id: Uuid,
+    pub ticker: String,
+    pub agent_slug: String,
+    pub agent_name: String,
+    pub provider_id: Uuid,
+    pub verdict: Option<String>,
+    pub conviction: Option<u8>,
+    pub intrinsic_value_low: Option<f64>,
+    pub intrinsic_value_high: Option<f64>,
+    pub margin_of_safety: Option<f64>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct AnalysisComparisonResponse {
+    pub left: AnalysisSummary,
+    pub right: AnalysisSummary,
+    pub diff: crate::comparison::AnalysisDiff,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::committees)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Committee {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub provider_id: Uuid,
+    pub ticker: String,
+    pub consensus_verdict: Option<String>,
+    pub consensus_score: Option<f64>,
+    pub moderator_output: Option<serde_json::Value>,
+    pub moderator_text: Option<String>,
+    pub llm_usage_id: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::committees)]
+pub struct NewCommittee {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub provider_id: Uuid,
+    pub ticker: String,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::committee_members)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CommitteeMember {
+    pub id: Uuid,
+    pub committee_id: Uuid,
+    pub agent_id: Uuid,
+    pub weight: f64,
+    pub analysis_id: Option<Uuid>,
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::committee_members)]
+pub struct NewCommitteeMember {
+    pub committee_id: Uuid,
+    pub agent_id: Uuid,
+    pub weight: f64,
+}
+
+#[derive(Deserialize)]
+pub struct CommitteeMemberRequest {
+    pub agent_slug: String,
+    pub weight: Option<f64>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateCommitteeRequest {
+    pub ticker: String,
+    pub members: Vec<CommitteeMemberRequest>,
+    pub provider_id: Option<Uuid>,
+    pub use_tools: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct CommitteeMemberResponse {
+    pub agent_slug: String,
+    pub agent_name: String,
+    pub weight: f64,
+    pub analysis_id: Option<Uuid>,
+    pub verdict: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CommitteeResponse {
+    pub id: Uuid,
+    pub ticker: String,
+    pub provider_id: Uuid,
+    pub status: String,
+    pub use_tools: bool,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub consensus_verdict: Option<String>,
+    pub consensus_score: Option<f64>,
+    pub moderator_output: Option<serde_json::Value>,
+    pub members: Vec<CommitteeMemberResponse>,
+    pub created_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+impl CommitteeResponse {
+    pub fn new(
+        job: AnalysisJob,
+        committee: Committee,
+        members: Vec<CommitteeMemberResponse>,
+    ) -> Self {
+        Self {
+            id: committee.id,
+            ticker: committee.ticker,
+            provider_id: committee.provider_id,
+            status: job.status,
+            use_tools: job.use_tools,
+            attempts: job.attempts,
+            last_error: job.last_error,
+            consensus_verdict: committee.consensus_verdict,
+            consensus_score: committee.consensus_score,
+            moderator_output: committee.moderator_output,
+            members,
+            created_at: committee.created_at,
+            finished_at: job.finished_at,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = crate::schema::mcp_tool_access)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct McpToolAccess {
+    pub tool_name: String,
+    pub allowed_roles: Vec<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::mcp_tool_access)]
+pub struct NewMcpToolAccess {
+    pub tool_name: String,
+    pub allowed_roles: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateMcpToolAccessRequest {
+    pub allowed_roles: Vec<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::mcp_usage)]
+pub struct NewMcpUsage {
+    pub user_id: Uuid,
+    pub method: String,
+    pub target: Option<String>,
+    pub is_error: bool,
+    pub duration_ms: i32,
+}
+
+#[derive(Serialize)]
+pub struct McpUsageStat {
+    pub user_id: Uuid,
+    pub username: String,
+    pub method: String,
+    pub target: Option<String>,
+    pub calls: i64,
+    pub errors: i64,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::watchlists)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Watchlist {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::watchlists)]
+pub struct NewWatchlist {
+    pub user_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::watchlist_items)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WatchlistItem {
+    pub id: Uuid,
+    pub watchlist_id: Uuid,
+    pub ticker: String,
+    pub notes: Option<String>,
+    pub position: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::watchlist_items)]
+pub struct NewWatchlistItem {
+    pub watchlist_id: Uuid,
+    pub ticker: String,
+    pub notes: Option<String>,
+    pub position: i32,
+}
+
+#[derive(Deserialize)]
+pub struct CreateWatchlistRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::watchlists)]
+pub struct UpdateWatchlistRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateWatchlistItemRequest {
+    pub ticker: String,
+    pub notes: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateWatchlistItemRequest {
+    pub notes: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ReorderWatchlistRequest {
+    /// Every ticker on the watchlist, in the new order.
+    pub tickers: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct WatchlistSummary {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub item_count: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct WatchlistItemView {
+    pub ticker: String,
+    pub notes: Option<String>,
+    pub position: i32,
+    pub added_at: NaiveDateTime,
+    pub price_date: Option<NaiveDate>,
+    pub last_price: Option<f64>,
+    pub daily_change: Option<f64>,
+    pub daily_change_percent: Option<f64>,
+    pub last_analysis_id: Option<Uuid>,
+    pub last_verdict: Option<String>,
+    pub last_analyzed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+pub struct WatchlistResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub items: Vec<WatchlistItemView>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::portfolios)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Portfolio {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub cost_basis_method: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::portfolios)]
+pub struct NewPortfolio {
+    pub user_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub cost_basis_method: String,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::portfolio_transactions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PortfolioTransaction {
+    pub id: Uuid,
+    pub portfolio_id: Uuid,
+    pub ticker: String,
+    pub kind: String,
+    pub trade_date: NaiveDate,
+    pub quantity: Option<f64>,
+    pub price: Option<f64>,
+    pub amount: Option<f64>,
+    pub fees: f64,
+    pub notes: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub external_id: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::portfolio_transactions)]
+pub struct NewPortfolioTransaction {
+    pub portfolio_id: Uuid,
+    pub ticker: String,
+    pub kind: String,
+    pub trade_date: NaiveDate,
+    pub quantity: Option<f64>,
+    pub price: Option<f64>,
+    pub amount: Option<f64>,
+    pub fees: f64,
+    pub notes: Option<String>,
+    pub external_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreatePortfolioRequest {
+    pub name: String,
+    pub description: Option<String>,
+    /// "fifo" (default) or "average".
+    pub cost_basis_method: Option<String>,
+}
+
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::portfolios)]
+pub struct UpdatePortfolioRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub cost_basis_method: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CreatePortfolioTransactionRequest {
+    pub ticker: String,
+    /// "buy", "sell" or "dividend".
+    pub kind: String,
+    pub trade_date: NaiveDate,
+    /// Shares traded, required for buys and sells.
+    pub quantity: Option<f64>,
+    /// Price per share, required for buys and sells.
+    pub price: Option<f64>,
+    /// Cash received, required for dividends.
+    pub amount: Option<f64>,
+    pub fees: Option<f64>,
+    pub notes: Option<String>,
+    /// The broker's id for the transaction, unique per portfolio.
+    pub external_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PortfolioSummary {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub cost_basis_method: String,
+    pub transaction_count: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct PortfolioResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub cost_basis_method: String,
+    pub performance: Performance,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct PortfolioRiskQuery {
+    /// Ticker whose stored prices are the benchmark for beta; defaults to SPY.
+    pub benchmark: Option<String>,
+    /// Calendar days of price history to measure over; defaults to one year.
+    pub days: Option<i64>,
+    /// Confidence level for VaR and CVaR; defaults to 0.95.
+    pub confidence: Option<f64>,
+    /// Annual risk-free rate as a fraction for Sharpe and Sortino; defaults to 0.
+    pub risk_free_rate: Option<f64>,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::import_mappings)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ImportMapping {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub mapping: serde_json::Value,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::import_mappings)]
+pub struct NewImportMapping {
+    pub user_id: Uuid,
+    pub name: String,
+    pub mapping: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+pub struct CreateImportMappingRequest {
+    pub name: String,
+    pub mapping: ColumnMapping,
+}
+
+#[derive(Serialize)]
+pub struct ImportMappingsResponse {
+    /// Built-in mappings for common broker exports, by name.
+    pub presets: BTreeMap<String, ColumnMapping>,
+    pub saved: Vec<ImportMapping>,
+}
+
+/// A CSV to import and the mapping to read it with: a built-in `preset`, a saved
+/// `mapping_id` or an inline `mapping`.
+#[derive(Deserialize)]
+pub struct ImportTransactionsRequest {
+    pub csv: String,
+    pub preset: Option<String>,
+    pub mapping_id: Option<Uuid>,
+    pub mapping: Option<ColumnMapping>,
+    /// Import the valid rows even when others have errors; by default nothing is imported
+    /// unless every row is valid, a duplicate or skipped.
+    #[serde(default)]
+    pub skip_invalid: bool,
+}
+
+#[derive(Serialize)]
+pub struct ImportRowReport {
+    /// Line of the row in the CSV, starting at 1.
+    pub line: u64,
+    /// "valid" (preview only), "imported", "duplicate", "skipped" or "invalid".
+    pub status: String,
+    pub transaction: Option<CreatePortfolioTransactionRequest>,
+    /// Why the row was skipped, flagged as a duplicate or rejected.
+    pub messages: Vec<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct ImportReport {
+    pub committed: bool,
+    /// Why the file could not be read at all.
+    pub error: Option<String>,
+    pub valid: usize,
+    pub imported: usize,
+    pub duplicates: usize,
+    pub skipped: usize,
+    pub invalid: usize,
+    pub rows: Vec<ImportRowReport>,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::alert_rules)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AlertRule {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub ticker: String,
+    pub condition: String,
+    pub threshold: Option<f64>,
+    pub agent_id: Option<Uuid>,
+    pub cooldown_minutes: i32,
+    pub is_active: bool,
+    pub last_triggered_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::alert_rules)]
+pub struct NewAlertRule {
+    pub user_id: Uuid,
+    pub ticker: String,
+    pub condition: String,
+    pub threshold: Option<f64>,
+    pub agent_id: Option<Uuid>,
+    pub cooldown_minutes: i32,
+}
+
+#[derive(Deserialize)]
+pub struct CreateAlertRuleRequest {
+    pub ticker: String,
+    /// "price_above", "price_below", "rsi_above", "rsi_below" or "below_intrinsic_value".
+    pub condition: String,
+    /// Price or RSI level; for "below_intrinsic_value" an optional margin of safety fraction.
+    pub threshold: Option<f64>,
+    /// Agent whose latest analysis supplies the intrinsic value; defaults to "buffett".
+    pub agent_slug: Option<String>,
+    /// Minutes before the rule may trigger again; defaults to one day.
+    pub cooldown_minutes: Option<i32>,
+}
+
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::alert_rules)]
+pub struct UpdateAlertRuleRequest {
+    pub threshold: Option<f64>,
+    pub cooldown_minutes: Option<i32>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::alert_triggers)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AlertTrigger {
+    pub id: Uuid,
+    pub rule_id: Uuid,
+    pub user_id: Uuid,
+    pub ticker: String,
+    pub trade_date: NaiveDate,
+    pub value: f64,
+    pub threshold: f64,
+    pub analysis_id: Option<Uuid>,
+    pub message: String,
+    pub triggered_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::alert_triggers)]
+pub struct NewAlertTrigger {
+    pub rule_id: Uuid,
+    pub user_id: Uuid,
+    pub ticker: String,
+    pub trade_date: NaiveDate,
+    pub value: f64,
+    pub threshold: f64,
+    pub analysis_id: Option<Uuid>,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct AlertTriggerQuery {
+    pub rule_id: Option<Uuid>,
+    pub ticker: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::notifications)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Notification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub kind: String,
+    pub title: String,
+    pub body: String,
+    pub data: serde_json::Value,
+    pub read_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::notifications)]
+pub struct NewNotification {
+    pub user_id: Uuid,
+    pub kind: String,
+    pub title: String,
+    pub body: String,
+    pub data: serde_json::Value,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::notification_settings)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NotificationSettings {
+    pub user_id: Uuid,
+    pub email_enabled: bool,
+    pub webhook_url: Option<String>,
+    pub webhook_secret: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::notification_settings)]
+#[diesel(treat_none_as_null = true)]
+pub struct NewNotificationSettings {
+    pub user_id: Uuid,
+    pub email_enabled: bool,
+    pub webhook_url: Option<String>,
+    pub webhook_secret: Option<String>,
+}
+
+/// One queued delivery of a notification over email or a webhook.
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::notification_outbox)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NotificationDelivery {
+    pub id: Uuid,
+    pub notification_id: Uuid,
+    pub channel: String,
+    pub destination: String,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub sent_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::notification_outbox)]
+pub struct NewNotificationDelivery {
+    pub notification_id: Uuid,
+    pub channel: String,
+    pub destination: String,
+}
+
+#[derive(Deserialize)]
+pub struct NotificationListQuery {
+    /// Only unread notifications when true.
+    pub unread: Option<bool>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct NotificationsResponse {
+    pub unread_count: i64,
+    pub notifications: Vec<Notification>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateNotificationRequest {
+    pub read: bool,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateNotificationSettingsRequest {
+    pub email_enabled: Option<bool>,
+    /// An http(s) URL to post notifications to; an empty string removes the webhook.
+    pub webhook_url: Option<String>,
+    /// Replace the webhook signing secret with a new one.
+    pub rotate_webhook_secret: Option<bool>,
+}
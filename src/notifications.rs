@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use diesel::dsl::{now, IntervalDsl};
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{
+    auth,
+    database::DbPool,
+    mailer::{Email, MailError, Mailer},
+    models::{
+        NewNotification, NewNotificationDelivery, Notification, NotificationDelivery,
+        NotificationSettings,
+    },
+    schema::{notification_outbox, notification_settings, notifications, users},
+};
+
+pub const KIND_ALERT_TRIGGERED: &str = "alert_triggered";
+pub const KIND_JOB_SUCCEEDED: &str = "job_succeeded";
+pub const KIND_JOB_FAILED: &str = "job_failed";
+
+pub const CHANNEL_EMAIL: &str = "email";
+pub const CHANNEL_WEBHOOK: &str = "webhook";
+
+pub const DELIVERY_PENDING: &str = "pending";
+pub const DELIVERY_SENT: &str = "sent";
+pub const DELIVERY_FAILED: &str = "failed";
+
+/// Headers of outgoing webhooks. The signature is `sha256=` followed by the hex HMAC-SHA256
+/// of `"{timestamp}.{body}"` keyed with the user's webhook secret.
+pub const WEBHOOK_ID_HEADER: &str = "X-Webhook-Id";
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// How long the dispatcher waits before polling an empty outbox again.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Deliveries claimed per poll.
+const BATCH_SIZE: i64 = 20;
+/// A claimed delivery becomes due again after this long, so one lost with its process is retried.
+const DELIVERY_LEASE_SECS: i32 = 300;
+/// Retry delay is `RETRY_BASE_DELAY_SECS * 2^(attempt - 1)`, capped at `RETRY_MAX_DELAY_SECS`.
+const RETRY_BASE_DELAY_SECS: i32 = 30;
+const RETRY_MAX_DELAY_SECS: i32 = 3600;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Store an in-app notification for `user_id` and queue its email and webhook deliveries.
+///
+/// Call it inside the transaction that records the event, so nothing is sent for an event
+/// that is rolled back.
+pub fn notify(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    kind: &str,
+    title: String,
+    body: String,
+    data: serde_json::Value,
+) -> QueryResult<Notification> {
+    let notification = diesel::insert_into(notifications::table)
+        .values(&NewNotification {
+            user_id,
+            kind: kind.to_string(),
+            title,
+            body,
+            data,
+        })
+        .returning(Notification::as_select())
+        .get_result(conn)?;
+
+    let settings = notification_settings::table
+        .find(user_id)
+        .select(NotificationSettings::as_select())
+        .first(conn)
+        .optional()?;
+    let Some(settings) = settings else {
+        return Ok(notification);
+    };
+
+    let mut deliveries = Vec::new();
+    if settings.email_enabled {
+        let email: String = users::table
+            .find(user_id)
+            .select(users::email)
+            .first(conn)?;
+        deliveries.push(NewNotificationDelivery {
+            notification_id: notification.id,
+            channel: CHANNEL_EMAIL.to_string(),
+            destination: email,
+        });
+    }
+    if let Some(url) = settings.webhook_url {
+        deliveries.push(NewNotificationDelivery {
+            notification_id: notification.id,
+            channel: CHANNEL_WEBHOOK.to_string(),
+            destination: url,
+        });
+    }
+    if !deliveries.is_empty() {
+        diesel::insert_into(notification_outbox::table)
+            .values(&deliveries)
+            .execute(conn)?;
+    }
+
+    Ok(notification)
+}
+
+/// A new random webhook signing secret.
+pub fn generate_webhook_secret() -> String {
+    format!("whsec_{}", auth::generate_opaque_token())
+}
+
+/// Hex HMAC-SHA256 of `"{timestamp}.{body}"`, as sent in the signature header.
+pub fn sign_webhook(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Whether the webhook URL's host may be posted to from the server: not `localhost` and not a
+/// loopback, private, link-local or otherwise internal IP address. Host names are checked
+/// again against the addresses they resolve to when the webhook is sent.
+pub fn is_public_webhook_host(url: &reqwest::Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => {
+            let host = host.trim_end_matches('.').to_ascii_lowercase();
+            host != "localhost" && !host.ends_with(".localhost")
+        }
+    }
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // 0.0.0.0/8, shared address space 100.64.0.0/10 and reserved 240.0.0.0/4
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // unique local fc00::/7 and link-local fe80::/10
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+/// Resolves webhook hosts to their public addresses only, so a host name pointing at an
+/// internal address cannot be used to reach it.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            let addrs: reqwest::dns::Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+pub struct DispatcherConfig {
+    pub mailer: Arc<dyn Mailer>,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum DeliveryError {
+    #[error("{0}")]
+    Retryable(String),
+    #[error("{0}")]
+    Permanent(String),
+}
+
+/// A delivery claimed from the outbox with what is needed to send it.
+struct Claimed {
+    delivery: NotificationDelivery,
+    notification: Notification,
+    webhook_secret: Option<String>,
+}
+
+struct Dispatcher {
+    mailer: Arc<dyn Mailer>,
+    http: reqwest::Client,
+}
+
+/// Start the task that sends queued email and webhook deliveries on the current Tokio runtime.
+pub fn spawn_dispatcher(pool: DbPool, config: DispatcherConfig) {
+    let http = reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(Arc::new(PublicResolver))
+        .build()
+        .expect("Failed to build the webhook HTTP client");
+
+    tokio::spawn(dispatch_loop(
+        pool,
+        Dispatcher {
+            mailer: config.mailer,
+            http,
+        },
+    ));
+    tracing::info!("Started notification dispatcher");
+}
+
+async fn dispatch_loop(pool: DbPool, dispatcher: Dispatcher) {
+    loop {
+        let claimed = match pool.get() {
+            Ok(mut conn) => claim_due_deliveries(&mut conn),
+            Err(e) => {
+                tracing::error!("Notification dispatcher could not get a connection: {}", e);
+                tokio::time::sleep(POLL_INTERVAL).await;
+                continue;
+            }
+        };
+
+        match claimed {
+            Ok(batch) if batch.is_empty() => tokio::time::sleep(POLL_INTERVAL).await,
+            Ok(batch) => {
+                for claimed in batch {
+                    let outcome = dispatcher.deliver(&claimed).await;
+                    let recorded = pool.get().map_err(|e| e.to_string()).and_then(|mut conn| {
+                        record_outcome(&mut conn, &claimed.delivery, outcome)
+                            .map_err(|e| e.to_string())
+                    });
+                    if let Err(e) = recorded {
+                        tracing::error!(
+                            "Failed to record notification delivery {}: {}",
+                            claimed.delivery.id,
+                            e
+                        );
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::error!("Failed to claim notification deliveries: {}", e);
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// Lock due deliveries with `FOR UPDATE SKIP LOCKED`, count the attempt and lease them.
+fn claim_due_deliveries(conn: &mut PgConnection) -> QueryResult<Vec<Claimed>> {
+    conn.transaction(|conn| {
+        let ids: Vec<Uuid> = notification_outbox::table
+            .filter(notification_outbox::status.eq(DELIVERY_PENDING))
+            .filter(notification_outbox::next_attempt_at.le(now))
+            .order(notification_outbox::next_attempt_at.asc())
+            .limit(BATCH_SIZE)
+            .select(notification_outbox::id)
+            .for_update()
+            .skip_locked()
+            .load(conn)?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        diesel::update(notification_outbox::table.filter(notification_outbox::id.eq_any(&ids)))
+            .set((
+                notification_outbox::attempts.eq(notification_outbox::attempts + 1),
+                notification_outbox::next_attempt_at.eq(now + DELIVERY_LEASE_SECS.seconds()),
+                notification_outbox::updated_at.eq(now),
+            ))
+            .execute(conn)?;
+
+        let rows: Vec<(NotificationDelivery, Notification)> = notification_outbox::table
+            .inner_join(notifications::table)
+            .filter(notification_outbox::id.eq_any(&ids))
+            .select((NotificationDelivery::as_select(), Notification::as_select()))
+            .load(conn)?;
+
+        let user_ids: Vec<Uuid> = rows.iter().map(|(_, n)| n.user_id).collect();
+        let secrets: HashMap<Uuid, Option<String>> = notification_settings::table
+            .filter(notification_settings::user_id.eq_any(&user_ids))
+            .select((
+                notification_settings::user_id,
+                notification_settings::webhook_secret,
+            ))
+            .load(conn)?
+            .into_iter()
+            .collect();
+
+        Ok(rows
+            .into_iter()
+            .map(|(delivery, notification)| Claimed {
+                webhook_secret: secrets.get(&notification.user_id).cloned().flatten(),
+                delivery,
+                notification,
+            })
+            .collect())
+    })
+}
+
+/// Mark the delivery sent, schedule another attempt with exponential backoff, or fail it.
+fn record_outcome(
+    conn: &mut PgConnection,
+    delivery: &NotificationDelivery,
+    outcome: Result<(), DeliveryError>,
+) -> QueryResult<()> {
+    let target = notification_outbox::table.find(delivery.id);
+    match outcome {
+        Ok(()) => diesel::update(target)
+            .set((
+                notification_outbox::status.eq(DELIVERY_SENT),
+                notification_outbox::last_error.eq(None::<String>),
+                notification_outbox::sent_at.eq(now),
+                notification_outbox::updated_at.eq(now),
+            ))
+            .execute(conn)?,
+        Err(DeliveryError::Retryable(error)) if delivery.attempts < delivery.max_attempts => {
+            tracing::warn!(
+                "Notification delivery {} failed, will retry: {}",
+                delivery.id,
+                error
+            );
+            let delay = RETRY_BASE_DELAY_SECS
+                .saturating_mul(1 << (delivery.attempts - 1).clamp(0, 16))
+                .min(RETRY_MAX_DELAY_SECS);
+            diesel::update(target)
+                .set((
+                    notification_outbox::last_error.eq(error),
+                    notification_outbox::next_attempt_at.eq(now + delay.seconds()),
+                    notification_outbox::updated_at.eq(now),
+                ))
+                .execute(conn)?
+        }
+        Err(error) => {
+            tracing::warn!("Notification delivery {} failed: {}", delivery.id, error);
+            diesel::update(target)
+                .set((
+                    notification_outbox::status.eq(DELIVERY_FAILED),
+                    notification_outbox::last_error.eq(error.to_string()),
+                    notification_outbox::updated_at.eq(now),
+                ))
+                .execute(conn)?
+        }
+    };
+    Ok(())
+}
+
+impl Dispatcher {
+    async fn deliver(&self, claimed: &Claimed) -> Result<(), DeliveryError> {
+        match claimed.delivery.channel.as_str() {
+            CHANNEL_EMAIL => self.send_email(claimed).await,
+            CHANNEL_WEBHOOK => self.post_webhook(claimed).await,
+            channel => Err(DeliveryError::Permanent(format!(
+                "unknown channel {}",
+                channel
+            ))),
+        }
+    }
+
+    async fn send_email(&self, claimed: &Claimed) -> Result<(), DeliveryError> {
+        let email = Email {
+            to: claimed.delivery.destination.clone(),
+            subject: claimed.notification.title.clone(),
+            body: claimed.notification.body.clone(),
+        };
+
+        self.mailer.send(&email).await.map_err(|e| match e {
+            MailError::Temporary(e) => DeliveryError::Retryable(e),
+            MailError::Permanent(e) => DeliveryError::Permanent(e),
+        })
+    }
+
+    async fn post_webhook(&self, claimed: &Claimed) -> Result<(), DeliveryError> {
+        let secret = claimed.webhook_secret.as_deref().ok_or_else(|| {
+            DeliveryError::Permanent("webhook is no longer configured".to_string())
+        })?;
+        let notification = &claimed.notification;
+        let body = serde_json::to_vec(&serde_json::json!({
+            "id": notification.id,
+            "delivery_id": claimed.delivery.id,
+            "kind": notification.kind,
+            "title": notification.title,
+            "body": notification.body,
+            "data": notification.data,
+            "created_at": notification.created_at,
+        }))
+        .map_err(|e| DeliveryError::Permanent(e.to_string()))?;
+        let url = reqwest::Url::parse(&claimed.delivery.destination)
+            .map_err(|e| DeliveryError::Permanent(e.to_string()))?;
+        if !is_public_webhook_host(&url) {
+            return Err(DeliveryError::Permanent(
+                "webhook host is not a public address".to_string(),
+            ));
+        }
+        let timestamp = Utc::now().timestamp();
+
+        let response = self
+            .http
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(WEBHOOK_ID_HEADER, claimed.delivery.id.to_string())
+            .header(WEBHOOK_TIMESTAMP_HEADER, timestamp.to_string())
+            .header(
+                WEBHOOK_SIGNATURE_HEADER,
+                format!("sha256={}", sign_webhook(secret, timestamp, &body)),
+            )
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| DeliveryError::Retryable(e.to_string()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else if status.is_server_error()
+            || status == reqwest::StatusCode::REQUEST_TIMEOUT
+            || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        {
+            Err(DeliveryError::Retryable(format!(
+                "webhook returned {}",
+                status
+            )))
+        } else {
+            Err(DeliveryError::Permanent(format!(
+                "webhook returned {}",
+                status
+            )))
+        }
+    }
+}
@@ -0,0 +1,594 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::{
+    auth::{generate_opaque_token, hash_password, hash_token},
+    environments,
+    models::{NewOidcLoginState, NewUser, NewUserIdentity, OidcLoginState, User, UserIdentity},
+    permissions::{self, ADMIN_ROLE},
+    schema::{oidc_login_states, roles, user_identities, users},
+    sessions,
+};
+
+/// How long the identity provider has to send the browser back.
+const LOGIN_STATE_TTL: chrono::Duration = chrono::Duration::minutes(10);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum OidcError {
+    #[error("identity provider request failed: {0}")]
+    Provider(String),
+    #[error("authorization code rejected: {0}")]
+    CodeRejected(String),
+    #[error("invalid ID token: {0}")]
+    InvalidIdToken(String),
+}
+
+/// Why a verified identity cannot sign in.
+#[derive(Debug, thiserror::Error)]
+pub enum SignInError {
+    #[error("the identity provider sent no email address")]
+    MissingEmail,
+    #[error("a user with this email address exists; sign in and link the identity")]
+    EmailTaken,
+    #[error("the identity is linked to another user")]
+    IdentityTaken,
+    #[error("none of the user's groups grants a role")]
+    NoRole,
+    #[error("role {0} in OIDC_ROLE_MAPPING or OIDC_DEFAULT_ROLE does not exist")]
+    UnknownRole(String),
+    #[error("the identity provider would demote the last active admin")]
+    LastAdmin,
+    #[error("the user is deactivated")]
+    Inactive,
+    #[error("failed to hash password: {0}")]
+    PasswordHash(#[from] bcrypt::BcryptError),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// Issuer URL; the provider's metadata is read from `{issuer}/.well-known/openid-configuration`.
+    pub issuer: String,
+    pub client_id: String,
+    /// Sent with HTTP Basic authentication when set; public clients rely on PKCE alone.
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    pub scopes: String,
+    /// ID token claim with the user's groups.
+    pub groups_claim: String,
+    /// Groups and the roles they grant, first match wins. When empty, roles are not managed
+    /// by the identity provider.
+    pub role_mapping: Vec<(String, String)>,
+    /// Role of users in none of the mapped groups; `None` refuses them.
+    pub default_role: Option<String>,
+}
+
+impl OidcConfig {
+    /// `None` when single sign-on is not configured.
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            issuer: environments::get_oidc_issuer()?,
+            client_id: environments::get_oidc_client_id()?,
+            client_secret: environments::get_oidc_client_secret(),
+            redirect_uri: environments::get_oidc_redirect_uri(),
+            scopes: environments::get_oidc_scopes(),
+            groups_claim: environments::get_oidc_groups_claim(),
+            role_mapping: environments::get_oidc_role_mapping(),
+            default_role: environments::get_oidc_default_role(),
+        })
+    }
+}
+
+/// Claims of a verified ID token.
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    pub nonce: Option<String>,
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
+}
+
+impl IdTokenClaims {
+    /// Groups listed in `claim`, as an array of strings or a single string.
+    pub fn groups(&self, claim: &str) -> Vec<String> {
+        match self.other.get(claim) {
+            Some(serde_json::Value::Array(groups)) => groups
+                .iter()
+                .filter_map(|group| group.as_str().map(str::to_string))
+                .collect(),
+            Some(serde_json::Value::String(group)) => vec![group.clone()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+struct Provider {
+    metadata: ProviderMetadata,
+    jwks: JwkSet,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+/// Client of the OpenID Connect provider. Its metadata and signing keys are fetched on first
+/// use and the keys again when a token names an unknown one. Cheap to clone.
+#[derive(Clone)]
+pub struct OidcClient {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    config: OidcConfig,
+    http: reqwest::Client,
+    provider: RwLock<Option<Arc<Provider>>>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .expect("Failed to build the OpenID Connect HTTP client");
+
+        Self {
+            shared: Arc::new(Shared {
+                config,
+                http,
+                provider: RwLock::new(None),
+            }),
+        }
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.shared.config
+    }
+
+    /// The provider URL that starts an authorization code login with PKCE.
+    pub async fn authorization_url(&self, login: &LoginStart) -> Result<String, OidcError> {
+        let provider = self.provider(false).await?;
+        let config = self.config();
+
+        let url = Url::parse_with_params(
+            &provider.metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", config.client_id.as_str()),
+                ("redirect_uri", config.redirect_uri.as_str()),
+                ("scope", config.scopes.as_str()),
+                ("state", login.state.as_str()),
+                ("nonce", login.nonce.as_str()),
+                (
+                    "code_challenge",
+                    pkce_challenge(&login.code_verifier).as_str(),
+                ),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| OidcError::Provider(format!("invalid authorization endpoint: {}", e)))?;
+
+        Ok(url.into())
+    }
+
+    /// Redeem an authorization code and return the claims of the verified ID token.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        login: &OidcLoginState,
+    ) -> Result<IdTokenClaims, OidcError> {
+        let provider = self.provider(false).await?;
+        let config = self.config();
+
+        let mut request = self
+            .shared
+            .http
+            .post(&provider.metadata.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", config.redirect_uri.as_str()),
+                ("client_id", config.client_id.as_str()),
+                ("code_verifier", login.code_verifier.as_str()),
+            ]);
+        if let Some(secret) = &config.client_secret {
+            request = request.basic_auth(&config.client_id, Some(secret));
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| OidcError::Provider(e.to_string()))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::BAD_REQUEST {
+            // e.g. `invalid_grant` for an expired code or a wrong PKCE verifier
+            let body = response.text().await.unwrap_or_default();
+            return Err(OidcError::CodeRejected(body));
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(OidcError::Provider(format!(
+                "token endpoint answered {}: {}",
+                status, body
+            )));
+        }
+        let tokens: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| OidcError::Provider(e.to_string()))?;
+        let id_token = tokens
+            .id_token
+            .ok_or_else(|| OidcError::Provider("no ID token in the token response".to_string()))?;
+
+        self.verify_id_token(&id_token, &login.nonce).await
+    }
+
+    /// The role the user's groups grant, or the default role.
+    pub fn role_for(&self, groups: &[String]) -> Option<String> {
+        let config = self.config();
+        config
+            .role_mapping
+            .iter()
+            .find(|(group, _)| groups.contains(group))
+            .map(|(_, role)| role.clone())
+            .or_else(|| config.default_role.clone())
+    }
+
+    async fn verify_id_token(&self, token: &str, nonce: &str) -> Result<IdTokenClaims, OidcError> {
+        let header = decode_header(token).map_err(|e| OidcError::InvalidIdToken(e.to_string()))?;
+        // Only the provider's published asymmetric keys may sign ID tokens
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(OidcError::InvalidIdToken(format!(
+                "unsupported algorithm {:?}",
+                header.alg
+            )));
+        }
+
+        let mut provider = self.provider(false).await?;
+        if find_key(&provider.jwks, header.kid.as_deref()).is_none() {
+            // The provider may have rotated its keys since they were fetched
+            provider = self.provider(true).await?;
+        }
+        let jwk = find_key(&provider.jwks, header.kid.as_deref())
+            .ok_or_else(|| OidcError::InvalidIdToken("unknown signing key".to_string()))?;
+        let key =
+            DecodingKey::from_jwk(jwk).map_err(|e| OidcError::InvalidIdToken(e.to_string()))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&provider.metadata.issuer]);
+        validation.set_audience(&[&self.config().client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = decode::<IdTokenClaims>(token, &key, &validation)
+            .map_err(|e| OidcError::InvalidIdToken(e.to_string()))?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError::InvalidIdToken("nonce mismatch".to_string()));
+        }
+        Ok(claims)
+    }
+
+    /// The provider's metadata and keys, fetched when missing or when `refresh` is set.
+    async fn provider(&self, refresh: bool) -> Result<Arc<Provider>, OidcError> {
+        if !refresh {
+            if let Some(provider) = self.shared.provider.read().await.as_ref() {
+                return Ok(provider.clone());
+            }
+        }
+
+        let mut cached = self.shared.provider.write().await;
+        let metadata = match cached.as_ref() {
+            Some(provider) => provider.metadata.clone(),
+            None => {
+                let url = format!("{}/.well-known/openid-configuration", self.config().issuer);
+                let metadata: ProviderMetadata = self.get_json(&url).await?;
+                if metadata.issuer.trim_end_matches('/') != self.config().issuer {
+                    return Err(OidcError::Provider(format!(
+                        "metadata names issuer {}, expected {}",
+                        metadata.issuer,
+                        self.config().issuer
+                    )));
+                }
+                metadata
+            }
+        };
+        let jwks: JwkSet = self.get_json(&metadata.jwks_uri).await?;
+
+        let provider = Arc::new(Provider { metadata, jwks });
+        *cached = Some(provider.clone());
+        Ok(provider)
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, OidcError> {
+        self.shared
+            .http
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| OidcError::Provider(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| OidcError::Provider(e.to_string()))
+    }
+}
+
+/// The secrets of a login waiting for the provider's redirect.
+pub struct LoginStart {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+/// Remember a new login until the provider redirects back. `link_user_id` is set when a signed
+/// in user links an identity instead of signing in.
+pub fn begin_login(conn: &mut PgConnection, link_user_id: Option<Uuid>) -> QueryResult<LoginStart> {
+    let login = LoginStart {
+        state: generate_opaque_token(),
+        nonce: generate_opaque_token(),
+        code_verifier: generate_opaque_token(),
+    };
+
+    diesel::delete(oidc_login_states::table.filter(oidc_login_states::expires_at.le(now)))
+        .execute(conn)?;
+    diesel::insert_into(oidc_login_states::table)
+        .values(&NewOidcLoginState {
+            state_hash: hash_token(&login.state),
+            code_verifier: login.code_verifier.clone(),
+            nonce: login.nonce.clone(),
+            link_user_id,
+            expires_at: Utc::now().naive_utc() + LOGIN_STATE_TTL,
+        })
+        .execute(conn)?;
+
+    Ok(login)
+}
+
+/// Take the login started with `state`, a sign-in when `link_user_id` is `None` or else a link
+/// started by that user. `None` if there is no such login or it is used or expired.
+pub fn take_login(
+    conn: &mut PgConnection,
+    state: &str,
+    link_user_id: Option<Uuid>,
+) -> QueryResult<Option<OidcLoginState>> {
+    diesel::delete(
+        oidc_login_states::table
+            .filter(oidc_login_states::state_hash.eq(hash_token(state)))
+            .filter(oidc_login_states::link_user_id.is_not_distinct_from(link_user_id))
+            .filter(oidc_login_states::expires_at.gt(now)),
+    )
+    .returning(OidcLoginState::as_select())
+    .get_result(conn)
+    .optional()
+}
+
+/// The user a verified identity signs in as. Known identities sign in as their user; otherwise
+/// the identity is linked to the user with the same verified email address, or a new user is
+/// created. When groups are mapped to roles, the user's role follows their groups, and a user
+/// whose role changes is signed out of their other sessions.
+pub fn sign_in(
+    conn: &mut PgConnection,
+    client: &OidcClient,
+    claims: &IdTokenClaims,
+) -> Result<User, SignInError> {
+    let config = client.config();
+    let role = client.role_for(&claims.groups(&config.groups_claim));
+
+    let (user, revoked) = conn.transaction(|conn| {
+        let identity: Option<UserIdentity> = user_identities::table
+            .filter(user_identities::issuer.eq(&config.issuer))
+            .filter(user_identities::subject.eq(&claims.sub))
+            .select(UserIdentity::as_select())
+            .first(conn)
+            .optional()?;
+
+        let user = match identity {
+            Some(identity) => {
+                diesel::update(user_identities::table.find(identity.id))
+                    .set((
+                        user_identities::email.eq(&claims.email),
+                        user_identities::last_login_at.eq(now),
+                    ))
+                    .execute(conn)?;
+                users::table
+                    .find(identity.user_id)
+                    .select(User::as_select())
+                    .first(conn)?
+            }
+            None => {
+                let email = claims.email.as_deref().ok_or(SignInError::MissingEmail)?;
+                let existing: Option<User> = users::table
+                    .filter(users::email.eq(email))
+                    .select(User::as_select())
+                    .first(conn)
+                    .optional()?;
+                let user = match existing {
+                    // Both sides must have proven the address, or whoever registered it
+                    // unverified would get the identity; its owner can link it instead
+                    Some(user) if claims.email_verified && user.email_verified_at.is_some() => user,
+                    Some(_) => return Err(SignInError::EmailTaken),
+                    None => {
+                        let role = role.clone().ok_or(SignInError::NoRole)?;
+                        ensure_role_exists(conn, &role)?;
+                        create_user(conn, claims, email, role)?
+                    }
+                };
+                insert_identity(conn, &config.issuer, claims, user.id)?;
+                user
+            }
+        };
+
+        if !user.is_active {
+            return Err(SignInError::Inactive);
+        }
+        if config.role_mapping.is_empty() {
+            return Ok((user, Vec::new()));
+        }
+        let role = role.ok_or(SignInError::NoRole)?;
+        if user.role == role {
+            return Ok((user, Vec::new()));
+        }
+        ensure_role_exists(conn, &role)?;
+        if role != ADMIN_ROLE && permissions::is_last_active_admin(conn, user.id)? {
+            return Err(SignInError::LastAdmin);
+        }
+        // Sessions started with the old role would keep its access tokens working
+        let revoked = sessions::revoke_all(conn, user.id, None)?;
+        let user = diesel::update(users::table.find(user.id))
+            .set((users::role.eq(role), users::updated_at.eq(now)))
+            .returning(User::as_select())
+            .get_result(conn)?;
+        Ok((user, revoked))
+    })?;
+    sessions::forget(&revoked);
+
+    Ok(user)
+}
+
+/// Fail with `UnknownRole` for a mapped or default role that does not exist.
+fn ensure_role_exists(conn: &mut PgConnection, role: &str) -> Result<(), SignInError> {
+    let exists: i64 = roles::table.find(role).count().get_result(conn)?;
+    if exists == 0 {
+        return Err(SignInError::UnknownRole(role.to_string()));
+    }
+    Ok(())
+}
+
+/// Link a verified identity to a signed in user.
+pub fn link(
+    conn: &mut PgConnection,
+    issuer: &str,
+    claims: &IdTokenClaims,
+    user_id: Uuid,
+) -> Result<UserIdentity, SignInError> {
+    conn.transaction(|conn| {
+        let identity: Option<UserIdentity> = user_identities::table
+            .filter(user_identities::issuer.eq(issuer))
+            .filter(user_identities::subject.eq(&claims.sub))
+            .select(UserIdentity::as_select())
+            .first(conn)
+            .optional()?;
+
+        match identity {
+            Some(identity) if identity.user_id == user_id => Ok(identity),
+            Some(_) => Err(SignInError::IdentityTaken),
+            None => Ok(insert_identity(conn, issuer, claims, user_id)?),
+        }
+    })
+}
+
+fn insert_identity(
+    conn: &mut PgConnection,
+    issuer: &str,
+    claims: &IdTokenClaims,
+    user_id: Uuid,
+) -> QueryResult<UserIdentity> {
+    diesel::insert_into(user_identities::table)
+        .values(&NewUserIdentity {
+            user_id,
+            issuer: issuer.to_string(),
+            subject: claims.sub.clone(),
+            email: claims.email.clone(),
+            last_login_at: Some(Utc::now().naive_utc()),
+        })
+        .returning(UserIdentity::as_select())
+        .get_result(conn)
+}
+
+/// Create the user of a new identity. The username comes from the identity and gets a number
+/// appended if taken; the password is random, so it can only be used after a reset.
+fn create_user(
+    conn: &mut PgConnection,
+    claims: &IdTokenClaims,
+    email: &str,
+    role: String,
+) -> Result<User, SignInError> {
+    let base: String = claims
+        .preferred_username
+        .as_deref()
+        .or_else(|| email.split('@').next())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        .collect();
+    let base = if base.is_empty() {
+        "user".to_string()
+    } else {
+        base
+    };
+
+    let taken: Vec<String> = users::table
+        .filter(users::username.like(format!("{}%", base)))
+        .select(users::username)
+        .load(conn)?;
+    let username = std::iter::once(base.clone())
+        .chain((2..).map(|n| format!("{}-{}", base, n)))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap_or(base);
+
+    let password_hash = hash_password(&generate_opaque_token())?;
+    let user: User = diesel::insert_into(users::table)
+        .values(&NewUser {
+            username,
+            email: email.to_string(),
+            password_hash,
+            role,
+        })
+        .returning(User::as_select())
+        .get_result(conn)?;
+
+    if !claims.email_verified {
+        return Ok(user);
+    }
+    Ok(diesel::update(users::table.find(user.id))
+        .set(users::email_verified_at.eq(now))
+        .returning(User::as_select())
+        .get_result(conn)?)
+}
+
+fn find_key<'a>(jwks: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => jwks.find(kid),
+        // Without a `kid` the provider must have a single key
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+}
+
+/// The S256 PKCE challenge of a code verifier.
+fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
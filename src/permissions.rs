@@ -0,0 +1,98 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    schema::{role_permissions, users},
+    ttl_cache::TtlCache,
+};
+
+pub const ANALYSES_RUN: &str = "analyses:run";
+pub const USERS_READ: &str = "users:read";
+pub const USERS_MANAGE: &str = "users:manage";
+pub const ROLES_READ: &str = "roles:read";
+pub const ROLES_MANAGE: &str = "roles:manage";
+pub const PROVIDERS_READ: &str = "providers:read";
+pub const PROVIDERS_WRITE: &str = "providers:write";
+pub const USAGE_READ: &str = "usage:read";
+pub const MARKET_DATA_WRITE: &str = "market_data:write";
+pub const MCP_READ: &str = "mcp:read";
+pub const MCP_MANAGE: &str = "mcp:manage";
+
+/// Permissions of endpoints under `/api/admin`. Roles with any of them have admin access: they
+/// get past `admin_middleware`, must enroll in two-factor authentication when that is required
+/// for admins, and can give API tokens the `admin` scope.
+const ADMIN_PERMISSIONS: [&str; 10] = [
+    USERS_READ,
+    USERS_MANAGE,
+    ROLES_READ,
+    ROLES_MANAGE,
+    PROVIDERS_READ,
+    PROVIDERS_WRITE,
+    USAGE_READ,
+    MARKET_DATA_WRITE,
+    MCP_READ,
+    MCP_MANAGE,
+];
+
+/// Has every permission, so there is always a role that can manage the others.
+pub const ADMIN_ROLE: &str = "admin";
+/// Given to new users.
+pub const DEFAULT_ROLE: &str = "user";
+/// Roles that cannot be deleted.
+pub const BUILT_IN_ROLES: [&str; 2] = [ADMIN_ROLE, DEFAULT_ROLE];
+
+/// How long a role's permissions are reused. Role changes through the API clear the cache;
+/// permissions granted or removed directly in the database apply once it has passed.
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+static CACHE: LazyLock<TtlCache<String, Vec<String>>> = LazyLock::new(|| TtlCache::new(CACHE_TTL));
+
+/// The permissions of a role, if loaded within `CACHE_TTL`.
+pub fn cached(role: &str) -> Option<Vec<String>> {
+    CACHE.get(role)
+}
+
+/// The permissions of a role, which are cached for `CACHE_TTL`. Unknown roles have none.
+pub fn load(conn: &mut PgConnection, role: &str) -> QueryResult<Vec<String>> {
+    let permissions: Vec<String> = role_permissions::table
+        .filter(role_permissions::role.eq(role))
+        .select(role_permissions::permission)
+        .load(conn)?;
+
+    CACHE.insert(role.to_string(), permissions.clone());
+    Ok(permissions)
+}
+
+/// Drop every cached role after roles change.
+pub fn forget_all() {
+    CACHE.clear();
+}
+
+pub fn has_admin_access(permissions: &[String]) -> bool {
+    permissions
+        .iter()
+        .any(|permission| ADMIN_PERMISSIONS.contains(&permission.as_str()))
+}
+
+/// Whether `granted` includes every admin permission in `required`.
+pub fn includes_admin_permissions(granted: &[String], required: &[String]) -> bool {
+    required
+        .iter()
+        .filter(|permission| ADMIN_PERMISSIONS.contains(&permission.as_str()))
+        .all(|permission| granted.contains(permission))
+}
+
+/// Whether the user is the only active admin. Locks the active admins until the transaction
+/// ends, so two admins cannot demote each other at the same time.
+pub fn is_last_active_admin(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<bool> {
+    let admins: Vec<Uuid> = users::table
+        .filter(users::role.eq(ADMIN_ROLE))
+        .filter(users::is_active.eq(true))
+        .select(users::id)
+        .for_update()
+        .load(conn)?;
+    Ok(admins == [user_id])
+}
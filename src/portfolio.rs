@@ -0,0 +1,564 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use chrono::{NaiveDate, Utc};
+use serde::Serialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+    models::{
+        CreatePortfolioTransactionRequest, NewPortfolioTransaction, PortfolioTransaction, PriceBar,
+    },
+    stocks::normalize_ticker,
+};
+
+pub const METHOD_FIFO: &str = "fifo";
+pub const METHOD_AVERAGE: &str = "average";
+pub const COST_BASIS_METHODS: [&str; 2] = [METHOD_FIFO, METHOD_AVERAGE];
+
+pub const KIND_BUY: &str = "buy";
+pub const KIND_SELL: &str = "sell";
+pub const KIND_DIVIDEND: &str = "dividend";
+pub const TRANSACTION_KINDS: [&str; 3] = [KIND_BUY, KIND_SELL, KIND_DIVIDEND];
+
+/// Share quantities smaller than this are treated as zero to absorb floating point error.
+const QUANTITY_EPSILON: f64 = 1e-9;
+const DAYS_PER_YEAR: f64 = 365.0;
+/// Range searched for the money-weighted return, from a near-total loss to 10,000% a year.
+const MIN_RATE: f64 = -0.9999;
+const MAX_RATE: f64 = 100.0;
+const IRR_ITERATIONS: usize = 200;
+
+#[derive(Debug, Error)]
+pub enum PortfolioError {
+    #[error("invalid transaction: {0}")]
+    InvalidTransaction(String),
+    #[error("cannot sell {requested} {ticker} on {date}: only {held} held")]
+    InsufficientShares {
+        transaction_id: Uuid,
+        ticker: String,
+        date: NaiveDate,
+        held: f64,
+        requested: f64,
+    },
+}
+
+/// An open or closed holding of one ticker.
+#[derive(Debug, Clone, Serialize)]
+pub struct Position {
+    pub ticker: String,
+    pub quantity: f64,
+    /// Cost basis per share; `None` once the position is closed.
+    pub average_cost: Option<f64>,
+    pub cost_basis: f64,
+    /// Latest stored close, or the last trade price when that is more recent.
+    pub last_price: Option<f64>,
+    pub price_date: Option<NaiveDate>,
+    pub market_value: f64,
+    pub unrealized_pnl: f64,
+    pub unrealized_pnl_percent: Option<f64>,
+    pub realized_pnl: f64,
+    pub dividends: f64,
+}
+
+/// Positions, profit and loss and returns of a portfolio as of its latest price.
+///
+/// Returns are fractions (0.05 is 5%). They are `None` when the history is too short to
+/// measure them, e.g. a single day of trades.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Performance {
+    pub as_of: Option<NaiveDate>,
+    pub positions: Vec<Position>,
+    pub cost_basis: f64,
+    pub market_value: f64,
+    /// Cash put into purchases, including fees, less the proceeds of sales.
+    pub net_invested: f64,
+    pub realized_pnl: f64,
+    pub unrealized_pnl: f64,
+    pub dividends: f64,
+    pub fees: f64,
+    /// Realized and unrealized profit and loss plus dividends.
+    pub total_return: f64,
+    /// Cumulative return with the effect of buys and sells removed.
+    pub time_weighted_return: Option<f64>,
+    /// Annualised internal rate of return of the cash flows and the current market value.
+    pub money_weighted_return: Option<f64>,
+}
+
+/// Daily closes per ticker.
+pub struct PriceHistory {
+    closes: HashMap<String, BTreeMap<NaiveDate, f64>>,
+}
+
+impl PriceHistory {
+    pub fn new(bars: &[PriceBar]) -> Self {
+        let mut closes: HashMap<String, BTreeMap<NaiveDate, f64>> = HashMap::new();
+        for bar in bars {
+            closes
+                .entry(bar.ticker.clone())
+                .or_default()
+                .insert(bar.trade_date, bar.close);
+        }
+        Self { closes }
+    }
+
+    /// The last close on or before `date`.
+    pub fn close_on(&self, ticker: &str, date: NaiveDate) -> Option<(NaiveDate, f64)> {
+        self.closes
+            .get(ticker)?
+            .range(..=date)
+            .next_back()
+            .map(|(date, close)| (*date, *close))
+    }
+
+    /// Simple returns between consecutive stored closes, keyed by the later date.
+    pub fn daily_returns(&self, ticker: &str) -> BTreeMap<NaiveDate, f64> {
+        let Some(closes) = self.closes.get(ticker) else {
+            return BTreeMap::new();
+        };
+        closes
+            .iter()
+            .zip(closes.iter().skip(1))
+            .filter(|((_, previous), _)| **previous > 0.0)
+            .map(|((_, previous), (date, close))| (*date, close / previous - 1.0))
+            .collect()
+    }
+
+    pub fn latest_date(&self) -> Option<NaiveDate> {
+        self.closes
+            .values()
+            .filter_map(|closes| closes.keys().next_back())
+            .max()
+            .copied()
+    }
+}
+
+struct Lot {
+    quantity: f64,
+    cost_per_share: f64,
+}
+
+#[derive(Default)]
+struct Holding {
+    /// Oldest first. Average cost keeps a single lot.
+    lots: VecDeque<Lot>,
+    realized_pnl: f64,
+    dividends: f64,
+    last_trade: Option<(NaiveDate, f64)>,
+}
+
+impl Holding {
+    fn quantity(&self) -> f64 {
+        self.lots.iter().map(|lot| lot.quantity).sum()
+    }
+
+    fn cost_basis(&self) -> f64 {
+        self.lots
+            .iter()
+            .map(|lot| lot.quantity * lot.cost_per_share)
+            .sum()
+    }
+
+    /// The more recent of the stored close and the last trade price.
+    fn price_on(
+        &self,
+        prices: &PriceHistory,
+        ticker: &str,
+        date: NaiveDate,
+    ) -> Option<(NaiveDate, f64)> {
+        let close = prices.close_on(ticker, date);
+        match (close, self.last_trade) {
+            (Some(close), Some(trade)) if trade.0 > close.0 => Some(trade),
+            (Some(close), _) => Some(close),
+            (None, trade) => trade,
+        }
+    }
+}
+
+/// Replays transactions into holdings and the investor's cash flows.
+pub struct Ledger {
+    average_cost: bool,
+    holdings: BTreeMap<String, Holding>,
+    fees: f64,
+    net_invested: f64,
+    /// Negative for money put in, positive for money taken out.
+    cash_flows: Vec<(NaiveDate, f64)>,
+}
+
+impl Ledger {
+    pub fn new(cost_basis_method: &str) -> Self {
+        Self {
+            average_cost: cost_basis_method == METHOD_AVERAGE,
+            holdings: BTreeMap::new(),
+            fees: 0.0,
+            net_invested: 0.0,
+            cash_flows: Vec::new(),
+        }
+    }
+
+    pub fn apply(&mut self, transaction: &PortfolioTransaction) -> Result<(), PortfolioError> {
+        let holding = self.holdings.entry(transaction.ticker.clone()).or_default();
+        let quantity = transaction.quantity.unwrap_or_default();
+        let price = transaction.price.unwrap_or_default();
+        let date = transaction.trade_date;
+
+        match transaction.kind.as_str() {
+            KIND_BUY => {
+                let cost = quantity * price + transaction.fees;
+                if self.average_cost && !holding.lots.is_empty() {
+                    let total = holding.quantity() + quantity;
+                    let cost_per_share = (holding.cost_basis() + cost) / total;
+                    holding.lots = VecDeque::from([Lot {
+                        quantity: total,
+                        cost_per_share,
+                    }]);
+                } else if quantity > QUANTITY_EPSILON {
+                    holding.lots.push_back(Lot {
+                        quantity,
+                        cost_per_share: cost / quantity,
+                    });
+                }
+                holding.last_trade = Some((date, price));
+                self.net_invested += cost;
+                self.cash_flows.push((date, -cost));
+            }
+            KIND_SELL => {
+                let held = holding.quantity();
+                if quantity > held + QUANTITY_EPSILON {
+                    return Err(PortfolioError::InsufficientShares {
+                        transaction_id: transaction.id,
+                        ticker: transaction.ticker.clone(),
+                        date,
+                        held,
+                        requested: quantity,
+                    });
+                }
+
+                let mut remaining = quantity;
+                let mut cost = 0.0;
+                while remaining > QUANTITY_EPSILON {
+                    let Some(lot) = holding.lots.front_mut() else {
+                        break;
+                    };
+                    let taken = lot.quantity.min(remaining);
+                    cost += taken * lot.cost_per_share;
+                    lot.quantity -= taken;
+                    remaining -= taken;
+                    if lot.quantity <= QUANTITY_EPSILON {
+                        holding.lots.pop_front();
+                    }
+                }
+
+                let proceeds = quantity * price - transaction.fees;
+                holding.realized_pnl += proceeds - cost;
+                holding.last_trade = Some((date, price));
+                self.net_invested -= proceeds;
+                self.cash_flows.push((date, proceeds));
+            }
+            KIND_DIVIDEND => {
+                let amount = transaction.amount.unwrap_or_default() - transaction.fees;
+                holding.dividends += amount;
+                self.cash_flows.push((date, amount));
+            }
+            _ => {}
+        }
+
+        self.fees += transaction.fees;
+        Ok(())
+    }
+
+    /// Market value of the holdings at the prices of `date`.
+    fn value_on(&self, prices: &PriceHistory, date: NaiveDate) -> f64 {
+        self.holdings
+            .iter()
+            .filter_map(|(ticker, holding)| {
+                let quantity = holding.quantity();
+                (quantity > QUANTITY_EPSILON)
+                    .then(|| holding.price_on(prices, ticker, date))
+                    .flatten()
+                    .map(|(_, price)| quantity * price)
+            })
+            .sum()
+    }
+}
+
+/// Transactions in the order they are applied: by trade date, and within a day buys
+/// before dividends before sells so same-day round trips are valid.
+fn ordered(transactions: &[PortfolioTransaction]) -> Vec<&PortfolioTransaction> {
+    let rank = |kind: &str| match kind {
+        KIND_BUY => 0,
+        KIND_DIVIDEND => 1,
+        _ => 2,
+    };
+    let mut ordered: Vec<&PortfolioTransaction> = transactions.iter().collect();
+    ordered.sort_by(|a, b| {
+        (a.trade_date, rank(&a.kind), a.created_at).cmp(&(
+            b.trade_date,
+            rank(&b.kind),
+            b.created_at,
+        ))
+    });
+    ordered
+}
+
+/// Check that the history never sells more shares than it holds.
+pub fn replay(
+    cost_basis_method: &str,
+    transactions: &[PortfolioTransaction],
+) -> Result<Ledger, PortfolioError> {
+    let mut ledger = Ledger::new(cost_basis_method);
+    for transaction in ordered(transactions) {
+        ledger.apply(transaction)?;
+    }
+    Ok(ledger)
+}
+
+/// Add candidate transactions to a valid history, rejecting those that would sell shares not
+/// held. A candidate sell is also rejected when it leaves a later existing sell short. Returns
+/// the accepted candidates and the rejected ones with the reason.
+pub fn admit(
+    cost_basis_method: &str,
+    existing: &[PortfolioTransaction],
+    mut candidates: Vec<PortfolioTransaction>,
+) -> (
+    Vec<PortfolioTransaction>,
+    Vec<(PortfolioTransaction, PortfolioError)>,
+) {
+    let mut rejected = Vec::new();
+    loop {
+        let mut history = existing.to_vec();
+        history.extend(candidates.iter().cloned());
+        let Err(error) = replay(cost_basis_method, &history) else {
+            return (candidates, rejected);
+        };
+        let PortfolioError::InsufficientShares {
+            transaction_id,
+            ref ticker,
+            date,
+            ..
+        } = error
+        else {
+            return (candidates, rejected);
+        };
+
+        let culprit = candidates
+            .iter()
+            .position(|candidate| candidate.id == transaction_id)
+            .or_else(|| {
+                // The short sell is an existing one: blame the latest candidate sell before it.
+                candidates
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, candidate)| {
+                        candidate.kind == KIND_SELL
+                            && candidate.ticker == *ticker
+                            && candidate.trade_date <= date
+                    })
+                    .max_by_key(|(_, candidate)| candidate.trade_date)
+                    .map(|(index, _)| index)
+            });
+        match culprit {
+            Some(index) => rejected.push((candidates.remove(index), error)),
+            None => return (candidates, rejected),
+        }
+    }
+}
+
+/// Check a new transaction: a known kind, a trade date that is not in the future, and a
+/// positive quantity and a price for buys and sells or the cash received for dividends.
+pub fn validate_transaction(
+    portfolio_id: Uuid,
+    request: CreatePortfolioTransactionRequest,
+) -> Result<NewPortfolioTransaction, PortfolioError> {
+    let invalid = |message: &str| PortfolioError::InvalidTransaction(message.to_string());
+    let ticker = normalize_ticker(&request.ticker).ok_or_else(|| invalid("invalid ticker"))?;
+    if !TRANSACTION_KINDS.contains(&request.kind.as_str()) {
+        return Err(invalid("kind must be buy, sell or dividend"));
+    }
+    if request.trade_date > Utc::now().date_naive() {
+        return Err(invalid("trade date is in the future"));
+    }
+
+    let fees = request.fees.unwrap_or(0.0);
+    let non_negative = |value: f64| value.is_finite() && value >= 0.0;
+    let positive = |value: f64| value.is_finite() && value > 0.0;
+    if !non_negative(fees) {
+        return Err(invalid("fees must not be negative"));
+    }
+
+    let (quantity, price, amount) = if request.kind == KIND_DIVIDEND {
+        let amount = request
+            .amount
+            .filter(|amount| positive(*amount))
+            .ok_or_else(|| invalid("dividends need a positive amount"))?;
+        (None, None, Some(amount))
+    } else {
+        let quantity = request
+            .quantity
+            .filter(|quantity| positive(*quantity))
+            .ok_or_else(|| invalid("trades need a positive quantity"))?;
+        let price = request
+            .price
+            .filter(|price| non_negative(*price))
+            .ok_or_else(|| invalid("trades need a price"))?;
+        (Some(quantity), Some(price), None)
+    };
+
+    Ok(NewPortfolioTransaction {
+        portfolio_id,
+        ticker,
+        kind: request.kind,
+        trade_date: request.trade_date,
+        quantity,
+        price,
+        amount,
+        fees,
+        notes: request.notes,
+        external_id: request.external_id,
+    })
+}
+
+/// Positions, profit and loss and returns as of the later of the last transaction and the
+/// latest stored close.
+///
+/// The time-weighted return chains the return of every period between trade dates, so it
+/// measures the holdings rather than the timing of deposits. The money-weighted return is
+/// the IRR of the same cash flows, which does reward or punish that timing.
+pub fn evaluate(
+    cost_basis_method: &str,
+    transactions: &[PortfolioTransaction],
+    prices: &PriceHistory,
+) -> Result<Performance, PortfolioError> {
+    let ordered = ordered(transactions);
+    let Some(last_trade_date) = ordered.last().map(|transaction| transaction.trade_date) else {
+        return Ok(Performance::default());
+    };
+    let as_of = prices
+        .latest_date()
+        .map_or(last_trade_date, |date| date.max(last_trade_date));
+
+    let mut ledger = Ledger::new(cost_basis_method);
+    let mut growth = 1.0;
+    let mut measured = false;
+    let mut period_start = 0.0;
+    for day in ordered.chunk_by(|a, b| a.trade_date == b.trade_date) {
+        let date = day[0].trade_date;
+        if period_start > 0.0 {
+            let income: f64 = day
+                .iter()
+                .filter(|transaction| transaction.kind == KIND_DIVIDEND)
+                .map(|transaction| transaction.amount.unwrap_or_default() - transaction.fees)
+                .sum();
+            growth *= (ledger.value_on(prices, date) + income) / period_start;
+            measured = true;
+        }
+        for transaction in day {
+            ledger.apply(transaction)?;
+        }
+        period_start = ledger.value_on(prices, date);
+    }
+
+    let market_value = ledger.value_on(prices, as_of);
+    if period_start > 0.0 && as_of > last_trade_date {
+        growth *= market_value / period_start;
+        measured = true;
+    }
+
+    let positions: Vec<Position> = ledger
+        .holdings
+        .iter()
+        .map(|(ticker, holding)| {
+            let quantity = holding.quantity();
+            let open = quantity > QUANTITY_EPSILON;
+            let cost_basis = if open { holding.cost_basis() } else { 0.0 };
+            let price = holding.price_on(prices, ticker, as_of);
+            let market_value = if open {
+                price.map_or(0.0, |(_, price)| quantity * price)
+            } else {
+                0.0
+            };
+            let unrealized_pnl = if open && price.is_some() {
+                market_value - cost_basis
+            } else {
+                0.0
+            };
+
+            Position {
+                ticker: ticker.clone(),
+                quantity: if open { quantity } else { 0.0 },
+                average_cost: open.then(|| cost_basis / quantity),
+                cost_basis,
+                last_price: price.map(|(_, price)| price),
+                price_date: price.map(|(date, _)| date),
+                market_value,
+                unrealized_pnl,
+                unrealized_pnl_percent: (cost_basis > 0.0)
+                    .then(|| unrealized_pnl / cost_basis * 100.0),
+                realized_pnl: holding.realized_pnl,
+                dividends: holding.dividends,
+            }
+        })
+        .collect();
+
+    let sum = |field: fn(&Position) -> f64| positions.iter().map(field).sum::<f64>();
+    let cost_basis = sum(|position| position.cost_basis);
+    let realized_pnl = sum(|position| position.realized_pnl);
+    let unrealized_pnl = sum(|position| position.unrealized_pnl);
+    let dividends = sum(|position| position.dividends);
+    let mut cash_flows = ledger.cash_flows.clone();
+    cash_flows.push((as_of, market_value));
+
+    Ok(Performance {
+        as_of: Some(as_of),
+        cost_basis,
+        market_value,
+        net_invested: ledger.net_invested,
+        realized_pnl,
+        unrealized_pnl,
+        dividends,
+        fees: ledger.fees,
+        total_return: realized_pnl + unrealized_pnl + dividends,
+        time_weighted_return: measured.then_some(growth - 1.0),
+        money_weighted_return: internal_rate_of_return(&cash_flows),
+        positions,
+    })
+}
+
+/// Annual rate at which the cash flows' net present value is zero, found by bisection.
+/// `None` when the flows never change sign over the searched range.
+fn internal_rate_of_return(cash_flows: &[(NaiveDate, f64)]) -> Option<f64> {
+    let first = cash_flows.iter().map(|(date, _)| *date).min()?;
+    let npv = |rate: f64| {
+        cash_flows
+            .iter()
+            .map(|(date, amount)| {
+                let years = (*date - first).num_days() as f64 / DAYS_PER_YEAR;
+                amount / (1.0 + rate).powf(years)
+            })
+            .sum::<f64>()
+    };
+
+    let (mut low, mut high) = (MIN_RATE, MAX_RATE);
+    let mut npv_low = npv(low);
+    let npv_high = npv(high);
+    if !npv_low.is_finite()
+        || !npv_high.is_finite()
+        || npv_low == 0.0
+        || npv_low.signum() == npv_high.signum()
+    {
+        return None;
+    }
+
+    for _ in 0..IRR_ITERATIONS {
+        let mid = (low + high) / 2.0;
+        let npv_mid = npv(mid);
+        if npv_mid.signum() == npv_low.signum() {
+            low = mid;
+            npv_low = npv_mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    Some((low + high) / 2.0)
+}
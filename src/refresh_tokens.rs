@@ -0,0 +1,102 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::dsl::now;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    auth::{generate_opaque_token, hash_token},
+    environments,
+    models::{NewRefreshToken, RefreshToken, User},
+    schema::{refresh_tokens, users},
+    sessions,
+};
+
+/// Expiry of a refresh token issued now.
+pub fn expiry() -> NaiveDateTime {
+    Utc::now().naive_utc() + Duration::days(environments::get_refresh_token_expiration_days())
+}
+
+/// Issue a refresh token in `family_id`. Only its hash is stored.
+pub fn issue(conn: &mut PgConnection, user_id: Uuid, family_id: Uuid) -> QueryResult<String> {
+    let token = generate_opaque_token();
+
+    diesel::insert_into(refresh_tokens::table)
+        .values(&NewRefreshToken {
+            user_id,
+            family_id,
+            token_hash: hash_token(&token),
+            expires_at: expiry(),
+        })
+        .execute(conn)?;
+
+    Ok(token)
+}
+
+/// Exchange a refresh token for a new one in the same family, returning its active user,
+/// the family and the new token.
+///
+/// Each token works once. Presenting a token that was already exchanged means it leaked,
+/// so the session of the family is revoked and the legitimate holder has to log in again too.
+/// `None` for unknown, revoked, expired or reused tokens and for deactivated users.
+pub fn rotate(conn: &mut PgConnection, token: &str) -> QueryResult<Option<(User, Uuid, String)>> {
+    conn.transaction(|conn| {
+        let stored = refresh_tokens::table
+            .filter(refresh_tokens::token_hash.eq(hash_token(token)))
+            .select(RefreshToken::as_select())
+            .for_update()
+            .first(conn)
+            .optional()?;
+        let Some(stored) = stored else {
+            return Ok(None);
+        };
+        if stored.revoked_at.is_some() || stored.expires_at <= Utc::now().naive_utc() {
+            return Ok(None);
+        }
+        if stored.used_at.is_some() {
+            tracing::warn!(
+                "Refresh token reused for user {}, revoking token family {}",
+                stored.user_id,
+                stored.family_id
+            );
+            sessions::revoke(conn, stored.family_id)?;
+            return Ok(None);
+        }
+
+        let user = users::table
+            .find(stored.user_id)
+            .filter(users::is_active.eq(true))
+            .select(User::as_select())
+            .first(conn)
+            .optional()?;
+        let Some(user) = user else {
+            sessions::revoke(conn, stored.family_id)?;
+            return Ok(None);
+        };
+
+        diesel::update(refresh_tokens::table.find(stored.id))
+            .set(refresh_tokens::used_at.eq(now))
+            .execute(conn)?;
+        let token = issue(conn, user.id, stored.family_id)?;
+
+        Ok(Some((user, stored.family_id, token)))
+    })
+}
+
+/// The family, and so the session, a refresh token belongs to.
+pub fn family_of(conn: &mut PgConnection, token: &str) -> QueryResult<Option<Uuid>> {
+    refresh_tokens::table
+        .filter(refresh_tokens::token_hash.eq(hash_token(token)))
+        .select(refresh_tokens::family_id)
+        .first(conn)
+        .optional()
+}
+
+pub fn revoke_family(conn: &mut PgConnection, family_id: Uuid) -> QueryResult<usize> {
+    diesel::update(
+        refresh_tokens::table
+            .filter(refresh_tokens::family_id.eq(family_id))
+            .filter(refresh_tokens::revoked_at.is_null()),
+    )
+    .set(refresh_tokens::revoked_at.eq(now))
+    .execute(conn)
+}
@@ -0,0 +1,315 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::portfolio::{Position, PriceHistory};
+
+pub const DEFAULT_BENCHMARK: &str = "SPY";
+pub const DEFAULT_LOOKBACK_DAYS: i64 = 365;
+pub const MAX_LOOKBACK_DAYS: i64 = 3650;
+pub const DEFAULT_CONFIDENCE: f64 = 0.95;
+/// Sector reported for tickers without a stock profile.
+pub const UNKNOWN_SECTOR: &str = "Unknown";
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+/// Statistics over fewer daily returns than this are too noisy to report.
+const MIN_OBSERVATIONS: usize = 20;
+
+pub struct RiskSettings {
+    pub benchmark: String,
+    pub confidence: f64,
+    /// Annual, as a fraction.
+    pub risk_free_rate: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HoldingRisk {
+    pub ticker: String,
+    pub sector: String,
+    pub market_value: f64,
+    pub weight: f64,
+    pub volatility: Option<f64>,
+    pub beta: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SectorWeight {
+    pub sector: String,
+    pub market_value: f64,
+    pub weight: f64,
+}
+
+/// Pairwise correlations of daily returns; `values[i][j]` pairs `tickers[i]` and `tickers[j]`.
+#[derive(Debug, Default, Serialize)]
+pub struct CorrelationMatrix {
+    pub tickers: Vec<String>,
+    pub values: Vec<Vec<Option<f64>>>,
+}
+
+/// Risk of the current holdings replayed over past prices.
+///
+/// Ratios and returns are fractions and annualised unless noted. VaR and CVaR are one-day
+/// historical losses, as a fraction of market value and as an amount. Statistics are `None`
+/// when there are too few overlapping daily returns to measure them.
+#[derive(Debug, Serialize)]
+pub struct RiskReport {
+    pub benchmark: String,
+    pub confidence: f64,
+    pub risk_free_rate: f64,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub observations: usize,
+    pub market_value: f64,
+    pub annualized_return: Option<f64>,
+    pub volatility: Option<f64>,
+    pub beta: Option<f64>,
+    pub sharpe_ratio: Option<f64>,
+    pub sortino_ratio: Option<f64>,
+    /// Largest peak-to-trough fall of the holdings' value, as a positive fraction.
+    pub max_drawdown: Option<f64>,
+    pub max_drawdown_peak: Option<NaiveDate>,
+    pub max_drawdown_trough: Option<NaiveDate>,
+    pub value_at_risk: Option<f64>,
+    pub conditional_value_at_risk: Option<f64>,
+    pub value_at_risk_amount: Option<f64>,
+    pub conditional_value_at_risk_amount: Option<f64>,
+    pub holdings: Vec<HoldingRisk>,
+    pub sectors: Vec<SectorWeight>,
+    /// Sum of squared sector weights: 1.0 is a single sector.
+    pub sector_herfindahl_index: Option<f64>,
+    pub correlations: CorrelationMatrix,
+}
+
+/// Weights the open positions by today's market value and replays them over the stored
+/// daily returns, using only days on which every holding has a return.
+pub fn analyze(
+    positions: &[Position],
+    sectors: &HashMap<String, String>,
+    prices: &PriceHistory,
+    settings: RiskSettings,
+) -> RiskReport {
+    let open: Vec<&Position> = positions
+        .iter()
+        .filter(|position| position.market_value > 0.0)
+        .collect();
+    let market_value = open
+        .iter()
+        .fold(0.0, |total, position| total + position.market_value);
+    let holding_returns: Vec<BTreeMap<NaiveDate, f64>> = open
+        .iter()
+        .map(|position| prices.daily_returns(&position.ticker))
+        .collect();
+    let benchmark_returns = prices.daily_returns(&settings.benchmark);
+
+    let portfolio_returns: BTreeMap<NaiveDate, f64> = match holding_returns.split_first() {
+        Some((first, rest)) if market_value > 0.0 => first
+            .keys()
+            .filter(|date| rest.iter().all(|returns| returns.contains_key(date)))
+            .map(|date| {
+                let weighted = open
+                    .iter()
+                    .zip(&holding_returns)
+                    .map(|(position, returns)| position.market_value / market_value * returns[date])
+                    .sum();
+                (*date, weighted)
+            })
+            .collect(),
+        _ => BTreeMap::new(),
+    };
+    let returns: Vec<f64> = portfolio_returns.values().copied().collect();
+    let measured = returns.len() >= MIN_OBSERVATIONS;
+
+    let annualized_return = measured.then(|| mean(&returns) * TRADING_DAYS_PER_YEAR);
+    let volatility = measured.then(|| annualized_volatility(&returns)).flatten();
+    let sharpe_ratio = annualized_return
+        .zip(volatility)
+        .filter(|(_, volatility)| *volatility > 0.0)
+        .map(|(annual, volatility)| (annual - settings.risk_free_rate) / volatility);
+    let sortino_ratio = annualized_return
+        .zip(measured.then(|| downside_deviation(&returns, settings.risk_free_rate)))
+        .filter(|(_, downside)| *downside > 0.0)
+        .map(|(annual, downside)| (annual - settings.risk_free_rate) / downside);
+    let drawdown = measured.then(|| max_drawdown(&portfolio_returns)).flatten();
+    let tail = measured
+        .then(|| historical_var(&returns, settings.confidence))
+        .flatten();
+
+    let holdings: Vec<HoldingRisk> = open
+        .iter()
+        .zip(&holding_returns)
+        .map(|(position, returns)| {
+            let values: Vec<f64> = returns.values().copied().collect();
+            HoldingRisk {
+                ticker: position.ticker.clone(),
+                sector: sector_of(sectors, &position.ticker),
+                market_value: position.market_value,
+                weight: position.market_value / market_value,
+                volatility: (values.len() >= MIN_OBSERVATIONS)
+                    .then(|| annualized_volatility(&values))
+                    .flatten(),
+                beta: beta(returns, &benchmark_returns),
+            }
+        })
+        .collect();
+
+    let mut sector_values: BTreeMap<String, f64> = BTreeMap::new();
+    for holding in &holdings {
+        *sector_values.entry(holding.sector.clone()).or_default() += holding.market_value;
+    }
+    let mut sector_weights: Vec<SectorWeight> = sector_values
+        .into_iter()
+        .map(|(sector, value)| SectorWeight {
+            sector,
+            market_value: value,
+            weight: value / market_value,
+        })
+        .collect();
+    sector_weights.sort_by(|a, b| b.market_value.total_cmp(&a.market_value));
+    let sector_herfindahl_index = (!sector_weights.is_empty()).then(|| {
+        sector_weights
+            .iter()
+            .map(|sector| sector.weight * sector.weight)
+            .sum()
+    });
+
+    let correlations = CorrelationMatrix {
+        tickers: holdings
+            .iter()
+            .map(|holding| holding.ticker.clone())
+            .collect(),
+        values: holding_returns
+            .iter()
+            .map(|row| {
+                holding_returns
+                    .iter()
+                    .map(|column| correlation(row, column))
+                    .collect()
+            })
+            .collect(),
+    };
+
+    RiskReport {
+        benchmark: settings.benchmark,
+        confidence: settings.confidence,
+        risk_free_rate: settings.risk_free_rate,
+        start_date: portfolio_returns.keys().next().copied(),
+        end_date: portfolio_returns.keys().next_back().copied(),
+        observations: returns.len(),
+        market_value,
+        annualized_return,
+        volatility,
+        beta: measured
+            .then(|| beta(&portfolio_returns, &benchmark_returns))
+            .flatten(),
+        sharpe_ratio,
+        sortino_ratio,
+        max_drawdown: drawdown.map(|(drawdown, ..)| drawdown),
+        max_drawdown_peak: drawdown.map(|(_, peak, _)| peak),
+        max_drawdown_trough: drawdown.map(|(.., trough)| trough),
+        value_at_risk: tail.map(|(var, _)| var),
+        conditional_value_at_risk: tail.map(|(_, cvar)| cvar),
+        value_at_risk_amount: tail.map(|(var, _)| var * market_value),
+        conditional_value_at_risk_amount: tail.map(|(_, cvar)| cvar * market_value),
+        holdings,
+        sectors: sector_weights,
+        sector_herfindahl_index,
+        correlations,
+    }
+}
+
+fn sector_of(sectors: &HashMap<String, String>, ticker: &str) -> String {
+    sectors
+        .get(ticker)
+        .cloned()
+        .unwrap_or_else(|| UNKNOWN_SECTOR.to_string())
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Sample covariance; `None` below two observations.
+fn covariance(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.len() < 2 || a.len() != b.len() {
+        return None;
+    }
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let sum: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| (a - mean_a) * (b - mean_b))
+        .sum();
+    Some(sum / (a.len() - 1) as f64)
+}
+
+/// Annualised standard deviation of daily returns.
+fn annualized_volatility(returns: &[f64]) -> Option<f64> {
+    covariance(returns, returns).map(|variance| (variance * TRADING_DAYS_PER_YEAR).sqrt())
+}
+
+/// Annualised root mean square of daily returns below the daily risk-free rate.
+fn downside_deviation(returns: &[f64], risk_free_rate: f64) -> f64 {
+    let target = risk_free_rate / TRADING_DAYS_PER_YEAR;
+    let squares: Vec<f64> = returns
+        .iter()
+        .map(|value| (value - target).min(0.0).powi(2))
+        .collect();
+    (mean(&squares) * TRADING_DAYS_PER_YEAR).sqrt()
+}
+
+/// Returns of both series on the days they share.
+fn aligned(a: &BTreeMap<NaiveDate, f64>, b: &BTreeMap<NaiveDate, f64>) -> (Vec<f64>, Vec<f64>) {
+    a.iter()
+        .filter_map(|(date, a)| b.get(date).map(|b| (*a, *b)))
+        .unzip()
+}
+
+fn beta(returns: &BTreeMap<NaiveDate, f64>, benchmark: &BTreeMap<NaiveDate, f64>) -> Option<f64> {
+    let (returns, benchmark) = aligned(returns, benchmark);
+    if returns.len() < MIN_OBSERVATIONS {
+        return None;
+    }
+    let variance = covariance(&benchmark, &benchmark)?;
+    (variance > 0.0)
+        .then(|| covariance(&returns, &benchmark).map(|covariance| covariance / variance))
+        .flatten()
+}
+
+fn correlation(a: &BTreeMap<NaiveDate, f64>, b: &BTreeMap<NaiveDate, f64>) -> Option<f64> {
+    let (a, b) = aligned(a, b);
+    if a.len() < MIN_OBSERVATIONS {
+        return None;
+    }
+    let deviations = covariance(&a, &a)?.sqrt() * covariance(&b, &b)?.sqrt();
+    (deviations > 0.0)
+        .then(|| covariance(&a, &b).map(|covariance| covariance / deviations))
+        .flatten()
+}
+
+/// Largest fall from a running peak of compounded returns, with the peak and trough dates.
+fn max_drawdown(returns: &BTreeMap<NaiveDate, f64>) -> Option<(f64, NaiveDate, NaiveDate)> {
+    let mut value = 1.0;
+    let (mut peak, mut peak_date) = (1.0, *returns.keys().next()?);
+    let mut worst: Option<(f64, NaiveDate, NaiveDate)> = None;
+    for (date, daily) in returns {
+        value *= 1.0 + daily;
+        if value > peak {
+            (peak, peak_date) = (value, *date);
+        }
+        let drawdown = 1.0 - value / peak;
+        if drawdown > worst.map_or(0.0, |(worst, ..)| worst) {
+            worst = Some((drawdown, peak_date, *date));
+        }
+    }
+    Some(worst.unwrap_or((0.0, peak_date, peak_date)))
+}
+
+/// One-day value at risk and expected shortfall from the empirical return distribution,
+/// as positive fractions of value.
+fn historical_var(returns: &[f64], confidence: f64) -> Option<(f64, f64)> {
+    let mut sorted = returns.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let tail = ((sorted.len() as f64 * (1.0 - confidence)).ceil() as usize).clamp(1, sorted.len());
+    let worst = sorted.get(..tail)?;
+    Some((-worst[tail - 1], -mean(worst)))
+}
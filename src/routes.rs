@@ -1,37 +1,170 @@
+use std::sync::Arc;
+
 use axum::{
     routing::{get, post, put, delete},
+    Extension,
     Router,
     middleware,
 };
 
 use crate::{
     database::DbPool,
-    handlers::{user, llm_provider},
-    middleware::{auth_middleware, admin_middleware},
+    handlers::{user, account, mfa, oidc, api_token, role, llm_provider, agent, analysis, stock, committee, mcp, watchlist, portfolio, portfolio_import, alert, notification, session, jwks},
+    mailer::Mailer,
+    mcp::client::McpClient,
+    middleware::{auth_middleware, admin_middleware, permission_middleware, session_auth_middleware},
+    permissions::{
+        ANALYSES_RUN, MARKET_DATA_WRITE, MCP_MANAGE, MCP_READ, PROVIDERS_READ, PROVIDERS_WRITE,
+        ROLES_MANAGE, ROLES_READ, USAGE_READ, USERS_MANAGE, USERS_READ,
+    },
+    oidc::OidcClient,
 };
 
-pub fn create_routes(pool: DbPool) -> Router {
-    Router::new()
-        // Public routes
+pub fn create_routes(
+    pool: DbPool,
+    mcp_client: McpClient,
+    mailer: Arc<dyn Mailer>,
+    oidc: Option<OidcClient>,
+) -> Router {
+    // Public routes
+    let public_routes = Router::new()
         .route("/api/auth/register", post(user::register_user))
         .route("/api/auth/login", post(user::login_user))
-        
-        // Protected user routes
+        .route("/api/auth/mfa", post(user::verify_mfa_login))
+        .route("/api/auth/oidc/login", get(oidc::oidc_login))
+        .route("/api/auth/oidc/callback", get(oidc::oidc_callback))
+        .route("/api/auth/refresh", post(user::refresh_token))
+        .route("/api/auth/logout", post(user::logout))
+        .route("/api/auth/verify-email", post(account::verify_email))
+        .route("/api/auth/forgot-password", post(account::forgot_password))
+        .route("/api/auth/reset-password", post(account::reset_password))
+        .route("/.well-known/jwks.json", get(jwks::get_jwks));
+
+    let require = |permission: &'static str| {
+        middleware::from_fn_with_state((pool.clone(), permission), permission_middleware)
+    };
+
+    // Account security routes, which API tokens cannot use
+    let account_routes = Router::new()
+        .route("/api/user/mfa", get(mfa::get_mfa_status))
+        .route("/api/user/mfa/totp", post(mfa::enroll_totp))
+        .route("/api/user/mfa/totp/confirm", post(mfa::confirm_totp))
+        .route("/api/user/mfa/totp/disable", post(mfa::disable_totp))
+        .route("/api/user/mfa/recovery-codes", post(mfa::regenerate_recovery_codes))
+        .route("/api/user/identities", get(oidc::list_identities))
+        .route("/api/user/identities/oidc", post(oidc::link_oidc_identity))
+        .route("/api/user/identities/oidc/callback", post(oidc::complete_oidc_link))
+        .route("/api/user/identities/{id}", delete(oidc::unlink_identity))
+        .route("/api/user/sessions", get(session::list_sessions))
+        .route("/api/user/sessions", delete(session::revoke_other_sessions))
+        .route("/api/user/sessions/{id}", delete(session::revoke_session))
+        .route("/api/user/api-tokens", get(api_token::list_api_tokens))
+        .route("/api/user/api-tokens", post(api_token::create_api_token))
+        .route("/api/user/api-tokens/{id}", delete(api_token::revoke_api_token))
+        .route_layer(middleware::from_fn_with_state(pool.clone(), session_auth_middleware));
+
+    // Protected user routes
+    let user_routes = Router::new()
         .route("/api/user/me", get(user::get_current_user))
-        .route_layer(middleware::from_fn(auth_middleware))
-        
+        .route("/api/user/verify-email/resend", post(account::resend_verification_email))
+        .route("/api/user/watchlists", get(watchlist::list_watchlists))
+        .route("/api/user/watchlists", post(watchlist::create_watchlist))
+        .route("/api/user/watchlists/{id}", get(watchlist::get_watchlist))
+        .route("/api/user/watchlists/{id}", put(watchlist::update_watchlist))
+        .route("/api/user/watchlists/{id}", delete(watchlist::delete_watchlist))
+        .route("/api/user/watchlists/{id}/order", put(watchlist::reorder_watchlist))
+        .route("/api/user/watchlists/{id}/items", post(watchlist::add_watchlist_item))
+        .route("/api/user/watchlists/{id}/items/{ticker}", put(watchlist::update_watchlist_item))
+        .route("/api/user/watchlists/{id}/items/{ticker}", delete(watchlist::remove_watchlist_item))
+        .route("/api/agents", get(agent::list_agents))
+        .route("/api/analyses", post(analysis::create_analysis).route_layer(require(ANALYSES_RUN)))
+        .route("/api/analyses", get(analysis::list_analyses))
+        .route("/api/analyses/compare", get(analysis::compare_analyses))
+        .route("/api/analyses/{id}", get(analysis::get_analysis))
+        .route("/api/analyses/{id}/cancel", post(analysis::cancel_analysis))
+        .route("/api/committees", post(committee::create_committee).route_layer(require(ANALYSES_RUN)))
+        .route("/api/committees/{id}", get(committee::get_committee))
+        .route("/api/committees/{id}/cancel", post(committee::cancel_committee))
+        .route("/api/user/alerts", get(alert::list_alert_rules))
+        .route("/api/user/alerts", post(alert::create_alert_rule))
+        .route("/api/user/alerts/triggers", get(alert::list_alert_triggers))
+        .route("/api/user/alerts/{id}", get(alert::get_alert_rule))
+        .route("/api/user/alerts/{id}", put(alert::update_alert_rule))
+        .route("/api/user/alerts/{id}", delete(alert::delete_alert_rule))
+        .route("/api/user/notifications", get(notification::list_notifications))
+        .route("/api/user/notifications/read-all", post(notification::mark_all_notifications_read))
+        .route("/api/user/notifications/settings", get(notification::get_notification_settings))
+        .route("/api/user/notifications/settings", put(notification::update_notification_settings))
+        .route("/api/user/notifications/{id}", put(notification::update_notification))
+        .route("/api/user/notifications/{id}", delete(notification::delete_notification))
+        .route("/api/user/notifications/{id}/deliveries", get(notification::list_notification_deliveries))
+        .route("/api/user/import-mappings", get(portfolio_import::list_import_mappings))
+        .route("/api/user/import-mappings", post(portfolio_import::create_import_mapping))
+        .route("/api/user/import-mappings/{id}", delete(portfolio_import::delete_import_mapping))
+        .route("/api/portfolios", get(portfolio::list_portfolios))
+        .route("/api/portfolios", post(portfolio::create_portfolio))
+        .route("/api/portfolios/{id}", get(portfolio::get_portfolio))
+        .route("/api/portfolios/{id}", put(portfolio::update_portfolio))
+        .route("/api/portfolios/{id}", delete(portfolio::delete_portfolio))
+        .route("/api/portfolios/{id}/risk", get(portfolio::get_portfolio_risk))
+        .route("/api/portfolios/{id}/import", post(portfolio_import::import_transactions))
+        .route("/api/portfolios/{id}/import/preview", post(portfolio_import::preview_import))
+        .route("/api/portfolios/{id}/transactions", get(portfolio::list_transactions))
+        .route("/api/portfolios/{id}/transactions", post(portfolio::create_transaction))
+        .route("/api/portfolios/{id}/transactions/{transaction_id}", delete(portfolio::delete_transaction))
+        .route("/api/mcp", post(mcp::handle_mcp_request))
+        .route_layer(middleware::from_fn_with_state(pool.clone(), auth_middleware));
+
+    let admin_routes = Router::new()
         // Admin-only routes for user management
-        .route("/api/admin/users", get(user::list_users))
-        .route_layer(middleware::from_fn(admin_middleware))
-        
+        .route("/api/admin/users", get(user::list_users).route_layer(require(USERS_READ)))
+        .route("/api/admin/users/{id}", get(user::get_user).route_layer(require(USERS_READ)))
+        .route("/api/admin/users/{id}", delete(user::delete_user).route_layer(require(USERS_MANAGE)))
+        .route("/api/admin/users/{id}/role", put(user::update_user_role).route_layer(require(USERS_MANAGE)))
+        .route("/api/admin/users/{id}/activate", post(user::activate_user).route_layer(require(USERS_MANAGE)))
+        .route("/api/admin/users/{id}/deactivate", post(user::deactivate_user).route_layer(require(USERS_MANAGE)))
+        .route("/api/admin/users/{id}/password-reset", post(user::force_password_reset).route_layer(require(USERS_MANAGE)))
+        .route("/api/admin/users/{id}/sessions", get(session::list_user_sessions).route_layer(require(USERS_READ)))
+        .route("/api/admin/users/{id}/sessions", delete(session::revoke_user_sessions).route_layer(require(USERS_MANAGE)))
+        .route("/api/admin/users/{id}/sessions/{session_id}", delete(session::revoke_user_session).route_layer(require(USERS_MANAGE)))
+        .route("/api/admin/users/{id}/mfa", delete(mfa::reset_user_mfa).route_layer(require(USERS_MANAGE)))
+        .route("/api/admin/users/{id}/api-tokens", get(api_token::list_user_api_tokens).route_layer(require(USERS_READ)))
+        .route("/api/admin/users/{id}/api-tokens/{token_id}", delete(api_token::revoke_user_api_token).route_layer(require(USERS_MANAGE)))
+
+        // Admin-only routes for roles and permissions
+        .route("/api/admin/roles", get(role::list_roles).route_layer(require(ROLES_READ)))
+        .route("/api/admin/roles/{name}", put(role::update_role).route_layer(require(ROLES_MANAGE)))
+        .route("/api/admin/roles/{name}", delete(role::delete_role).route_layer(require(ROLES_MANAGE)))
+        .route("/api/admin/permissions", get(role::list_permissions).route_layer(require(ROLES_READ)))
+
         // Admin-only routes for LLM provider management
-        .route("/api/admin/llm-providers", post(llm_provider::create_llm_provider))
-        .route("/api/admin/llm-providers", get(llm_provider::list_llm_providers))
-        .route("/api/admin/llm-providers/:id", get(llm_provider::get_llm_provider))
-        .route("/api/admin/llm-providers/:id", put(llm_provider::update_llm_provider))
-        .route("/api/admin/llm-providers/:id", delete(llm_provider::delete_llm_provider))
-        .route("/api/admin/llm-usage-stats", get(llm_provider::get_llm_usage_stats))
-        .route_layer(middleware::from_fn(admin_middleware))
-        
+        .route("/api/admin/llm-providers", post(llm_provider::create_llm_provider).route_layer(require(PROVIDERS_WRITE)))
+        .route("/api/admin/llm-providers", get(llm_provider::list_llm_providers).route_layer(require(PROVIDERS_READ)))
+        .route("/api/admin/llm-providers/{id}", get(llm_provider::get_llm_provider).route_layer(require(PROVIDERS_READ)))
+        .route("/api/admin/llm-providers/{id}", put(llm_provider::update_llm_provider).route_layer(require(PROVIDERS_WRITE)))
+        .route("/api/admin/llm-providers/{id}", delete(llm_provider::delete_llm_provider).route_layer(require(PROVIDERS_WRITE)))
+        .route("/api/admin/llm-usage-stats", get(llm_provider::get_llm_usage_stats).route_layer(require(USAGE_READ)))
+
+        // Admin-only routes for market data ingestion
+        .route("/api/admin/stocks/{ticker}/prices", post(stock::upsert_price_bars).route_layer(require(MARKET_DATA_WRITE)))
+        .route("/api/admin/stocks/{ticker}/fundamentals", post(stock::upsert_fundamentals).route_layer(require(MARKET_DATA_WRITE)))
+        .route("/api/admin/stocks/{ticker}/profile", put(stock::upsert_stock_profile).route_layer(require(MARKET_DATA_WRITE)))
+
+        // Admin-only routes for the MCP server
+        .route("/api/admin/mcp/status", get(mcp::get_mcp_status).route_layer(require(MCP_READ)))
+        .route("/api/admin/mcp/tool-access", get(mcp::list_tool_access).route_layer(require(MCP_READ)))
+        .route("/api/admin/mcp/tool-access/{tool}", put(mcp::update_tool_access).route_layer(require(MCP_MANAGE)))
+        .route("/api/admin/mcp/tool-access/{tool}", delete(mcp::delete_tool_access).route_layer(require(MCP_MANAGE)))
+        .route("/api/admin/mcp/usage-stats", get(mcp::get_mcp_usage_stats).route_layer(require(USAGE_READ)))
+        .route_layer(middleware::from_fn_with_state(pool.clone(), admin_middleware));
+
+    Router::new()
+        .merge(public_routes)
+        .merge(account_routes)
+        .merge(user_routes)
+        .merge(admin_routes)
+        .layer(Extension(mcp_client))
+        .layer(Extension(mailer))
+        .layer(Extension(oidc))
         .with_state(pool)
 }
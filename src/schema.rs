@@ -1,5 +1,178 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    account_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        purpose -> Varchar,
+        email -> Varchar,
+        token_hash -> Varchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    agents (id) {
+        id -> Uuid,
+        slug -> Varchar,
+        name -> Varchar,
+        description -> Nullable<Text>,
+        system_prompt -> Text,
+        prompt_template -> Text,
+        is_active -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        output_schema -> Jsonb,
+        kind -> Varchar,
+    }
+}
+
+diesel::table! {
+    alert_rules (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        ticker -> Varchar,
+        condition -> Varchar,
+        threshold -> Nullable<Float8>,
+        agent_id -> Nullable<Uuid>,
+        cooldown_minutes -> Int4,
+        is_active -> Bool,
+        last_triggered_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    alert_triggers (id) {
+        id -> Uuid,
+        rule_id -> Uuid,
+        user_id -> Uuid,
+        ticker -> Varchar,
+        trade_date -> Date,
+        value -> Float8,
+        threshold -> Float8,
+        analysis_id -> Nullable<Uuid>,
+        message -> Text,
+        triggered_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    analyses (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        agent_id -> Uuid,
+        provider_id -> Uuid,
+        llm_usage_id -> Nullable<Uuid>,
+        ticker -> Varchar,
+        input_data -> Jsonb,
+        prompt -> Text,
+        result_text -> Text,
+        created_at -> Timestamp,
+        structured_output -> Nullable<Jsonb>,
+        verdict -> Nullable<Varchar>,
+        tool_trace -> Nullable<Jsonb>,
+    }
+}
+
+diesel::table! {
+    analysis_jobs (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        agent_id -> Uuid,
+        provider_id -> Uuid,
+        ticker -> Varchar,
+        status -> Varchar,
+        attempts -> Int4,
+        max_attempts -> Int4,
+        run_after -> Timestamp,
+        last_error -> Nullable<Text>,
+        started_at -> Nullable<Timestamp>,
+        finished_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        kind -> Varchar,
+        use_tools -> Bool,
+    }
+}
+
+diesel::table! {
+    api_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Varchar,
+        token_prefix -> Varchar,
+        token_hash -> Varchar,
+        scopes -> Array<Text>,
+        expires_at -> Timestamp,
+        last_used_at -> Nullable<Timestamp>,
+        last_used_ip -> Nullable<Varchar>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    committee_members (id) {
+        id -> Uuid,
+        committee_id -> Uuid,
+        agent_id -> Uuid,
+        weight -> Float8,
+        analysis_id -> Nullable<Uuid>,
+        error -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    committees (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        provider_id -> Uuid,
+        ticker -> Varchar,
+        consensus_verdict -> Nullable<Varchar>,
+        consensus_score -> Nullable<Float8>,
+        moderator_output -> Nullable<Jsonb>,
+        moderator_text -> Nullable<Text>,
+        llm_usage_id -> Nullable<Uuid>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    fundamentals (id) {
+        id -> Uuid,
+        ticker -> Varchar,
+        period_end -> Date,
+        period_type -> Varchar,
+        revenue -> Nullable<Float8>,
+        net_income -> Nullable<Float8>,
+        eps -> Nullable<Float8>,
+        free_cash_flow -> Nullable<Float8>,
+        book_value_per_share -> Nullable<Float8>,
+        total_assets -> Nullable<Float8>,
+        total_liabilities -> Nullable<Float8>,
+        current_assets -> Nullable<Float8>,
+        total_debt -> Nullable<Float8>,
+        shares_outstanding -> Nullable<Float8>,
+        dividends_per_share -> Nullable<Float8>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    import_mappings (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Varchar,
+        mapping -> Jsonb,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     llm_providers (id) {
         id -> Uuid,
@@ -26,6 +199,211 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    mcp_tool_access (tool_name) {
+        tool_name -> Varchar,
+        allowed_roles -> Array<Text>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    mcp_usage (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        method -> Varchar,
+        target -> Nullable<Varchar>,
+        is_error -> Bool,
+        duration_ms -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    mfa_recovery_codes (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        code_hash -> Varchar,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    notification_outbox (id) {
+        id -> Uuid,
+        notification_id -> Uuid,
+        channel -> Varchar,
+        destination -> Varchar,
+        status -> Varchar,
+        attempts -> Int4,
+        max_attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_error -> Nullable<Text>,
+        sent_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    notification_settings (user_id) {
+        user_id -> Uuid,
+        email_enabled -> Bool,
+        webhook_url -> Nullable<Varchar>,
+        webhook_secret -> Nullable<Varchar>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    notifications (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        kind -> Varchar,
+        title -> Varchar,
+        body -> Text,
+        data -> Jsonb,
+        read_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    oidc_login_states (id) {
+        id -> Uuid,
+        state_hash -> Varchar,
+        code_verifier -> Varchar,
+        nonce -> Varchar,
+        link_user_id -> Nullable<Uuid>,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    permissions (name) {
+        name -> Varchar,
+        description -> Text,
+    }
+}
+
+diesel::table! {
+    portfolio_transactions (id) {
+        id -> Uuid,
+        portfolio_id -> Uuid,
+        ticker -> Varchar,
+        kind -> Varchar,
+        trade_date -> Date,
+        quantity -> Nullable<Float8>,
+        price -> Nullable<Float8>,
+        amount -> Nullable<Float8>,
+        fees -> Float8,
+        notes -> Nullable<Text>,
+        created_at -> Timestamp,
+        external_id -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    portfolios (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Varchar,
+        description -> Nullable<Text>,
+        cost_basis_method -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    price_bars (id) {
+        id -> Uuid,
+        ticker -> Varchar,
+        trade_date -> Date,
+        open -> Float8,
+        high -> Float8,
+        low -> Float8,
+        close -> Float8,
+        volume -> Int8,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        family_id -> Uuid,
+        token_hash -> Varchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    role_permissions (role, permission) {
+        role -> Varchar,
+        permission -> Varchar,
+    }
+}
+
+diesel::table! {
+    roles (name) {
+        name -> Varchar,
+        description -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        user_agent -> Nullable<Text>,
+        ip_address -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        last_seen_at -> Timestamp,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    stock_profiles (ticker) {
+        ticker -> Varchar,
+        name -> Nullable<Varchar>,
+        sector -> Nullable<Varchar>,
+        industry -> Nullable<Varchar>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    totp_credentials (user_id) {
+        user_id -> Uuid,
+        secret -> Varchar,
+        confirmed_at -> Nullable<Timestamp>,
+        last_used_step -> Nullable<Int8>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    user_identities (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        issuer -> Varchar,
+        subject -> Varchar,
+        email -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        last_login_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Uuid,
@@ -36,10 +414,109 @@ diesel::table! {
         is_active -> Bool,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        email_verified_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    watchlist_items (id) {
+        id -> Uuid,
+        watchlist_id -> Uuid,
+        ticker -> Varchar,
+        notes -> Nullable<Text>,
+        position -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    watchlists (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Varchar,
+        description -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
+diesel::joinable!(account_tokens -> users (user_id));
+diesel::joinable!(alert_rules -> agents (agent_id));
+diesel::joinable!(alert_rules -> users (user_id));
+diesel::joinable!(alert_triggers -> alert_rules (rule_id));
+diesel::joinable!(alert_triggers -> analyses (analysis_id));
+diesel::joinable!(alert_triggers -> users (user_id));
+diesel::joinable!(analyses -> agents (agent_id));
+diesel::joinable!(analyses -> llm_providers (provider_id));
+diesel::joinable!(analyses -> llm_usage (llm_usage_id));
+diesel::joinable!(analyses -> users (user_id));
+diesel::joinable!(analysis_jobs -> agents (agent_id));
+diesel::joinable!(analysis_jobs -> llm_providers (provider_id));
+diesel::joinable!(analysis_jobs -> users (user_id));
+diesel::joinable!(api_tokens -> users (user_id));
+diesel::joinable!(committee_members -> agents (agent_id));
+diesel::joinable!(committee_members -> analyses (analysis_id));
+diesel::joinable!(committee_members -> committees (committee_id));
+diesel::joinable!(committees -> analysis_jobs (id));
+diesel::joinable!(committees -> llm_providers (provider_id));
+diesel::joinable!(committees -> llm_usage (llm_usage_id));
+diesel::joinable!(committees -> users (user_id));
+diesel::joinable!(import_mappings -> users (user_id));
 diesel::joinable!(llm_usage -> llm_providers (provider_id));
 diesel::joinable!(llm_usage -> users (user_id));
+diesel::joinable!(mcp_usage -> users (user_id));
+diesel::joinable!(mfa_recovery_codes -> users (user_id));
+diesel::joinable!(notification_outbox -> notifications (notification_id));
+diesel::joinable!(notification_settings -> users (user_id));
+diesel::joinable!(notifications -> users (user_id));
+diesel::joinable!(oidc_login_states -> users (link_user_id));
+diesel::joinable!(portfolio_transactions -> portfolios (portfolio_id));
+diesel::joinable!(portfolios -> users (user_id));
+diesel::joinable!(refresh_tokens -> sessions (family_id));
+diesel::joinable!(refresh_tokens -> users (user_id));
+diesel::joinable!(role_permissions -> permissions (permission));
+diesel::joinable!(role_permissions -> roles (role));
+diesel::joinable!(sessions -> users (user_id));
+diesel::joinable!(totp_credentials -> users (user_id));
+diesel::joinable!(user_identities -> users (user_id));
+diesel::joinable!(users -> roles (role));
+diesel::joinable!(watchlist_items -> watchlists (watchlist_id));
+diesel::joinable!(watchlists -> users (user_id));
 
-diesel::allow_tables_to_appear_in_same_query!(llm_providers, llm_usage, users,);
+diesel::allow_tables_to_appear_in_same_query!(
+    account_tokens,
+    agents,
+    alert_rules,
+    alert_triggers,
+    analyses,
+    analysis_jobs,
+    api_tokens,
+    committee_members,
+    committees,
+    fundamentals,
+    import_mappings,
+    llm_providers,
+    llm_usage,
+    mcp_tool_access,
+    mcp_usage,
+    mfa_recovery_codes,
+    notification_outbox,
+    notification_settings,
+    notifications,
+    oidc_login_states,
+    permissions,
+    portfolio_transactions,
+    portfolios,
+    price_bars,
+    refresh_tokens,
+    role_permissions,
+    roles,
+    sessions,
+    stock_profiles,
+    totp_credentials,
+    user_identities,
+    users,
+    watchlist_items,
+    watchlists,
+);
@@ -0,0 +1,184 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use axum::http::HeaderMap;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    environments,
+    models::{NewSession, User},
+    refresh_tokens,
+    schema::{sessions, users},
+    ttl_cache::TtlCache,
+};
+
+/// How long the outcome of a session check is reused. Revoking a session through this server
+/// forgets its check at once; a user deactivated directly in the database keeps their
+/// sessions until it has passed.
+const CHECK_CACHE_TTL: Duration = Duration::from_secs(30);
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// Owner of each checked session while it is live, `None` once it is revoked or expired.
+static CHECKS: LazyLock<TtlCache<Uuid, Option<Uuid>>> =
+    LazyLock::new(|| TtlCache::new(CHECK_CACHE_TTL));
+
+/// Where a login or refresh came from, as recorded on its session.
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl ClientInfo {
+    /// The client's User-Agent and address. Behind a trusted proxy the last `X-Forwarded-For`
+    /// entry, the one the proxy added, is preferred over the peer address, which is the proxy's.
+    pub fn new(headers: &HeaderMap, peer: SocketAddr) -> Self {
+        let user_agent = headers
+            .get("User-Agent")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LEN).collect());
+        let forwarded_for = headers
+            .get("X-Forwarded-For")
+            .filter(|_| environments::is_behind_trusted_proxy())
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|value| value.trim().parse::<IpAddr>().ok());
+        let ip_address = forwarded_for.unwrap_or(peer.ip()).to_string();
+
+        Self {
+            user_agent,
+            ip_address: Some(ip_address),
+        }
+    }
+}
+
+/// Start a session for a login and issue its first refresh token.
+pub fn start(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    client: ClientInfo,
+) -> QueryResult<(Uuid, String)> {
+    conn.transaction(|conn| {
+        let session_id = diesel::insert_into(sessions::table)
+            .values(&NewSession {
+                user_id,
+                user_agent: client.user_agent,
+                ip_address: client.ip_address,
+                expires_at: refresh_tokens::expiry(),
+            })
+            .returning(sessions::id)
+            .get_result(conn)?;
+        let refresh_token = refresh_tokens::issue(conn, user_id, session_id)?;
+
+        Ok((session_id, refresh_token))
+    })
+}
+
+/// Rotate a refresh token and extend its session, returning the user, the session and the
+/// new refresh token. `None` when the refresh token is rejected; see `refresh_tokens::rotate`.
+pub fn refresh(
+    conn: &mut PgConnection,
+    refresh_token: &str,
+    client: ClientInfo,
+) -> QueryResult<Option<(User, Uuid, String)>> {
+    conn.transaction(|conn| {
+        let Some((user, session_id, refresh_token)) = refresh_tokens::rotate(conn, refresh_token)?
+        else {
+            return Ok(None);
+        };
+
+        diesel::update(sessions::table.find(session_id))
+            .set((
+                sessions::user_agent.eq(client.user_agent),
+                sessions::ip_address.eq(client.ip_address),
+                sessions::last_seen_at.eq(now),
+                sessions::expires_at.eq(refresh_tokens::expiry()),
+            ))
+            .execute(conn)?;
+
+        Ok(Some((user, session_id, refresh_token)))
+    })
+}
+
+/// The owner of a session while it is live, if checked within the cache TTL.
+pub fn cached(session_id: Uuid) -> Option<Option<Uuid>> {
+    CHECKS.get(&session_id)
+}
+
+/// The owner of a session if it is live: not revoked, not expired and its user is active.
+/// Live sessions are marked as seen; the outcome is cached for `CHECK_CACHE_TTL`.
+pub fn check(conn: &mut PgConnection, session_id: Uuid) -> QueryResult<Option<Uuid>> {
+    let user_id = sessions::table
+        .inner_join(users::table)
+        .filter(sessions::id.eq(session_id))
+        .filter(sessions::revoked_at.is_null())
+        .filter(sessions::expires_at.gt(now))
+        .filter(users::is_active.eq(true))
+        .select(sessions::user_id)
+        .first::<Uuid>(conn)
+        .optional()?;
+
+    if user_id.is_some() {
+        diesel::update(sessions::table.find(session_id))
+            .set(sessions::last_seen_at.eq(now))
+            .execute(conn)?;
+    }
+
+    CHECKS.insert(session_id, user_id);
+    Ok(user_id)
+}
+
+/// End a session: its refresh tokens stop working and so do its access tokens.
+pub fn revoke(conn: &mut PgConnection, session_id: Uuid) -> QueryResult<()> {
+    conn.transaction(|conn| {
+        diesel::update(
+            sessions::table
+                .filter(sessions::id.eq(session_id))
+                .filter(sessions::revoked_at.is_null()),
+        )
+        .set(sessions::revoked_at.eq(now))
+        .execute(conn)?;
+        refresh_tokens::revoke_family(conn, session_id)
+    })?;
+
+    forget(&[session_id]);
+    Ok(())
+}
+
+/// End every live session of a user except `keep`, returning the ended sessions. Their checks
+/// stay cached until they are passed to `forget`, which callers do once their transaction has
+/// committed; forgetting them earlier would let a request in between cache them as live again.
+pub fn revoke_all(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    keep: Option<Uuid>,
+) -> QueryResult<Vec<Uuid>> {
+    conn.transaction(|conn| {
+        let mut statement = diesel::update(
+            sessions::table
+                .filter(sessions::user_id.eq(user_id))
+                .filter(sessions::revoked_at.is_null()),
+        )
+        .into_boxed();
+        if let Some(keep) = keep {
+            statement = statement.filter(sessions::id.ne(keep));
+        }
+        let revoked = statement
+            .set(sessions::revoked_at.eq(now))
+            .returning(sessions::id)
+            .get_results(conn)?;
+        for session_id in &revoked {
+            refresh_tokens::revoke_family(conn, *session_id)?;
+        }
+        Ok(revoked)
+    })
+}
+
+/// Drop the cached checks of revoked sessions, so their access tokens stop working at once.
+pub fn forget(session_ids: &[Uuid]) {
+    for session_id in session_ids {
+        CHECKS.remove(session_id);
+    }
+}
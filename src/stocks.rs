@@ -0,0 +1,51 @@
+use diesel::prelude::*;
+
+use crate::{
+    models::{Fundamental, PriceBar},
+    schema::{fundamentals, price_bars},
+};
+
+/// Longest ticker accepted, long enough for exchange suffixes such as `BRK.B` or `7203.T`.
+const MAX_TICKER_LEN: usize = 12;
+
+/// Trim and upper-case a user supplied ticker, rejecting anything that is not a plausible symbol.
+pub fn normalize_ticker(ticker: &str) -> Option<String> {
+    let ticker = ticker.trim().to_ascii_uppercase();
+    let is_valid = !ticker.is_empty()
+        && ticker.len() <= MAX_TICKER_LEN
+        && ticker
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+
+    is_valid.then_some(ticker)
+}
+
+/// Load up to `limit` daily bars for `ticker`, newest first.
+pub fn load_price_history(
+    conn: &mut PgConnection,
+    ticker: &str,
+    limit: i64,
+) -> QueryResult<Vec<PriceBar>> {
+    price_bars::table
+        .filter(price_bars::ticker.eq(ticker))
+        .order(price_bars::trade_date.desc())
+        .limit(limit)
+        .select(PriceBar::as_select())
+        .load(conn)
+}
+
+/// Load up to `limit` reporting periods of the given type for `ticker`, newest first.
+pub fn load_fundamentals(
+    conn: &mut PgConnection,
+    ticker: &str,
+    period_type: &str,
+    limit: i64,
+) -> QueryResult<Vec<Fundamental>> {
+    fundamentals::table
+        .filter(fundamentals::ticker.eq(ticker))
+        .filter(fundamentals::period_type.eq(period_type))
+        .order(fundamentals::period_end.desc())
+        .limit(limit)
+        .select(Fundamental::as_select())
+        .load(conn)
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Name under which the analysis schema is sent to providers (also the forced tool name).
+pub const ANALYSIS_SCHEMA_NAME: &str = "stock_analysis";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Buy,
+    Hold,
+    Sell,
+}
+
+impl Verdict {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Buy => "buy",
+            Self::Hold => "hold",
+            Self::Sell => "sell",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CitedDataPoint {
+    pub metric: String,
+    pub value: f64,
+    pub period: Option<String>,
+}
+
+/// The fields every agent's output schema must provide.
+///
+/// Agents may ask for more in their own schema; extra fields are validated against the
+/// schema and kept in the stored JSON but are not part of this struct.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StructuredAnalysis {
+    pub verdict: Verdict,
+    pub conviction: u8,
+    pub intrinsic_value_low: f64,
+    pub intrinsic_value_high: f64,
+    pub margin_of_safety: f64,
+    pub summary: String,
+    pub key_risks: Vec<String>,
+    pub moats: Vec<String>,
+    pub cited_data_points: Vec<CitedDataPoint>,
+}
+
+impl StructuredAnalysis {
+    fn check(&self) -> Result<(), String> {
+        if !(1..=10).contains(&self.conviction) {
+            return Err(format!(
+                "conviction must be between 1 and 10, got {}",
+                self.conviction
+            ));
+        }
+        if self.intrinsic_value_low > self.intrinsic_value_high {
+            return Err("intrinsic_value_low must not exceed intrinsic_value_high".to_string());
+        }
+        if self.summary.trim().is_empty() {
+            return Err("summary must not be empty".to_string());
+        }
+        if self.key_risks.is_empty() {
+            return Err("key_risks must list at least one risk".to_string());
+        }
+        if self.cited_data_points.is_empty() {
+            return Err("cited_data_points must cite at least one figure".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Parse a model response, validate it against the agent's schema and the shared
+/// analysis fields, and return both the full JSON document and the typed struct.
+///
+/// The error string is written for the model, so it can be sent back on a retry.
+pub fn parse_analysis(text: &str, schema: &Value) -> Result<(Value, StructuredAnalysis), String> {
+    let json = extract_json(text).ok_or("response did not contain a JSON object")?;
+    let document: Value =
+        serde_json::from_str(json).map_err(|e| format!("response is not valid JSON: {}", e))?;
+
+    validate(&document, schema, "$")?;
+
+    let analysis: StructuredAnalysis = serde_json::from_value(document.clone())
+        .map_err(|e| format!("response does not match the analysis structure: {}", e))?;
+    analysis.check()?;
+
+    Ok((document, analysis))
+}
+
+/// Take the outermost `{...}` of a response, tolerating markdown fences and preambles.
+fn extract_json(text: &str) -> Option<&str> {
+    let start = text.find('{')?;
+    let end = text.rfind('}')?;
+    (start < end).then(|| &text[start..=end])
+}
+
+/// Validate `value` against the subset of JSON Schema used by agent output schemas:
+/// `type`, `enum`, `properties`, `required`, `items`, `minimum`, `maximum` and `minItems`.
+pub fn validate(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        let matches = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "number" => value.is_number(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => true,
+        };
+        if !matches {
+            return Err(format!("{} must be of type {}", path, expected));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return Err(format!(
+                "{} must be one of {}",
+                path,
+                Value::from(allowed.clone())
+            ));
+        }
+    }
+
+    if let Some(number) = value.as_f64() {
+        if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+            if number < minimum {
+                return Err(format!("{} must be at least {}", path, minimum));
+            }
+        }
+        if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+            if number > maximum {
+                return Err(format!("{} must be at most {}", path, maximum));
+            }
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for field in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(field) {
+                    return Err(format!("{}.{} is required", path, field));
+                }
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (field, field_schema) in properties {
+                if let Some(field_value) = object.get(field) {
+                    validate(field_value, field_schema, &format!("{}.{}", path, field))?;
+                }
+            }
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        if let Some(min_items) = schema.get("minItems").and_then(Value::as_u64) {
+            if (items.len() as u64) < min_items {
+                return Err(format!(
+                    "{} must contain at least {} items",
+                    path, min_items
+                ));
+            }
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (index, item) in items.iter().enumerate() {
+                validate(item, item_schema, &format!("{}[{}]", path, index))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The moderator's summary of a committee, matching the moderator agent's output schema.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModeratorOutput {
+    pub summary: String,
+    pub agreements: Vec<String>,
+    pub disagreements: Vec<String>,
+    pub recommended_verdict: Verdict,
+    pub rationale: String,
+}
+
+/// Parse and validate a moderator response; see `parse_analysis`.
+pub fn parse_moderation(text: &str, schema: &Value) -> Result<(Value, ModeratorOutput), String> {
+    let json = extract_json(text).ok_or("response did not contain a JSON object")?;
+    let document: Value =
+        serde_json::from_str(json).map_err(|e| format!("response is not valid JSON: {}", e))?;
+
+    validate(&document, schema, "$")?;
+
+    let moderation: ModeratorOutput = serde_json::from_value(document.clone())
+        .map_err(|e| format!("response does not match the moderator structure: {}", e))?;
+
+    Ok((document, moderation))
+}
@@ -0,0 +1,169 @@
+use std::time::Instant;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    analysis::AnalysisError,
+    llm::{ChatMessage, ChatRequest, LlmClient, ToolCall, ToolDefinition},
+    mcp::{client::McpClient, protocol::Tool},
+    models::Agent,
+};
+
+/// Model turns allowed before the agent has to answer with what it has.
+const MAX_TOOL_STEPS: usize = 8;
+/// Tokens the research phase may spend before the agent has to answer.
+const TOOL_TOKEN_BUDGET: i32 = 100_000;
+/// Tool output beyond this many characters is cut before it is shown to the model.
+const MAX_TOOL_RESULT_CHARS: usize = 20_000;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    FinalAnswer,
+    MaxSteps,
+    MaxTokens,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToolCallRecord {
+    pub step: usize,
+    pub tool: String,
+    pub arguments: Value,
+    pub is_error: bool,
+    /// Structured tool output when the server provides it, otherwise its text.
+    pub output: Value,
+    pub duration_ms: u128,
+}
+
+/// Everything the agent did before giving its answer, stored with the analysis.
+#[derive(Debug, Serialize)]
+pub struct ToolTrace {
+    pub steps: usize,
+    pub stop_reason: StopReason,
+    pub tokens_used: i32,
+    pub calls: Vec<ToolCallRecord>,
+}
+
+/// Conversation after the agent stopped calling tools.
+pub struct Research {
+    pub messages: Vec<ChatMessage>,
+    /// The agent's last message when it stopped on its own rather than hitting a limit.
+    pub final_text: Option<String>,
+    pub trace: ToolTrace,
+}
+
+/// Describe MCP tools in the provider-neutral form the LLM client translates per provider.
+pub fn tool_definitions(tools: &[Tool]) -> Vec<ToolDefinition> {
+    tools
+        .iter()
+        .map(|tool| ToolDefinition {
+            name: tool.name.clone(),
+            description: tool.description.clone(),
+            parameters: tool.input_schema.clone(),
+        })
+        .collect()
+}
+
+/// Let the agent call tools until it answers or runs out of steps or tokens.
+///
+/// On failure the tokens already spent are returned alongside the error.
+pub async fn research(
+    client: &LlmClient,
+    mcp: &McpClient,
+    agent: &Agent,
+    tools: &[ToolDefinition],
+    prompt: &str,
+) -> Result<Research, (AnalysisError, i32)> {
+    let mut messages = vec![ChatMessage::user(prompt)];
+    let mut calls = Vec::new();
+    let mut tokens_used = 0;
+    let mut steps = 0;
+
+    let (stop_reason, final_text) = loop {
+        if steps >= MAX_TOOL_STEPS {
+            break (StopReason::MaxSteps, None);
+        }
+        if tokens_used >= TOOL_TOKEN_BUDGET {
+            break (StopReason::MaxTokens, None);
+        }
+
+        let completion = client
+            .chat(&ChatRequest {
+                system: &agent.system_prompt,
+                messages: &messages,
+                tools,
+                response_schema: None,
+            })
+            .await
+            .map_err(|e| (e.into(), tokens_used))?;
+        tokens_used += completion.tokens_used;
+        steps += 1;
+
+        if completion.tool_calls.is_empty() {
+            messages.push(ChatMessage::assistant(completion.text.clone()));
+            break (StopReason::FinalAnswer, Some(completion.text));
+        }
+
+        let requested = completion.tool_calls.clone();
+        messages.push(ChatMessage::assistant_tool_calls(
+            completion.text,
+            completion.tool_calls,
+        ));
+
+        let results =
+            futures::future::join_all(requested.iter().map(|call| run_tool(mcp, call, steps)))
+                .await;
+        for (call, (text, record)) in requested.iter().zip(results) {
+            tracing::debug!("Agent {} called {}", agent.slug, call.name);
+            messages.push(ChatMessage::tool_result(call, text, record.is_error));
+            calls.push(record);
+        }
+    };
+
+    Ok(Research {
+        messages,
+        final_text,
+        trace: ToolTrace {
+            steps,
+            stop_reason,
+            tokens_used,
+            calls,
+        },
+    })
+}
+
+/// Run one requested call. Failures become error results the model can react to.
+async fn run_tool(mcp: &McpClient, call: &ToolCall, step: usize) -> (String, ToolCallRecord) {
+    let started = Instant::now();
+    let (text, output, is_error) = match mcp.call_tool(&call.name, call.arguments.clone()).await {
+        Ok(result) => {
+            let text = result.text();
+            let output = result
+                .structured_content
+                .unwrap_or_else(|| Value::String(text.clone()));
+            (text, output, result.is_error)
+        }
+        Err(error) => {
+            let message = error.to_string();
+            (message.clone(), Value::String(message), true)
+        }
+    };
+
+    let text = if text.chars().count() > MAX_TOOL_RESULT_CHARS {
+        let truncated: String = text.chars().take(MAX_TOOL_RESULT_CHARS).collect();
+        format!("{}\n[output truncated; request less data]", truncated)
+    } else {
+        text
+    };
+
+    let record = ToolCallRecord {
+        step,
+        tool: call.name.clone(),
+        arguments: call.arguments.clone(),
+        is_error,
+        output,
+        duration_ms: started.elapsed().as_millis(),
+    };
+    (text, record)
+}
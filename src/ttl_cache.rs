@@ -0,0 +1,54 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// An in-process cache whose entries expire `ttl` after they were inserted. It saves asking the
+/// database on every request, so its owner removes entries it knows to be outdated and
+/// anything changed behind the owner's back is picked up once the entry expires.
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (V, Instant)>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The value of `key` if it was inserted within the TTL.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|(_, inserted_at)| inserted_at.elapsed() < self.ttl)
+            .map(|(value, _)| value.clone())
+    }
+
+    /// Insert or replace the value of `key`, dropping expired entries.
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (_, inserted_at)| inserted_at.elapsed() < self.ttl);
+        entries.insert(key, (value, Instant::now()));
+    }
+
+    pub fn remove<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
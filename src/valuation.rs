@@ -0,0 +1,155 @@
+use serde::Serialize;
+
+use crate::models::{Fundamental, PriceBar};
+
+/// Discount rate used for the owner-earnings DCF.
+const DISCOUNT_RATE: f64 = 0.10;
+/// Perpetual growth rate applied after the explicit forecast period.
+const TERMINAL_GROWTH: f64 = 0.025;
+/// Number of years projected explicitly in the DCF.
+const DCF_YEARS: i32 = 10;
+/// Historical free cash flow growth is clamped to this range before projecting it forward.
+const MIN_GROWTH: f64 = -0.05;
+const MAX_GROWTH: f64 = 0.15;
+/// Trading days in a year, used for the 52-week range.
+const TRADING_DAYS_PER_YEAR: usize = 252;
+
+/// Ratios and intrinsic value estimates derived from stored prices and fundamentals.
+///
+/// Every field is optional because companies routinely lack one or more inputs
+/// (negative earnings, no dividend, missing balance sheet items).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Valuation {
+    pub price: Option<f64>,
+    pub fifty_two_week_high: Option<f64>,
+    pub fifty_two_week_low: Option<f64>,
+    pub market_cap: Option<f64>,
+    pub pe_ratio: Option<f64>,
+    pub pb_ratio: Option<f64>,
+    pub earnings_yield: Option<f64>,
+    pub dividend_yield: Option<f64>,
+    pub return_on_equity: Option<f64>,
+    pub debt_to_equity: Option<f64>,
+    pub free_cash_flow_growth: Option<f64>,
+    pub graham_number: Option<f64>,
+    pub ncav_per_share: Option<f64>,
+    pub dcf_value_per_share: Option<f64>,
+    pub margin_of_safety_graham: Option<f64>,
+    pub margin_of_safety_dcf: Option<f64>,
+}
+
+/// Compute a valuation from price bars (newest first) and annual fundamentals (newest first).
+pub fn compute(prices: &[PriceBar], annual: &[Fundamental]) -> Valuation {
+    let price = prices.first().map(|bar| bar.close);
+    let last_year = &prices[..prices.len().min(TRADING_DAYS_PER_YEAR)];
+    let fifty_two_week_high = last_year.iter().map(|bar| bar.high).reduce(f64::max);
+    let fifty_two_week_low = last_year.iter().map(|bar| bar.low).reduce(f64::min);
+
+    let latest = annual.first();
+    let field = |f: fn(&Fundamental) -> Option<f64>| latest.and_then(f);
+
+    let eps = field(|f| f.eps);
+    let book_value_per_share = field(|f| f.book_value_per_share);
+    let shares = field(|f| f.shares_outstanding).filter(|shares| *shares > 0.0);
+    let equity = match (field(|f| f.total_assets), field(|f| f.total_liabilities)) {
+        (Some(assets), Some(liabilities)) => Some(assets - liabilities),
+        _ => book_value_per_share
+            .zip(shares)
+            .map(|(bvps, shares)| bvps * shares),
+    };
+
+    let market_cap = price.zip(shares).map(|(price, shares)| price * shares);
+    let pe_ratio = ratio(price, eps.filter(|eps| *eps > 0.0));
+    let pb_ratio = ratio(price, book_value_per_share.filter(|bvps| *bvps > 0.0));
+    let earnings_yield = ratio(eps, price);
+    let dividend_yield = ratio(field(|f| f.dividends_per_share), price);
+    let return_on_equity = ratio(field(|f| f.net_income), equity.filter(|e| *e > 0.0));
+    let debt_to_equity = ratio(field(|f| f.total_debt), equity.filter(|e| *e > 0.0));
+
+    let graham_number = eps
+        .zip(book_value_per_share)
+        .filter(|(eps, bvps)| *eps > 0.0 && *bvps > 0.0)
+        .map(|(eps, bvps)| (22.5 * eps * bvps).sqrt());
+
+    let ncav_per_share = field(|f| f.current_assets)
+        .zip(field(|f| f.total_liabilities))
+        .zip(shares)
+        .map(|((current_assets, liabilities), shares)| (current_assets - liabilities) / shares);
+
+    let free_cash_flow_growth = fcf_growth(annual);
+    let dcf_value_per_share = field(|f| f.free_cash_flow)
+        .filter(|fcf| *fcf > 0.0)
+        .zip(shares)
+        .map(|(fcf, shares)| {
+            let growth = free_cash_flow_growth.unwrap_or(0.0);
+            discounted_cash_flow(fcf, growth) / shares
+        });
+
+    Valuation {
+        price,
+        fifty_two_week_high,
+        fifty_two_week_low,
+        market_cap,
+        pe_ratio,
+        pb_ratio,
+        earnings_yield,
+        dividend_yield,
+        return_on_equity,
+        debt_to_equity,
+        free_cash_flow_growth,
+        graham_number,
+        ncav_per_share,
+        dcf_value_per_share,
+        margin_of_safety_graham: margin_of_safety(graham_number, price),
+        margin_of_safety_dcf: margin_of_safety(dcf_value_per_share, price),
+    }
+}
+
+/// Fraction by which `intrinsic_value` exceeds `price`; negative when the stock trades above it.
+pub fn margin_of_safety(intrinsic_value: Option<f64>, price: Option<f64>) -> Option<f64> {
+    intrinsic_value
+        .zip(price)
+        .filter(|(value, _)| *value > 0.0)
+        .map(|(value, price)| (value - price) / value)
+}
+
+fn ratio(numerator: Option<f64>, denominator: Option<f64>) -> Option<f64> {
+    numerator
+        .zip(denominator)
+        .filter(|(_, denominator)| *denominator != 0.0)
+        .map(|(numerator, denominator)| numerator / denominator)
+}
+
+/// Compound annual growth of free cash flow between the oldest and newest positive years.
+fn fcf_growth(annual: &[Fundamental]) -> Option<f64> {
+    let positive: Vec<f64> = annual
+        .iter()
+        .filter_map(|f| f.free_cash_flow)
+        .filter(|fcf| *fcf > 0.0)
+        .collect();
+
+    if positive.len() < 2 {
+        return None;
+    }
+
+    let newest = positive[0];
+    let oldest = positive[positive.len() - 1];
+    let years = (positive.len() - 1) as f64;
+    let growth = (newest / oldest).powf(1.0 / years) - 1.0;
+
+    Some(growth.clamp(MIN_GROWTH, MAX_GROWTH))
+}
+
+/// Present value of `DCF_YEARS` of growing free cash flow plus a Gordon terminal value.
+fn discounted_cash_flow(free_cash_flow: f64, growth: f64) -> f64 {
+    let mut present_value = 0.0;
+    let mut cash_flow = free_cash_flow;
+
+    for year in 1..=DCF_YEARS {
+        cash_flow *= 1.0 + growth;
+        present_value += cash_flow / (1.0 + DISCOUNT_RATE).powi(year);
+    }
+
+    let terminal_value = cash_flow * (1.0 + TERMINAL_GROWTH) / (DISCOUNT_RATE - TERMINAL_GROWTH);
+    present_value + terminal_value / (1.0 + DISCOUNT_RATE).powi(DCF_YEARS)
+}